@@ -0,0 +1,48 @@
+use std::env::current_dir;
+use std::fs::create_dir_all;
+
+use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
+
+use basket::msg::{
+    AssetImbalancesResponse, AssetPriceResponse, AumResponse, BasketResponse, BorrowRatesResponse,
+    ConfigResponse, Cw20HookMsg, EstimateTaxResponse, ExecuteMsg, FeePreviewResponse,
+    GlobalShortsResponse, InstantiateMsg, LpPnlResponse, LpPriceResponse, LpToAssetsResponse,
+    MigrateMsg, MinRedeemableLpResponse, QueryMsg, ReservesResponse, ReverseSimulationResponse, SimulationResponse,
+    SpotPriceResponse, StatsResponse,
+};
+use basket::state::Basket;
+
+fn main() {
+    let mut out_dir = current_dir().unwrap();
+    out_dir.push("schema");
+    create_dir_all(&out_dir).unwrap();
+    remove_schemas(&out_dir).unwrap();
+
+    // Messages
+    export_schema(&schema_for!(InstantiateMsg), &out_dir);
+    export_schema(&schema_for!(ExecuteMsg), &out_dir);
+    export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(Cw20HookMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
+
+    // Query responses, `Basket` is returned by QueryMsg::RawBasket
+    export_schema(&schema_for!(Basket), &out_dir);
+    export_schema(&schema_for!(BasketResponse), &out_dir);
+    export_schema(&schema_for!(SimulationResponse), &out_dir);
+    export_schema(&schema_for!(ReverseSimulationResponse), &out_dir);
+    export_schema(&schema_for!(AssetImbalancesResponse), &out_dir);
+    export_schema(&schema_for!(AssetPriceResponse), &out_dir);
+    export_schema(&schema_for!(StatsResponse), &out_dir);
+    export_schema(&schema_for!(AumResponse), &out_dir);
+    export_schema(&schema_for!(LpPriceResponse), &out_dir);
+    export_schema(&schema_for!(ReservesResponse), &out_dir);
+    export_schema(&schema_for!(FeePreviewResponse), &out_dir);
+    export_schema(&schema_for!(GlobalShortsResponse), &out_dir);
+    export_schema(&schema_for!(ConfigResponse), &out_dir);
+    export_schema(&schema_for!(BorrowRatesResponse), &out_dir);
+    export_schema(&schema_for!(EstimateTaxResponse), &out_dir);
+    export_schema(&schema_for!(LpPnlResponse), &out_dir);
+    export_schema(&schema_for!(LpToAssetsResponse), &out_dir);
+    export_schema(&schema_for!(SpotPriceResponse), &out_dir);
+    export_schema(&schema_for!(MinRedeemableLpResponse), &out_dir);
+}
@@ -1,17 +1,17 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{fmt, convert::TryInto};
+use std::{fmt, convert::{TryFrom, TryInto}};
 
 use cosmwasm_std::{
     to_binary, Addr, Api, BankMsg, Coin, CosmosMsg, Decimal, MessageInfo, QuerierWrapper, StdError,
-    StdResult, Uint128, WasmMsg,
+    StdResult, Uint128, Uint256, WasmMsg,
 };
 use cw20::Cw20ExecuteMsg;
 use terra_cosmwasm::TerraQuerier;
 
 use crate::{
     error::ContractError,
-    state::BasketAsset,
+    state::{BasketAsset, PriceCache},
     querier::query_token_precision, 
     price::PythPrice,
 };
@@ -90,6 +90,30 @@ impl Asset {
         }
     }
 
+    /// Calculates and returns the amount to send so that `self.amount` is received once the tax
+    /// of a chain's native token is deducted. For other tokens it returns `self.amount`.
+    /// ## Params
+    /// * **self** is the type of the caller object.
+    ///
+    /// * **querier** is an object of type [`QuerierWrapper`]
+    pub fn add_tax(&self, querier: &QuerierWrapper) -> StdResult<Uint128> {
+        let amount = self.amount;
+        if let AssetInfo::NativeToken { denom } = &self.info {
+            let terra_querier = TerraQuerier::new(querier);
+            let tax_rate: Decimal = (terra_querier.query_tax_rate()?).rate;
+            let tax_cap: Uint128 = (terra_querier.query_tax_cap(denom.to_string())?).cap;
+            // Smallest gross amount whose deduction in `compute_tax` leaves `amount`
+            let rate_fraction = DECIMAL_FRACTION * tax_rate + DECIMAL_FRACTION;
+            let mut gross = amount.multiply_ratio(rate_fraction, DECIMAL_FRACTION);
+            if gross.multiply_ratio(DECIMAL_FRACTION, rate_fraction) < amount {
+                gross = gross.checked_add(Uint128::new(1))?;
+            }
+            Ok(std::cmp::min(gross, amount.checked_add(tax_cap)?))
+        } else {
+            Ok(amount)
+        }
+    }
+
     /// Returns a message of type [`CosmosMsg`].
     ///
     /// For native tokens of type [`AssetInfo`] uses the default method [`BankMsg::Send`] to send a token amount to a recipient.
@@ -102,8 +126,7 @@ impl Asset {
     /// * **querier** is an object of type [`QuerierWrapper`]
     ///
     /// * **recipient** is the address where the funds will be sent.
-    #[allow(clippy::redundant_field_names)]
-    pub fn into_msg(self, _querier: &QuerierWrapper, recipient: Addr) -> StdResult<CosmosMsg> {
+    pub fn into_msg(self, querier: &QuerierWrapper, recipient: Addr) -> StdResult<CosmosMsg> {
         let amount = self.amount;
 
         match &self.info {
@@ -115,12 +138,9 @@ impl Asset {
                 })?,
                 funds: vec![],
             })),
-            AssetInfo::NativeToken { denom } => Ok(CosmosMsg::Bank(BankMsg::Send {
+            AssetInfo::NativeToken { .. } => Ok(CosmosMsg::Bank(BankMsg::Send {
                 to_address: recipient.to_string(),
-                amount: vec![Coin {
-                    denom: denom.to_string(),
-                    amount: amount,
-                }],
+                amount: vec![self.deduct_tax(querier)?],
             })),
         }
     }
@@ -154,6 +174,68 @@ impl Asset {
     }
 }
 
+/// Validates that no native coins other than the offered ones were sent along with a message.
+/// Returns [`Ok`] if successful, otherwise returns [`Err`].
+/// ## Params
+/// * **message_info** is an object of type [`MessageInfo`]
+///
+/// * **offer_assets** is an array of [`Asset`] the caller intends to send
+pub fn assert_no_unexpected_funds(message_info: &MessageInfo, offer_assets: &[Asset]) -> StdResult<()> {
+    for coin in &message_info.funds {
+        let expected = offer_assets.iter().any(|asset| match &asset.info {
+            AssetInfo::NativeToken { denom } => *denom == coin.denom,
+            AssetInfo::Token { .. } => false,
+        });
+        if !expected {
+            return Err(StdError::generic_err(format!(
+                "Unexpected native token sent: {}",
+                coin.denom
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Validates the native coins sent along with a multi-asset message against the declared offer.
+/// Every declared native amount must be sent in full, summed per denom, and no other denoms may be
+/// attached. Returns [`Ok`] if successful, otherwise returns [`Err`].
+/// ## Params
+/// * **message_info** is an object of type [`MessageInfo`]
+///
+/// * **offer_assets** is an array of [`Asset`] the caller intends to send
+pub fn assert_sent_native_funds(message_info: &MessageInfo, offer_assets: &[Asset]) -> StdResult<()> {
+    assert_no_unexpected_funds(message_info, offer_assets)?;
+
+    let mut declared: Vec<Coin> = vec![];
+    for asset in offer_assets {
+        if let AssetInfo::NativeToken { denom } = &asset.info {
+            match declared.iter_mut().find(|coin| coin.denom == *denom) {
+                Some(coin) => coin.amount += asset.amount,
+                None => declared.push(Coin {
+                    denom: denom.clone(),
+                    amount: asset.amount,
+                }),
+            }
+        }
+    }
+
+    for coin in &declared {
+        let sent: Uint128 = message_info
+            .funds
+            .iter()
+            .filter(|sent| sent.denom == coin.denom)
+            .map(|sent| sent.amount)
+            .sum();
+        if sent != coin.amount {
+            return Err(StdError::generic_err(format!(
+                "Native token balance mismatch between the argument and the transferred: {}",
+                coin.denom
+            )));
+        }
+    }
+    Ok(())
+}
+
 /// This enum describes available Token types.
 /// ## Examples
 /// ``` ignore
@@ -318,6 +400,9 @@ impl PricedAsset {
     }
 
     pub fn query_decimals(&mut self, querier: &QuerierWrapper) -> Result<i32, ContractError> {
+        if let Some(decimals) = self.decimals.or_else(|| self.basket_asset.decimals.map(i32::from)) {
+            return Ok(decimals);
+        }
         let decimals: i32 = query_token_precision(querier, &self.asset.info)?
             .try_into()
             .expect("Unable to query for offer token decimals");
@@ -325,11 +410,31 @@ impl PricedAsset {
         Ok(decimals)
     }
 
+    /// Price of this asset in USD, see `query_price_cached` to price in a basket's quote
     pub fn query_price(&mut self, querier: &QuerierWrapper) -> Result<PythPrice, ContractError> {
+        self.query_price_cached(querier, &mut PriceCache::default())
+    }
+
+    /// Same as `query_price`, reusing the price in `price_cache` if this asset was already priced.
+    /// The price is in units of the cache's quote
+    pub fn query_price_cached(
+        &mut self,
+        querier: &QuerierWrapper,
+        price_cache: &mut PriceCache,
+    ) -> Result<PythPrice, ContractError> {
+        // Valuations that follow need the decimals too, take them from the cache as well
+        if self.decimals.is_none() {
+            self.decimals = Some(price_cache.get_decimals(querier, &self.basket_asset)?.into());
+        }
         match self.price {
             Some(price) => Ok(price),
             None => {
-                let price = PythPrice::new(self.basket_asset.oracle.get_price(querier)?);
+                let price = price_cache.get_price(querier, &self.basket_asset)?;
+                let price = PythPrice::new(price_cache.to_quote(querier, price)?);
+                // Amounts are derived by dividing by the price, a zero price would trap
+                if price.pyth_price.price == 0 {
+                    return Err(ContractError::ZeroPrice);
+                }
                 self.price = Some(price);
                 Ok(price)
             }
@@ -339,41 +444,62 @@ impl PricedAsset {
     pub fn query_contract_value(&mut self, querier: &QuerierWrapper) -> Result<Uint128, ContractError> {
         let decimals = self.query_decimals(querier)?;
         let price: PythPrice = self.query_price(querier)?;
-        let value = if price.pyth_price.expo < 0 {
-            Uint128::from(price.pyth_price.price as u128)
-            .multiply_ratio(
-                (self.basket_asset.available_reserves.u128()
-                + self.basket_asset.occupied_reserves.u128()) * 10_u128.pow(-USD_VALUE_PRECISION as u32),
-                10_u128.pow(price.pyth_price.expo.unsigned_abs() + decimals.unsigned_abs())
-            )
-        } else {
-            Uint128::from(price.pyth_price.price as u128)
-            .multiply_ratio(
-                (self.basket_asset.available_reserves.u128()
-                + self.basket_asset.occupied_reserves.u128()) * 10_u128.pow(-USD_VALUE_PRECISION as u32 + price.pyth_price.expo.unsigned_abs()),
-                10_u128.pow(decimals as u32)
-            )
-        };
-        Ok(value)
+        let reserves = self.basket_asset.available_reserves.checked_add(self.basket_asset.occupied_reserves)?;
+        usd_value(price, reserves, decimals)
     }
 
     pub fn query_value(&mut self, querier: &QuerierWrapper) -> Result<Uint128, ContractError> {
         let decimals = self.query_decimals(querier)?;
         let price: PythPrice = self.query_price(querier)?;
-        let value = if price.pyth_price.expo < 0 {
-            Uint128::from(price.pyth_price.price as u128)
-            .multiply_ratio(
-                self.asset.amount.u128() * 10_u128.pow(-USD_VALUE_PRECISION as u32),
-                10_u128.pow(price.pyth_price.expo.unsigned_abs() + decimals.unsigned_abs())
-            )
+        usd_value(price, self.asset.amount, decimals)
+    }
+
+    /// Inverse of `query_value`: the amount of this asset worth at least `value` USD, rounded up
+    pub fn query_amount_for_value(&mut self, querier: &QuerierWrapper, value: Uint128) -> Result<Uint128, ContractError> {
+        let decimals = self.query_decimals(querier)?;
+        let price: PythPrice = self.query_price(querier)?;
+        let shift = usd_value_shift(price, decimals);
+        let price = Uint128::from(price.pyth_price.price as u128);
+        if shift >= 0 {
+            let scale = 10_u128.checked_pow(shift as u32).ok_or(ContractError::FailedCast)?;
+            ceil_multiply_ratio(value, Uint128::new(1), price.checked_mul(Uint128::from(scale))?)
         } else {
-            Uint128::from(price.pyth_price.price as u128)
-            .multiply_ratio(
-                self.asset.amount.u128() * 10_u128.pow(-USD_VALUE_PRECISION as u32 + price.pyth_price.expo.unsigned_abs()),
-                10_u128.pow(decimals as u32)
-            )
-        };
-        Ok(value)
+            let scale = 10_u128.checked_pow(shift.unsigned_abs()).ok_or(ContractError::FailedCast)?;
+            ceil_multiply_ratio(value, Uint128::from(scale), price)
+        }
+    }
+}
+
+/// Power of ten turning `price * amount` into a USD value with `USD_VALUE_PRECISION` decimals, for a
+/// token with `decimals` decimals. Negative when the token and price decimals outweigh the USD
+/// precision, e.g. for most assets, and nonnegative for low decimal tokens such as 0 decimal CW20s.
+fn usd_value_shift(price: PythPrice, decimals: i32) -> i32 {
+    price.pyth_price.expo - USD_VALUE_PRECISION - decimals
+}
+
+/// USD value (with `USD_VALUE_PRECISION` decimals) of `amount` units of a token with `decimals` decimals.
+/// The intermediate products are computed in [`Uint256`] so that large reserves of high priced assets
+/// cannot overflow, and the result is narrowed back to [`Uint128`] with a checked conversion.
+fn usd_value(price: PythPrice, amount: Uint128, decimals: i32) -> Result<Uint128, ContractError> {
+    let shift = usd_value_shift(price, decimals);
+    let value = Uint256::from(price.pyth_price.price as u128).checked_mul(Uint256::from(amount))?;
+    let value = if shift >= 0 {
+        value.checked_mul(Uint256::from(10_u128).pow(shift as u32))?
+    } else {
+        value
+            .checked_div(Uint256::from(10_u128).pow(shift.unsigned_abs()))
+            .map_err(|_| ContractError::FailedCast)?
+    };
+    Uint128::try_from(value).map_err(|_| ContractError::FailedCast)
+}
+
+/// Same as [`Uint128::multiply_ratio`], but rounds the result up instead of down
+pub fn ceil_multiply_ratio(value: Uint128, numerator: Uint128, denominator: Uint128) -> Result<Uint128, ContractError> {
+    let floor = value.multiply_ratio(numerator, denominator);
+    if Uint256::from(floor) * Uint256::from(denominator) < Uint256::from(value) * Uint256::from(numerator) {
+        Ok(floor.checked_add(Uint128::new(1))?)
+    } else {
+        Ok(floor)
     }
 }
 
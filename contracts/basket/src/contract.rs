@@ -1,17 +1,22 @@
 use crate::{
-    asset::{addr_validate_to_lower, Asset, AssetInfo, PricedAsset},
+    asset::{addr_validate_to_lower, assert_no_unexpected_funds, assert_sent_native_funds, ceil_multiply_ratio, safe_u128_to_i64, Asset, AssetInfo, PricedAsset},
     error::ContractError,
     msg::*,
-    querier::query_supply,
-    state::{Basket, BasketAsset, ToAssetInfo, BASKET},
+    price::PythPrice,
+    querier::{query_supply, query_token_balance, query_token_precision},
+    state::{
+        price_deviation_exceeds, Basket, BasketAsset, DepositInfo, OracleInterface, PendingDeposit,
+        PendingSwap, PriceCache, ToAssetInfo, BASKET, DEPOSITS, LAST_LARGE_SWAP, LOCK, PENDING_DEPOSIT,
+        PENDING_SWAP,
+    },
 };
 #[allow(unused_imports)]
 use cosmwasm_std::{
-    attr, entry_point, from_binary, to_binary, Addr, Binary, CosmosMsg, Decimal, Deps,
-    DepsMut, Env, MessageInfo, Reply, ReplyOn, Response, StdError, StdResult, SubMsg, Uint128, Uint256,
-    WasmMsg,
+    attr, entry_point, from_binary, to_binary, Addr, Api, Binary, Coin, ContractResult, CosmosMsg, Decimal, Deps,
+    DepsMut, Env, MessageInfo, QuerierWrapper, Reply, ReplyOn, Response, StdError, StdResult, Storage, SubMsg, Uint128,
+    Uint256, WasmMsg,
 };
-use cw2::set_contract_version;
+use cw2::{get_contract_version, set_contract_version};
 use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse};
 use protobuf::Message;
 use pyth_sdk_terra::Price;
@@ -19,16 +24,27 @@ use pyth_sdk_terra::Price;
 /// Contract name that is used for migration.
 const CONTRACT_NAME: &str = "tsunami-basket";
 /// Contract version that is used for migration.
-const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 const INSTANTIATE_BASKET_REPLY_ID: u64 = 1;
-const BASIS_POINTS_PRECISION: Uint128 = Uint128::new(10_000);
-const BASE_FEE_IN_BASIS_POINTS: Uint128 = Uint128::new(15);
-const PENALTY_IN_BASIS_POINTS: Uint128 = Uint128::new(15);
+const UPDATE_PRICES_REPLY_ID: u64 = 2;
+const DEPOSIT_REPLY_ID: u64 = 3;
+
+// Rounding policy: every rounding step favors the basket. Amounts paid out (LP minted, swap
+// returns, redemptions) are rounded down with `multiply_ratio`, while fees and amounts the user
+// must pay in are rounded up with `ceil_multiply_ratio`. A fee taken as a share of a floored
+// post-fee amount, e.g. `value * (10_000 - bps) / 10_000`, is already rounded up.
+pub const BASIS_POINTS_PRECISION: Uint128 = Uint128::new(10_000);
 
 // Calculate USD value of asset down to this precision
 pub const USD_VALUE_PRECISION: i32 = -6;
+// Default LP token decimals, baskets may use anything from the USD precision up to cw20's max
 pub const LP_DECIMALS: u8 = 9;
+pub const MAX_LP_DECIMALS: u8 = 18;
+// Borrow rates are annual, over a 365 day year
+pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+// Exponent the offer to ask price ratio of a spot price is computed in
+const SPOT_PRICE_EXPO: i32 = -12;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -38,20 +54,46 @@ pub fn instantiate(
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     // Check assets + Ensure no repeated assets
-    check_assets(&msg.assets)?;
+    check_assets(deps.api, &msg.assets)?;
+
+    // Code id 0 is never stored, the LP instantiation would only fail later in `reply`
+    if msg.token_code_id == 0 {
+        return Err(ContractError::InvalidCodeId);
+    }
+
+    // A stub left over from testing would price the basket off a constant
+    if msg.production {
+        let is_stub = |oracle: &OracleInterface| matches!(oracle, OracleInterface::Stub { .. });
+        let asset_stub = msg.assets.iter().any(|asset| is_stub(&asset.oracle) || is_stub(&asset.backup_oracle));
+        if asset_stub || msg.quote.iter().any(|quote| is_stub(&quote.oracle)) {
+            return Err(ContractError::StubOracleInProduction);
+        }
+    }
 
     // Set contract version
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
     // SubMsg to Create the LP token contract
-    let token_name = format!("{}-LP", &msg.name);
-    let sub_msg = instantiate_lp(&msg, env, token_name)?;
+    let token_name = msg.lp_token_name.clone().unwrap_or_else(|| format!("{}-LP", &msg.name));
+    let token_symbol = msg.lp_token_symbol.clone().unwrap_or_else(|| "TLP".to_string());
+    if !is_valid_lp_symbol(&token_symbol) {
+        return Err(ContractError::InvalidLpTokenSymbol);
+    }
+
+    // Fewer LP decimals than the USD precision would truncate the first deposit's mint
+    let lp_decimals = msg.lp_decimals.unwrap_or(LP_DECIMALS);
+    if (lp_decimals as i32) < -USD_VALUE_PRECISION || lp_decimals > MAX_LP_DECIMALS {
+        return Err(ContractError::InvalidLpDecimals);
+    }
+    let now = env.block.time.seconds();
+    let sub_msg = instantiate_lp(&msg, env, token_name, token_symbol)?;
 
     // Build BasketAssets from message
-    let assets: Vec<BasketAsset> = build_assets(&msg);
+    let assets: Vec<BasketAsset> = build_assets(&deps.querier, &msg)?;
 
-    // Build Basket from Assets and parameters in message
-    let basket = Basket::new(assets, &msg);
+    // Build Basket from Assets and parameters in message, borrow fees accrue from now on
+    let mut basket = Basket::new(assets, &msg);
+    basket.last_borrow_accrual_time = now;
 
     // Store Basket in Item/Singleton
     BASKET.save(deps.storage, &basket)?;
@@ -67,12 +109,62 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
+    // Only admin messages go through while the basket is paused
+    let is_user_action = matches!(
+        msg,
+        ExecuteMsg::DepositLiquidity { .. }
+            | ExecuteMsg::DepositToUnderweight { .. }
+            | ExecuteMsg::Receive(_)
+            | ExecuteMsg::Swap { .. }
+            | ExecuteMsg::UpdatePricesThenSwap { .. }
+            | ExecuteMsg::SwapExactOut { .. }
+            | ExecuteMsg::SwapRoute { .. }
+    );
+    if is_user_action && BASKET.load(deps.storage)?.paused {
+        return Err(ContractError::Paused);
+    }
+    // A frozen AUM would mint or swap against live prices at a stale rate
+    let is_priced_action = matches!(
+        msg,
+        ExecuteMsg::DepositLiquidity { .. }
+            | ExecuteMsg::DepositToUnderweight { .. }
+            | ExecuteMsg::Swap { .. }
+            | ExecuteMsg::UpdatePricesThenSwap { .. }
+            | ExecuteMsg::SwapExactOut { .. }
+            | ExecuteMsg::SwapRoute { .. }
+    );
+    if is_priced_action {
+        BASKET.load(deps.storage)?.assert_aum_live()?;
+    }
+    if is_user_action && LOCK.may_load(deps.storage)?.unwrap_or(false) {
+        return Err(ContractError::Reentrancy);
+    }
+
     match msg {
         ExecuteMsg::DepositLiquidity {
             assets,
             slippage_tolerance,
+            min_lp_out,
             receiver,
-        } => provide_liquidity(deps, env, info, assets, slippage_tolerance, receiver),
+            auto_stake,
+            deadline,
+        } => {
+            assert_deadline(&env, deadline)?;
+            provide_liquidity(
+                deps,
+                env,
+                info,
+                assets,
+                slippage_tolerance,
+                min_lp_out,
+                receiver,
+                auto_stake,
+            )
+        }
+        ExecuteMsg::DepositToUnderweight {
+            value_usd,
+            slippage_tolerance,
+        } => deposit_to_underweight(deps, env, info, value_usd, slippage_tolerance),
         ExecuteMsg::Receive( msg ) => receive_cw20(deps, env, info, msg),
         ExecuteMsg::Swap {
             sender,
@@ -81,26 +173,84 @@ pub fn execute(
             max_spread,
             to,
             ask_asset,
-        } => swap(
-            deps,
-            env,
-            info,
-            sender,
-            offer_asset,
-            belief_price,
-            max_spread,
-            to,
+            deadline,
+        } => {
+            assert_deadline(&env, deadline)?;
+            swap(
+                deps,
+                env,
+                info,
+                sender,
+                offer_asset,
+                belief_price,
+                max_spread,
+                to,
+                ask_asset,
+            )
+        }
+        ExecuteMsg::UpdatePricesThenSwap {
+            price_update_data,
+            swap,
+        } => {
+            assert_deadline(&env, swap.deadline)?;
+            update_prices_then_swap(deps, info, price_update_data, *swap)
+        }
+        ExecuteMsg::SwapExactOut {
+            offer_asset_info,
             ask_asset,
-        ),
+            max_offer_amount,
+            to,
+        } => swap_exact_out(deps, env, info, offer_asset_info, ask_asset, max_offer_amount, to),
+        ExecuteMsg::SwapRoute {
+            offer_asset,
+            route,
+            minimum_receive,
+        } => swap_route(deps, env, info, offer_asset, route, minimum_receive),
+        ExecuteMsg::SetAssetSwapEnabled { asset, enabled } => {
+            set_asset_swap_enabled(deps, info, asset, enabled)
+        }
+        ExecuteMsg::SetPaused { paused } => set_paused(deps, info, paused),
+        ExecuteMsg::UpdateAssetWeights { weights } => update_asset_weights(deps, info, weights),
+        ExecuteMsg::AddAsset { asset } => add_asset(deps, info, asset),
+        ExecuteMsg::RemoveAsset { asset } => remove_asset(deps, info, asset),
+        ExecuteMsg::UpdateOracle {
+            asset,
+            oracle,
+            backup_oracle,
+        } => update_oracle(deps, info, asset, oracle, backup_oracle),
+        ExecuteMsg::SetManualPrice { asset, price, expo } => {
+            set_manual_price(deps, info, asset, price, expo)
+        }
+        ExecuteMsg::SetGenerator { generator_address } => {
+            set_generator(deps, info, generator_address)
+        }
+        ExecuteMsg::SetLpSupplyCap { max_lp_supply } => {
+            set_lp_supply_cap(deps, info, max_lp_supply)
+        }
+        ExecuteMsg::SetSwapCooldown { large_swap_usd, cooldown_seconds } => {
+            set_swap_cooldown(deps, info, large_swap_usd, cooldown_seconds)
+        }
+        ExecuteMsg::AccrueInterest {} => accrue_interest(deps, env),
+        ExecuteMsg::FreezeAum { value } => set_frozen_aum(deps, info, Some(value)),
+        ExecuteMsg::UnfreezeAum {} => set_frozen_aum(deps, info, None),
+        ExecuteMsg::SetMaxPriceAge { max_price_age } => set_max_price_age(deps, info, max_price_age),
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    if msg.id == UPDATE_PRICES_REPLY_ID {
+        return swap_after_price_update(deps, env, msg);
+    }
+    if msg.id == DEPOSIT_REPLY_ID {
+        return deposit_after_transfer(deps, env);
+    }
+
     let mut basket: Basket = BASKET.load(deps.storage)?;
 
-    if basket.lp_token_address != Addr::unchecked("") {
-        return Err(ContractError::Unauthorized);
+    // The LP token is only instantiated once, a second reply must not replace it
+    if basket.lp_token_initialized {
+        return Err(ContractError::AlreadyInitialized);
     }
 
     let data = msg.result.unwrap().data.expect("Could not retrieve Reply msg.data when replying");
@@ -110,185 +260,1762 @@ pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractE
         })?;
 
     basket.lp_token_address = addr_validate_to_lower(deps.api, res.get_contract_address())?;
+    basket.lp_token_initialized = true;
 
     BASKET.save(deps.storage, &basket)?;
 
     Ok(Response::new().add_attribute("liquidity_token_addr", basket.lp_token_address))
 }
 
-pub fn withdraw_liquidity(
-    deps: DepsMut,
-    _env: Env,
-    info: MessageInfo,
-    sender: Addr,
-    amount: Uint128,
-    ask_asset: AssetInfo,
-) -> Result<Response, ContractError> {
-    // Load Basket
-    let basket: Basket = BASKET.load(deps.storage)?;
-
-    // Abort if not from basket lp token contract
-    if info.sender != basket.lp_token_address {
-        return Err(ContractError::Unauthorized);
+/// ## Description
+/// Migrates the basket to this contract version. Fields missing from a basket stored by an earlier
+/// version load with their serde defaults, those that cannot default are filled in here: the
+/// decimals of assets added before they were kept on [`BasketAsset`], the total weight, and the
+/// time borrow fees accrue from.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **_msg** is an object of type [`MigrateMsg`].
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+    for asset in basket.assets.iter_mut().filter(|asset| asset.decimals.is_none()) {
+        asset.decimals = Some(query_token_precision(&deps.querier, &asset.info)?);
     }
+    basket.update_total_weights();
+    // Borrow fees start accruing at the migration rather than at time zero
+    if basket.last_borrow_accrual_time == 0 {
+        basket.last_borrow_accrual_time = env.block.time.seconds();
+    }
+    BASKET.save(deps.storage, &basket)?;
 
-    // Retrieve ask asset
-    let ask_asset = basket.assets
-        .iter()
-        .find(|asset| asset.info == ask_asset)
-        .ok_or(ContractError::AssetNotInBasket)?;
-
-    let mut ask_asset = PricedAsset::new(Asset{info: ask_asset.info.clone(), amount: Uint128::zero()}, ask_asset.clone());
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new().add_attribute("action", "migrate"))
+}
 
-    // Calculate gross asset return value
-    let mut redemption_value: Uint128 =
-        basket.withdraw_amount(amount, &deps.querier)?;
+/// Holds off user actions until the reply of the submessage being dispatched, see [`LOCK`]
+fn lock(storage: &mut dyn Storage) -> Result<(), ContractError> {
+    if LOCK.may_load(storage)?.unwrap_or(false) {
+        return Err(ContractError::Reentrancy);
+    }
+    Ok(LOCK.save(storage, &true)?)
+}
 
-    // Calculate fee_bps
-    let initial_aum_value: Uint128 = basket.calculate_aum(&deps.querier)?.to_Uint128(USD_VALUE_PRECISION)?;
-    let fee_bps: Uint128 = calculate_fee_basis_points(
-        initial_aum_value,
-        &basket,
-        &[ask_asset.query_contract_value(&deps.querier)?],
-        &vec![redemption_value],
-        &vec![ask_asset.basket_asset.clone()],
-        Action::Ask,
-    )[0];
+fn unlock(storage: &mut dyn Storage) -> Result<(), ContractError> {
+    Ok(LOCK.save(storage, &false)?)
+}
 
-    // Update refund_asset with fee
-    redemption_value =
-        redemption_value.multiply_ratio(BASIS_POINTS_PRECISION - fee_bps, BASIS_POINTS_PRECISION);
+/// ## Description
+/// Runs the swap stored by [`update_prices_then_swap`] once its price update went through. A
+/// failed update fails the whole message rather than swapping on the prices it meant to replace.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **msg** is an object of type [`Reply`]. This is the result of the price update.
+fn swap_after_price_update(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    let pending: PendingSwap = PENDING_SWAP.load(deps.storage)?;
+    PENDING_SWAP.remove(deps.storage);
+    unlock(deps.storage)?;
+
+    if let ContractResult::Err(reason) = msg.result {
+        return Err(ContractError::PriceUpdateFailed { reason });
+    }
 
-    let decimals = ask_asset.query_decimals(&deps.querier)?;
-    let redemption_amount = redemption_value.multiply_ratio(Uint128::from(10_u64).pow(decimals as u32), ask_asset.query_price(&deps.querier)?.to_Uint128(-decimals)?);
-    let redemption_asset = Asset {
-        amount: redemption_amount,
-        info: ask_asset.asset.info,
+    let info = MessageInfo {
+        sender: pending.sender,
+        funds: pending.funds,
     };
+    let SwapParams {
+        sender,
+        offer_asset,
+        belief_price,
+        max_spread,
+        to,
+        ask_asset,
+        ..
+    } = pending.swap;
+    swap(deps, env, info, sender, offer_asset, belief_price, max_spread, to, ask_asset)
+}
 
-    // Update the asset info
-    let messages: Vec<CosmosMsg> = vec![
-        redemption_asset
-            .clone()
-            .into_msg(&deps.querier, sender.clone())?,
-        CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: basket.lp_token_address.to_string(),
-            msg: to_binary(&Cw20ExecuteMsg::Burn { amount })?,
-            funds: vec![],
-        }),
-    ];
-
-    let attributes = vec![
-        attr("action", "withdraw_liquidity"),
-        attr("sender", sender.as_str()),
-        attr("redemption_asset", format!("{}", redemption_asset)),
-        attr("fee_bps", &fee_bps.to_string()),
-    ];
+/// ## Description
+/// Posts `price_update_data` to the basket's pyth contract and swaps once the update succeeded,
+/// so that pull-based oracles can be refreshed and used in a single transaction. The swap is held
+/// in [`PENDING_SWAP`] until the update replies.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`]. Its funds are forwarded to the swap.
+///
+/// * **price_update_data** is a [`Vec`] of [`Binary`] price update VAAs.
+///
+/// * **swap** is an object of type [`SwapParams`].
+pub fn update_prices_then_swap(
+    deps: DepsMut,
+    info: MessageInfo,
+    price_update_data: Vec<Binary>,
+    swap: SwapParams,
+) -> Result<Response, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let pyth_contract = basket.pyth_contract_address.ok_or(ContractError::PythNotConfigured)?;
+
+    PENDING_SWAP.save(
+        deps.storage,
+        &PendingSwap {
+            sender: info.sender,
+            funds: info.funds,
+            swap,
+        },
+    )?;
+    lock(deps.storage)?;
 
     Ok(Response::new()
-        .add_messages(messages)
-        .add_attributes(attributes))
+        .add_submessage(SubMsg {
+            msg: WasmMsg::Execute {
+                contract_addr: pyth_contract.to_string(),
+                msg: to_binary(&PythExecuteMsg::UpdatePriceFeeds {
+                    data: price_update_data,
+                })?,
+                funds: vec![],
+            }
+            .into(),
+            id: UPDATE_PRICES_REPLY_ID,
+            gas_limit: None,
+            reply_on: ReplyOn::Always,
+        })
+        .add_attribute("action", "update_prices"))
 }
 
-/// Produces unit price of USD, in units of `USD_VALUE_PRECISION`
-pub fn get_unit_price() -> Price {
-    Price {
-        price: 1, //10_i64.pow(-USD_VALUE_PRECISION as u32),
-        expo: USD_VALUE_PRECISION,
-        conf: 0,
+/// ## Description
+/// Enables or disables swaps into and out of a single basket asset. Deposits and withdrawals
+/// are unaffected. Only the basket admin may call this.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **asset** is an object of type [`AssetInfo`]. This is the basket asset being toggled.
+///
+/// * **enabled** is an object of type [`bool`].
+pub fn set_asset_swap_enabled(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset: AssetInfo,
+    enabled: bool,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
     }
-}
 
-fn instantiate_lp(
-    msg: &InstantiateMsg,
-    env: Env,
-    token_name: String,
-) -> Result<Vec<SubMsg>, ContractError> {
-    Ok(vec![SubMsg {
-        msg: WasmMsg::Instantiate {
-            code_id: msg.token_code_id,
-            msg: to_binary(&InstantiateLpMsg {
-                name: token_name,
-                symbol: "TLP".to_string(),
-                decimals: LP_DECIMALS,
-                initial_balances: vec![],
-                mint: Some(MinterResponse {
-                    minter: env.contract.address.to_string(),
-                    cap: None,
-                }),
-            })
-            .expect("failed to convert InstantiateLpMsg to binary."),
-            funds: vec![],
-            admin: None,
-            label: "Tsunami LP Token".to_string(),
-        }
-        .into(),
-        id: INSTANTIATE_BASKET_REPLY_ID,
-        gas_limit: None,
-        reply_on: ReplyOn::Success,
-    }])
-}
+    let basket_asset = basket
+        .assets
+        .iter_mut()
+        .find(|basket_asset| basket_asset.info.equal(&asset))
+        .ok_or(ContractError::AssetNotInBasket)?;
+    basket_asset.swap_enabled = enabled;
 
-fn build_assets(msg: &InstantiateMsg) -> Vec<BasketAsset> {
-    let mut assets = Vec::new();
-    for asset in msg.assets.clone() {
-        assets.push(BasketAsset::new(asset));
-    }
-    assets
-}
+    BASKET.save(deps.storage, &basket)?;
 
-fn check_assets(assets: &Vec<InstantiateAssetInfo>) -> Result<u64, ContractError> {
-    let mut asset_names: Vec<String> = Vec::new();
-    for asset in assets {
-        if asset_names.contains(&asset.address.to_string()) {
-            return Err(ContractError::DuplicateAssetAssertion);
-        }
-        asset_names.push(asset.address.to_string());
-    }
-    Ok(1)
+    Ok(Response::new()
+        .add_attribute("action", "set_asset_swap_enabled")
+        .add_attribute("asset", asset.to_string())
+        .add_attribute("enabled", enabled.to_string()))
 }
 
 /// ## Description
-/// Exposes all the queries available in the contract.
+/// Pauses or unpauses deposits, withdrawals and swaps for the whole basket. Only the basket
+/// admin may call this.
 /// ## Params
-/// * **deps** is an object of type [`Deps`].
-///
-/// * **_env** is an object of type [`Env`].
+/// * **deps** is an object of type [`DepsMut`].
 ///
-/// * **msg** is an object of type [`QueryMsg`].
+/// * **info** is an object of type [`MessageInfo`].
 ///
-/// ## Queries
-/// * **QueryMsg::Basket {}** Returns information about the basket in an object of type [`Basket`].
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Basket {} => to_binary(&query_basket(deps)?),
+/// * **paused** is an object of type [`bool`].
+pub fn set_paused(deps: DepsMut, info: MessageInfo, paused: bool) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
     }
+
+    basket.paused = paused;
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_paused")
+        .add_attribute("paused", paused.to_string()))
 }
 
 /// ## Description
-/// Returns information about the basket contract in an object of type [`BASKET`].
+/// Sets new target weights for basket assets. Assets not listed keep their current weight.
+/// A zero weight is only accepted for an asset without reserves, otherwise returns
+/// [`ContractError::ZeroWeightWithReserves`]. Only the basket admin may call this.
 /// ## Params
-/// * **deps** is an object of type [`Deps`].
-pub fn query_basket(deps: Deps) -> StdResult<Basket> {
-    BASKET.load(deps.storage)
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **weights** is an array of [`AssetInfo`] and [`Uint128`] pairs. Every asset must be in the basket.
+pub fn update_asset_weights(
+    deps: DepsMut,
+    info: MessageInfo,
+    weights: Vec<(AssetInfo, Uint128)>,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    for (asset, weight) in &weights {
+        let basket_asset = basket
+            .assets
+            .iter_mut()
+            .find(|basket_asset| basket_asset.info.equal(asset))
+            .ok_or(ContractError::AssetNotInBasket)?;
+        // A zero target would make every fee on the asset's reserves degenerate
+        if weight.is_zero()
+            && !(basket_asset.available_reserves.is_zero() && basket_asset.occupied_reserves.is_zero())
+        {
+            return Err(ContractError::ZeroWeightWithReserves { asset: basket_asset.to_string() });
+        }
+        basket_asset.token_weight = *weight;
+    }
+    basket.update_total_weights();
+
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_asset_weights")
+        .add_attribute(
+            "weights",
+            weights
+                .iter()
+                .map(|(asset, weight)| format!("{}:{}", asset, weight))
+                .collect::<Vec<String>>()
+                .join(","),
+        ))
 }
 
 /// ## Description
-/// Receives a message of type [`Cw20ReceiveMsg`] and processes it depending on the received template.
-/// If the template is not found in the received message, then an [`ContractError`] is returned,
-/// otherwise it returns the [`Response`] with the specified attributes if the operation was successful.
+/// Whitelists a new asset in a live basket. The asset starts with empty reserves, so its first
+/// deposit is fee free like any first deposit. Only the basket admin may call this.
 /// ## Params
 /// * **deps** is an object of type [`DepsMut`].
 ///
-/// * **env** is an object of type [`Env`].
-///
 /// * **info** is an object of type [`MessageInfo`].
 ///
-/// * **cw20_msg** is an object of type [`Cw20ReceiveMsg`]. This is the CW20 message that has to be processed.
-pub fn receive_cw20(
+/// * **asset** is an object of type [`InstantiateAssetInfo`].
+pub fn add_asset(
     deps: DepsMut,
-    env: Env,
+    info: MessageInfo,
+    asset: InstantiateAssetInfo,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    asset.info.check(deps.api)?;
+    asset.oracle.check(deps.api)?;
+    asset.backup_oracle.check(deps.api)?;
+    if basket.assets.iter().any(|basket_asset| basket_asset.info.equal(&asset.info)) {
+        return Err(ContractError::DuplicateAssetAssertion);
+    }
+
+    let asset_info = asset.info.to_string();
+    let mut basket_asset = BasketAsset::new(asset);
+    basket_asset.decimals = Some(query_token_precision(&deps.querier, &basket_asset.info)?);
+    basket.assets.push(basket_asset);
+    basket.update_total_weights();
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "add_asset")
+        .add_attribute("asset", asset_info))
+}
+
+/// ## Description
+/// Retires an asset from the basket. The asset must hold no available, occupied or fee
+/// reserves, otherwise returns [`ContractError::AssetNotEmpty`]. Only the basket admin may
+/// call this.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **asset** is an object of type [`AssetInfo`].
+pub fn remove_asset(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset: AssetInfo,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    let index = basket
+        .assets
+        .iter()
+        .position(|basket_asset| basket_asset.info.equal(&asset))
+        .ok_or(ContractError::AssetNotInBasket)?;
+
+    let basket_asset = &basket.assets[index];
+    if !basket_asset.available_reserves.is_zero()
+        || !basket_asset.occupied_reserves.is_zero()
+        || !basket_asset.fee_reserves.is_zero()
+    {
+        return Err(ContractError::AssetNotEmpty { asset: basket_asset.to_string() });
+    }
+
+    basket.assets.remove(index);
+    basket.update_total_weights();
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "remove_asset")
+        .add_attribute("asset", asset.to_string()))
+}
+
+/// ## Description
+/// Repoints the price oracle of a basket asset, e.g. after a Pyth price id changes. The backup
+/// oracle is kept unless a new one is given. Only the basket admin may call this.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **asset** is an object of type [`AssetInfo`].
+///
+/// * **oracle** is an object of type [`OracleInterface`].
+///
+/// * **backup_oracle** is an [`Option`] field of type [`OracleInterface`].
+pub fn update_oracle(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset: AssetInfo,
+    oracle: OracleInterface,
+    backup_oracle: Option<OracleInterface>,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    for new_oracle in std::iter::once(&oracle).chain(backup_oracle.iter()) {
+        new_oracle.check(deps.api)?;
+    }
+
+    let basket_asset = basket
+        .assets
+        .iter_mut()
+        .find(|basket_asset| basket_asset.info.equal(&asset))
+        .ok_or(ContractError::AssetNotInBasket)?;
+    basket_asset.oracle = oracle;
+    if let Some(backup_oracle) = backup_oracle {
+        basket_asset.backup_oracle = backup_oracle;
+    }
+
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_oracle")
+        .add_attribute("asset", asset.to_string()))
+}
+
+/// ## Description
+/// Prices a basket asset with an admin set [`OracleInterface::Manual`] price, replacing its current
+/// oracle. Meant as an emergency override while the asset's feed is broken; `UpdateOracle` restores
+/// a live feed. Only the basket admin may call this.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **asset** is an object of type [`AssetInfo`].
+///
+/// * **price** is the new price of the asset, of type [`i64`].
+///
+/// * **expo** is the exponent of the price, of type [`i32`].
+pub fn set_manual_price(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset: AssetInfo,
+    price: i64,
+    expo: i32,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    if price < 0 {
+        return Err(ContractError::NegativePrice);
+    }
+
+    let basket_asset = basket
+        .assets
+        .iter_mut()
+        .find(|basket_asset| basket_asset.info.equal(&asset))
+        .ok_or(ContractError::AssetNotInBasket)?;
+    basket_asset.oracle = OracleInterface::Manual {
+        price,
+        expo,
+        set_by: info.sender,
+    };
+
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_manual_price")
+        .add_attribute("asset", asset.to_string())
+        .add_attribute("price", price.to_string())
+        .add_attribute("expo", expo.to_string()))
+}
+
+/// ## Description
+/// Sets or clears the generator contract that LP tokens are staked in for deposits with
+/// `auto_stake`. Only the basket admin may call this.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **generator_address** is an [`Option`] field of type [`String`].
+pub fn set_generator(
+    deps: DepsMut,
+    info: MessageInfo,
+    generator_address: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    basket.generator_address = generator_address
+        .map(|addr| addr_validate_to_lower(deps.api, &addr))
+        .transpose()?;
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_generator")
+        .add_attribute(
+            "generator_address",
+            basket.generator_address.map(|addr| addr.to_string()).unwrap_or_default(),
+        ))
+}
+
+/// ## Description
+/// Sets or clears the most LP tokens that may be outstanding. Only the basket admin may call
+/// this. Lowering the cap below the current supply only blocks further deposits.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **max_lp_supply** is an [`Option`] field of type [`Uint128`].
+pub fn set_lp_supply_cap(
+    deps: DepsMut,
+    info: MessageInfo,
+    max_lp_supply: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    basket.max_lp_supply = max_lp_supply;
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_lp_supply_cap")
+        .add_attribute(
+            "max_lp_supply",
+            max_lp_supply.map(|cap| cap.to_string()).unwrap_or_default(),
+        ))
+}
+
+/// ## Description
+/// Sets or clears the most seconds a Pyth price may be published before the block it is used in.
+/// Only the basket admin may call this.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **max_price_age** is an [`Option`] field of type [`u64`].
+pub fn set_max_price_age(
+    deps: DepsMut,
+    info: MessageInfo,
+    max_price_age: Option<u64>,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    basket.max_price_age = max_price_age;
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_max_price_age")
+        .add_attribute(
+            "max_price_age",
+            max_price_age.map(|age| age.to_string()).unwrap_or_default(),
+        ))
+}
+
+/// ## Description
+/// Sets or clears the USD size from which swaps are large, and how long an address must wait
+/// between large swaps. Only the basket admin may call this.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **large_swap_usd** is an [`Option`] field of type [`Uint128`].
+///
+/// * **cooldown_seconds** is a field of type [`u64`].
+pub fn set_swap_cooldown(
+    deps: DepsMut,
+    info: MessageInfo,
+    large_swap_usd: Option<Uint128>,
+    cooldown_seconds: u64,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    basket.large_swap_threshold_usd = large_swap_usd;
+    basket.swap_cooldown_seconds = cooldown_seconds;
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_swap_cooldown")
+        .add_attribute(
+            "large_swap_usd",
+            large_swap_usd.map(|threshold| threshold.to_string()).unwrap_or_default(),
+        )
+        .add_attribute("cooldown_seconds", cooldown_seconds.to_string()))
+}
+
+/// ## Description
+/// Rejects a large swap by `trader` made within the basket's cooldown of their previous one, and
+/// records the time of any large swap let through. Swaps below the threshold are not tracked.
+/// ## Params
+/// * **storage** is an object of type [`Storage`].
+///
+/// * **basket** is an object of type [`Basket`].
+///
+/// * **trader** is an object of type [`Addr`]. The address the cooldown applies to.
+///
+/// * **offer_value** is an object of type [`Uint128`]. USD value of the swap's offer.
+///
+/// * **now** is a field of type [`u64`]. Block time, in seconds.
+fn check_swap_cooldown(
+    storage: &mut dyn Storage,
+    basket: &Basket,
+    trader: &Addr,
+    offer_value: Uint128,
+    now: u64,
+) -> Result<(), ContractError> {
+    match basket.large_swap_threshold_usd {
+        Some(threshold) if offer_value >= threshold => {}
+        _ => return Ok(()),
+    }
+
+    if let Some(last_swap) = LAST_LARGE_SWAP.may_load(storage, trader)? {
+        let ready_at = last_swap.saturating_add(basket.swap_cooldown_seconds);
+        if now < ready_at {
+            return Err(ContractError::SwapCooldown {
+                cooldown_seconds: basket.swap_cooldown_seconds,
+                remaining_seconds: ready_at - now,
+            });
+        }
+    }
+    LAST_LARGE_SWAP.save(storage, trader, &now)?;
+    Ok(())
+}
+
+/// ## Description
+/// Freezes the AUM at `frozen_aum`, or unfreezes it when `None`. Only the basket admin may call
+/// this. Meant for oracle outages: while frozen, single asset withdrawals redeem LP at the frozen
+/// AUM and deposits and swaps are held.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **frozen_aum** is an [`Option`] field of type [`Uint128`].
+pub fn set_frozen_aum(
+    deps: DepsMut,
+    info: MessageInfo,
+    frozen_aum: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    if info.sender != basket.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    basket.frozen_aum = frozen_aum;
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", if frozen_aum.is_some() { "freeze_aum" } else { "unfreeze_aum" })
+        .add_attribute("aum_frozen", frozen_aum.is_some().to_string())
+        .add_attribute("frozen_aum", frozen_aum.map(|value| value.to_string()).unwrap_or_default()))
+}
+
+/// ## Description
+/// Accrues borrow fees on every asset's occupied reserves into its fee reserves, for the time
+/// elapsed since the last accrual. Anyone may call this.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **env** is an object of type [`Env`].
+pub fn accrue_interest(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+    let elapsed = accrue_borrow_fees(&mut basket, env.block.time.seconds())?;
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "accrue_interest")
+        .add_attribute("elapsed_seconds", elapsed.to_string()))
+}
+
+/// Adds the borrow fees owed on occupied reserves since the last accrual to every asset's fee
+/// reserves and moves the accrual time to `now`. Returns the seconds accrued for
+pub fn accrue_borrow_fees(basket: &mut Basket, now: u64) -> Result<u64, ContractError> {
+    let elapsed = now.saturating_sub(basket.last_borrow_accrual_time);
+    let rates: Vec<Uint128> = basket.assets.iter().map(|asset| borrow_rate_bps(basket, asset)).collect();
+    for (asset, rate) in basket.assets.iter_mut().zip(rates) {
+        // Fees are rounded up, in the basket's favor
+        let fee = ceil_multiply_ratio(
+            asset.occupied_reserves,
+            rate.checked_mul(Uint128::from(elapsed))?,
+            BASIS_POINTS_PRECISION.checked_mul(Uint128::from(SECONDS_PER_YEAR))?,
+        )?;
+        asset.fee_reserves = asset.fee_reserves.checked_add(fee)?;
+    }
+    basket.last_borrow_accrual_time = basket.last_borrow_accrual_time.max(now);
+    Ok(elapsed)
+}
+
+/// Adds a deposit of `usd_value` that minted `lp_amount` to `account`'s cost basis
+fn record_deposit(
+    storage: &mut dyn Storage,
+    account: &Addr,
+    usd_value: Uint128,
+    lp_amount: Uint128,
+) -> Result<(), ContractError> {
+    let mut deposit = DEPOSITS.may_load(storage, account)?.unwrap_or(DepositInfo {
+        usd_deposited: Uint128::zero(),
+        lp_amount: Uint128::zero(),
+    });
+    deposit.usd_deposited = deposit.usd_deposited.checked_add(usd_value)?;
+    deposit.lp_amount = deposit.lp_amount.checked_add(lp_amount)?;
+    Ok(DEPOSITS.save(storage, account, &deposit)?)
+}
+
+/// Removes the cost basis of `lp_amount` withdrawn LP from `account`'s, in proportion to the LP
+/// it covers. Withdrawing more than the deposited LP, e.g. LP received by transfer, clears it
+fn release_deposit(storage: &mut dyn Storage, account: &Addr, lp_amount: Uint128) -> Result<(), ContractError> {
+    let mut deposit = match DEPOSITS.may_load(storage, account)? {
+        Some(deposit) => deposit,
+        None => return Ok(()),
+    };
+    if lp_amount >= deposit.lp_amount {
+        DEPOSITS.remove(storage, account);
+        return Ok(());
+    }
+
+    // Rounded up, so that the cost basis left never exceeds the LP left's share of it
+    let released = ceil_multiply_ratio(deposit.usd_deposited, lp_amount, deposit.lp_amount)?;
+    deposit.usd_deposited = deposit.usd_deposited.saturating_sub(released);
+    deposit.lp_amount -= lp_amount;
+    Ok(DEPOSITS.save(storage, account, &deposit)?)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw_liquidity(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    sender: Addr,
+    amount: Uint128,
+    ask_asset: AssetInfo,
+    min_out: Option<Uint128>,
+    receiver: Option<Addr>,
+) -> Result<Response, ContractError> {
+    // Load Basket
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    // Abort if not from basket lp token contract
+    if info.sender != basket.lp_token_address {
+        return Err(ContractError::Unauthorized);
+    }
+
+    release_deposit(deps.storage, &sender, amount)?;
+
+    // Retrieve ask asset
+    let ask_asset = basket.assets
+        .iter()
+        .find(|asset| asset.info == ask_asset)
+        .ok_or(ContractError::AssetNotInBasket)?;
+
+    let mut ask_asset = PricedAsset::new(Asset{info: ask_asset.info.clone(), amount: Uint128::zero()}, ask_asset.clone());
+
+    // Calculate gross asset return value, at the frozen AUM if the admin has set one
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let mut redemption_value: Uint128 =
+        basket.withdraw_amount(amount, &deps.querier, &mut price_cache)?;
+
+    // Calculate fee_bps
+    let initial_aum_value: Uint128 =
+        basket.calculate_aum(&deps.querier, &mut price_cache)?.to_Uint128(USD_VALUE_PRECISION)?;
+    ask_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    let reserve_value = ask_asset.query_contract_value(&deps.querier)?;
+    if redemption_value > reserve_value {
+        return Err(ContractError::InsufficientReserves);
+    }
+    let fee_bps: Uint128 = calculate_fee_basis_points(
+        initial_aum_value,
+        &basket,
+        basket.mint_burn_basis_points,
+        &[reserve_value],
+        &vec![redemption_value],
+        &vec![ask_asset.basket_asset.clone()],
+        Action::Ask,
+    )?[0];
+
+    // Update refund_asset with fee
+    redemption_value =
+        redemption_value.multiply_ratio(BASIS_POINTS_PRECISION - fee_bps, BASIS_POINTS_PRECISION);
+
+    let decimals = ask_asset.query_decimals(&deps.querier)?;
+    let redemption_amount = redemption_value.multiply_ratio(Uint128::from(10_u64).pow(decimals as u32), ask_asset.query_price(&deps.querier)?.to_Uint128(-decimals)?);
+
+    // Burning LP for nothing is never intended, see `QueryMsg::MinRedeemableLp`
+    if redemption_amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount);
+    }
+
+    // Abort if the price moved against the user beyond what they accept
+    if let Some(min_out) = min_out {
+        if redemption_amount < min_out {
+            return Err(ContractError::MaxSlippageAssertion);
+        }
+    }
+    let redemption_asset = Asset {
+        amount: redemption_amount,
+        info: ask_asset.asset.info,
+    };
+
+    // Redemptions are paid from available reserves only, fee_reserves are held in the same balance
+    // but are not owed to LPs
+    if let Some(basket_asset) = basket
+        .assets
+        .iter_mut()
+        .find(|basket_asset| basket_asset.info.equal(&redemption_asset.info))
+    {
+        basket_asset.available_reserves = basket_asset
+            .available_reserves
+            .checked_sub(redemption_asset.amount)
+            .map_err(|_| ContractError::InsufficientReserves)?;
+    }
+    BASKET.save(deps.storage, &basket)?;
+
+    // Native redemptions are sent net of Terra tax, see `Asset::into_msg`
+    let tax_amount = redemption_asset.compute_tax(&deps.querier)?;
+
+    // Send the redemption to the receiver (if set), but always burn the sender's LP
+    let receiver = receiver.unwrap_or_else(|| sender.clone());
+    let messages: Vec<CosmosMsg> = vec![
+        redemption_asset
+            .clone()
+            .into_msg(&deps.querier, receiver.clone())?,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: basket.lp_token_address.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Burn { amount })?,
+            funds: vec![],
+        }),
+    ];
+
+    let attributes = vec![
+        attr("action", "withdraw_liquidity"),
+        attr("sender", sender.as_str()),
+        attr("redemption_asset", format!("{}", redemption_asset)),
+        attr("fee_bps", &fee_bps.to_string()),
+        attr("usd_value", redemption_value.to_string()),
+        attr("receiver", receiver.as_str()),
+        attr("tax_amount", tax_amount.to_string()),
+    ];
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(attributes))
+}
+
+/// ## Description
+/// Burns just enough of the `amount` LP tokens sent to redeem `target_usd` worth of `ask_asset`
+/// after fees, and sends the remaining LP back to the sender.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **info** is an object of type [`MessageInfo`]. The sender must be the LP token contract.
+///
+/// * **sender** is an object of type [`Addr`]. This is the LP holder redeeming their tokens.
+///
+/// * **amount** is an object of type [`Uint128`]. This is the most LP tokens that may be burned.
+///
+/// * **target_usd** is an object of type [`Uint128`]. Value to receive, in units of USD_VALUE_PRECISION.
+///
+/// * **ask_asset** is an object of type [`AssetInfo`]. This is the asset paid out.
+///
+/// * **min_out** is an [`Option`] field of type [`Uint128`]. Fails if less of `ask_asset` would be paid out.
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw_usd_value(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    sender: Addr,
+    amount: Uint128,
+    target_usd: Uint128,
+    ask_asset: AssetInfo,
+    min_out: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    if target_usd.is_zero() {
+        return Err(ContractError::InvalidZeroAmount);
+    }
+
+    let basket: Basket = BASKET.load(deps.storage)?;
+    if info.sender != basket.lp_token_address {
+        return Err(ContractError::Unauthorized);
+    }
+
+    let mut ask = priced_basket_asset(&basket, Asset { info: ask_asset.clone(), amount: Uint128::zero() })?;
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let aum_value: Uint128 =
+        basket.calculate_aum(&deps.querier, &mut price_cache)?.to_Uint128(USD_VALUE_PRECISION)?;
+    ask.query_price_cached(&deps.querier, &mut price_cache)?;
+    let reserve_value = ask.query_contract_value(&deps.querier)?;
+
+    // The fee depends on the gross value redeemed, so it is refined once the gross value is known
+    let mut gross_value = target_usd;
+    for _ in 0..2 {
+        if gross_value > reserve_value {
+            return Err(ContractError::InsufficientReserves);
+        }
+        let fee_bps = calculate_fee_basis_points(
+            aum_value,
+            &basket,
+            basket.mint_burn_basis_points,
+            &[reserve_value],
+            &vec![gross_value],
+            &[ask.basket_asset.clone()],
+            Action::Ask,
+        )?[0];
+        gross_value = ceil_multiply_ratio(target_usd, BASIS_POINTS_PRECISION, BASIS_POINTS_PRECISION.checked_sub(fee_bps)?)?;
+    }
+
+    // Rounded up, so the LP burned redeems at least the gross value
+    let lp_supply = basket.total_tokens(&deps.querier, basket.lp_token_address.clone())?;
+    let lp_needed = ceil_multiply_ratio(gross_value, lp_supply, aum_value)?;
+    if lp_needed > amount {
+        return Err(ContractError::InsufficientLpSent { lp_needed });
+    }
+
+    let mut response = withdraw_liquidity(deps.branch(), env, info, sender.clone(), lp_needed, ask_asset, min_out, None)?;
+    if amount > lp_needed {
+        response = response.add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: basket.lp_token_address.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: sender.to_string(),
+                amount: amount - lp_needed,
+            })?,
+            funds: vec![],
+        }));
+    }
+
+    Ok(response
+        .add_attribute("target_usd", target_usd.to_string())
+        .add_attribute("lp_burned", lp_needed.to_string()))
+}
+
+/// Share of each asset's reserves owned by `lp_amount` of `lp_supply` LP tokens, net of the
+/// mint/burn fee. Assets whose share rounds to zero are left out
+fn proportional_redemption(basket: &Basket, lp_amount: Uint128, lp_supply: Uint128) -> Vec<Asset> {
+    if lp_supply.is_zero() {
+        return vec![];
+    }
+    basket
+        .assets
+        .iter()
+        .map(|basket_asset| Asset {
+            info: basket_asset.info.clone(),
+            amount: basket_asset
+                .available_reserves
+                .multiply_ratio(lp_amount, lp_supply)
+                .multiply_ratio(BASIS_POINTS_PRECISION - basket.mint_burn_basis_points, BASIS_POINTS_PRECISION),
+        })
+        .filter(|asset| !asset.amount.is_zero())
+        .collect()
+}
+
+/// ## Description
+/// Burns `amount` LP tokens and returns a slice of every basket asset proportional to the share of
+/// the LP supply burned. Since the reserve ratios are unchanged no imbalance fee applies, only the
+/// basket's mint/burn fee, charged once on the total redeemed.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **_env** is an object of type [`Env`].
+///
+/// * **info** is an object of type [`MessageInfo`]. The sender must be the LP token contract.
+///
+/// * **sender** is an object of type [`Addr`]. This is the LP holder redeeming their tokens.
+///
+/// * **amount** is an object of type [`Uint128`]. This is the amount of LP tokens burned.
+pub fn withdraw_liquidity_proportional(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    sender: Addr,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    // Load Basket
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    // Abort if not from basket lp token contract
+    if info.sender != basket.lp_token_address {
+        return Err(ContractError::Unauthorized);
+    }
+
+    release_deposit(deps.storage, &sender, amount)?;
+
+    let lp_supply: Uint128 = basket.total_tokens(&deps.querier, basket.lp_token_address.clone())?;
+    let fee_bps: Uint128 = basket.mint_burn_basis_points;
+
+    let redemption_assets = proportional_redemption(&basket, amount, lp_supply);
+    for redemption_asset in &redemption_assets {
+        let basket_asset = basket
+            .assets
+            .iter_mut()
+            .find(|basket_asset| basket_asset.info.equal(&redemption_asset.info))
+            .ok_or(ContractError::AssetNotInBasket)?;
+        basket_asset.available_reserves = basket_asset.available_reserves.checked_sub(redemption_asset.amount)?;
+    }
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for redemption_asset in &redemption_assets {
+        messages.push(redemption_asset.clone().into_msg(&deps.querier, sender.clone())?);
+    }
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: basket.lp_token_address.to_string(),
+        msg: to_binary(&Cw20ExecuteMsg::Burn { amount })?,
+        funds: vec![],
+    }));
+
+    BASKET.save(deps.storage, &basket)?;
+
+    let attributes = vec![
+        attr("action", "withdraw_liquidity_proportional"),
+        attr("sender", sender.as_str()),
+        attr(
+            "redemption_assets",
+            redemption_assets
+                .iter()
+                .map(|asset| asset.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+        ),
+        attr("fee_bps", fee_bps.to_string()),
+    ];
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(attributes))
+}
+
+/// Produces unit price of USD, in units of `USD_VALUE_PRECISION`
+pub fn get_unit_price() -> Price {
+    Price {
+        price: 1, //10_i64.pow(-USD_VALUE_PRECISION as u32),
+        expo: USD_VALUE_PRECISION,
+        conf: 0,
+    }
+}
+
+fn instantiate_lp(
+    msg: &InstantiateMsg,
+    env: Env,
+    token_name: String,
+    token_symbol: String,
+) -> Result<Vec<SubMsg>, ContractError> {
+    Ok(vec![SubMsg {
+        msg: WasmMsg::Instantiate {
+            code_id: msg.token_code_id,
+            msg: to_binary(&InstantiateLpMsg {
+                name: token_name,
+                symbol: token_symbol,
+                decimals: msg.lp_decimals.unwrap_or(LP_DECIMALS),
+                initial_balances: vec![],
+                mint: Some(MinterResponse {
+                    minter: env.contract.address.to_string(),
+                    cap: None,
+                }),
+            })
+            .expect("failed to convert InstantiateLpMsg to binary."),
+            funds: vec![],
+            // Lets the basket admin migrate the LP token alongside the basket
+            admin: Some(msg.admin.to_string()),
+            label: "Tsunami LP Token".to_string(),
+        }
+        .into(),
+        id: INSTANTIATE_BASKET_REPLY_ID,
+        gas_limit: None,
+        reply_on: ReplyOn::Success,
+    }])
+}
+
+/// Checks a symbol against the cw20 rules: 3 to 12 characters, each a letter or a dash
+fn is_valid_lp_symbol(symbol: &str) -> bool {
+    (3..=12).contains(&symbol.len())
+        && symbol.chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+}
+
+fn build_assets(querier: &QuerierWrapper, msg: &InstantiateMsg) -> Result<Vec<BasketAsset>, ContractError> {
+    let mut assets = Vec::new();
+    for asset in msg.assets.clone() {
+        let mut basket_asset = BasketAsset::new(asset);
+        basket_asset.decimals = Some(query_token_precision(querier, &basket_asset.info)?);
+        assets.push(basket_asset);
+    }
+    Ok(assets)
+}
+
+fn check_assets(api: &dyn Api, assets: &Vec<InstantiateAssetInfo>) -> Result<u64, ContractError> {
+    let mut asset_names: Vec<String> = Vec::new();
+    for asset in assets {
+        if asset_names.contains(&asset.address.to_string()) {
+            return Err(ContractError::DuplicateAssetAssertion);
+        }
+        asset_names.push(asset.address.to_string());
+
+        asset.oracle.check(api)?;
+        asset.backup_oracle.check(api)?;
+    }
+    Ok(1)
+}
+
+/// ## Description
+/// Exposes all the queries available in the contract.
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **_env** is an object of type [`Env`].
+///
+/// * **msg** is an object of type [`QueryMsg`].
+///
+/// ## Queries
+/// * **QueryMsg::Basket {}** Returns information about the basket in an object of type [`BasketResponse`].
+///
+/// * **QueryMsg::RawBasket {}** Returns the stored basket in an object of type [`Basket`].
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
+    // Swap and LP prices are not quoted off a frozen AUM
+    let is_priced_query = matches!(
+        msg,
+        QueryMsg::SimulateSwap { .. }
+            | QueryMsg::ReverseSimulateSwap { .. }
+            | QueryMsg::SpotPrice { .. }
+            | QueryMsg::FeePreview { .. }
+            | QueryMsg::LpPrice {}
+            | QueryMsg::LpPnl { .. }
+            | QueryMsg::MinRedeemableLp { .. }
+    );
+    if is_priced_query {
+        BASKET.load(deps.storage)?.assert_aum_live()?;
+    }
+
+    match msg {
+        QueryMsg::Basket {} => Ok(to_binary(&query_basket_response(deps, env)?)?),
+        QueryMsg::RawBasket {} => Ok(to_binary(&query_basket(deps)?)?),
+        QueryMsg::SimulateSwap { offer_asset, ask_asset } => {
+            Ok(to_binary(&query_simulate_swap(deps, env, offer_asset, ask_asset)?)?)
+        }
+        QueryMsg::ReverseSimulateSwap { ask_asset, offer_info } => {
+            Ok(to_binary(&query_reverse_simulate_swap(deps, env, ask_asset, offer_info)?)?)
+        }
+        QueryMsg::AssetImbalances {} => Ok(to_binary(&query_asset_imbalances(deps, env)?)?),
+        QueryMsg::AssetPrice { asset } => Ok(to_binary(&query_asset_price(deps, env, asset)?)?),
+        QueryMsg::Stats {} => Ok(to_binary(&query_stats(deps, env)?)?),
+        QueryMsg::Aum {} => Ok(to_binary(&query_aum(deps, env)?)?),
+        QueryMsg::Reserves {} => Ok(to_binary(&query_reserves(deps)?)?),
+        QueryMsg::FeePreview { asset, amount, action } => {
+            Ok(to_binary(&query_fee_preview(deps, env, asset, amount, action)?)?)
+        }
+        QueryMsg::GlobalShorts {} => Ok(to_binary(&query_global_shorts(deps, env)?)?),
+        QueryMsg::LpPrice {} => Ok(to_binary(&query_lp_price(deps, env)?)?),
+        QueryMsg::Config {} => Ok(to_binary(&query_config(deps)?)?),
+        QueryMsg::BorrowRates {} => Ok(to_binary(&query_borrow_rates(deps)?)?),
+        QueryMsg::EstimateTax { asset } => Ok(to_binary(&query_estimate_tax(deps, asset)?)?),
+        QueryMsg::LpPnl { user } => Ok(to_binary(&query_lp_pnl(deps, env, user)?)?),
+        QueryMsg::LpToAssets { lp_amount } => Ok(to_binary(&query_lp_to_assets(deps, lp_amount)?)?),
+        QueryMsg::SpotPrice { offer, ask } => Ok(to_binary(&query_spot_price(deps, env, offer, ask)?)?),
+        QueryMsg::MinRedeemableLp { ask_asset } => {
+            Ok(to_binary(&query_min_redeemable_lp(deps, env, ask_asset)?)?)
+        }
+    }
+}
+
+/// ## Description
+/// Returns information about the basket contract in an object of type [`BASKET`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+pub fn query_basket(deps: Deps) -> StdResult<Basket> {
+    BASKET.load(deps.storage)
+}
+
+/// ## Description
+/// Returns the public view of the basket in an object of type [`BasketResponse`], pricing each
+/// asset with its oracle.
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+pub fn query_basket_response(deps: Deps, env: Env) -> Result<BasketResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let prices = basket.get_prices_with_sources(&deps.querier, &mut PriceCache::new(&basket, env.block.time))?;
+
+    let assets = basket
+        .assets
+        .iter()
+        .zip(prices)
+        .map(|(asset, (price, oracle_source))| BasketAssetResponse {
+            info: asset.info.clone(),
+            token_weight: asset.token_weight,
+            available_reserves: asset.available_reserves,
+            occupied_reserves: asset.occupied_reserves,
+            fee_reserves: asset.fee_reserves,
+            price,
+            oracle_source,
+        })
+        .collect();
+
+    Ok(BasketResponse {
+        name: basket.name,
+        assets,
+        tax_basis_points: basket.tax_basis_points,
+        stable_tax_basis_points: basket.stable_tax_basis_points,
+        mint_burn_basis_points: basket.mint_burn_basis_points,
+        swap_fee_basis_points: basket.swap_fee_basis_points,
+        stable_swap_fee_basis_points: basket.stable_swap_fee_basis_points,
+        margin_fee_basis_points: basket.margin_fee_basis_points,
+        liquidation_fee_usd: basket.liquidation_fee_usd,
+        lp_token_address: basket.lp_token_address,
+    })
+}
+
+/// ## Description
+/// Returns the current oracle price of a single basket asset in an object of type
+/// [`AssetPriceResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **asset** is an object of type [`AssetInfo`].
+pub fn query_asset_price(deps: Deps, env: Env, asset: AssetInfo) -> Result<AssetPriceResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+
+    let basket_asset = basket
+        .assets
+        .iter()
+        .find(|basket_asset| basket_asset.info.equal(&asset))
+        .ok_or(ContractError::AssetNotInBasket)?;
+    let price = basket_asset.oracle.get_price(&deps.querier, env.block.time.seconds(), basket.max_price_age)?;
+
+    Ok(AssetPriceResponse {
+        price: price.price,
+        expo: price.expo,
+        conf: price.conf,
+    })
+}
+
+/// ## Description
+/// Returns the available, occupied and fee reserves of every basket asset, with their utilization,
+/// in an object of type [`ReservesResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+pub fn query_reserves(deps: Deps) -> Result<ReservesResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+
+    let assets = basket
+        .assets
+        .iter()
+        .map(|asset| AssetReserves {
+            info: asset.info.clone(),
+            available_reserves: asset.available_reserves,
+            occupied_reserves: asset.occupied_reserves,
+            fee_reserves: asset.fee_reserves,
+            max_asset_amount: asset.max_asset_amount,
+            utilization: utilization(asset),
+        })
+        .collect();
+
+    Ok(ReservesResponse { assets })
+}
+
+/// ## Description
+/// Returns the open short size of every basket asset, valued at the current oracle price, in an
+/// object of type [`GlobalShortsResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+pub fn query_global_shorts(deps: Deps, env: Env) -> Result<GlobalShortsResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+
+    let assets = basket
+        .assets
+        .iter()
+        .map(|asset| {
+            let mut short = PricedAsset::new(
+                Asset {
+                    info: asset.info.clone(),
+                    amount: asset.global_short_size,
+                },
+                asset.clone(),
+            );
+            short.query_price_cached(&deps.querier, &mut price_cache)?;
+            Ok(AssetShorts {
+                info: asset.info.clone(),
+                global_short_size: asset.global_short_size,
+                short_notional_usd: short.query_value(&deps.querier)?,
+            })
+        })
+        .collect::<Result<Vec<_>, ContractError>>()?;
+
+    let total_short_notional_usd = assets.iter().map(|asset| asset.short_notional_usd).sum();
+    Ok(GlobalShortsResponse {
+        assets,
+        total_short_notional_usd,
+    })
+}
+
+/// ## Description
+/// Returns the value of all assets under management and the aggregate confidence interval of
+/// their prices in an object of type [`AumResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+pub fn query_aum(deps: Deps, env: Env) -> Result<AumResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let aum = basket.calculate_aum(&deps.querier, &mut PriceCache::new(&basket, env.block.time))?;
+
+    Ok(AumResponse {
+        aum: aum.to_Uint128(USD_VALUE_PRECISION)?,
+        conf: aum.conf_to_Uint128(USD_VALUE_PRECISION)?,
+    })
+}
+
+/// ## Description
+/// Returns the value of one whole LP token, with the AUM and LP supply it is derived from, in an
+/// object of type [`LpPriceResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+pub fn query_lp_price(deps: Deps, env: Env) -> Result<LpPriceResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let aum = basket.calculate_aum(&deps.querier, &mut PriceCache::new(&basket, env.block.time))?.to_Uint128(USD_VALUE_PRECISION)?;
+    let lp_supply = query_supply(&deps.querier, basket.lp_token_address.clone())?;
+
+    // An empty basket mints 1 LP per USD, see `provide_liquidity`
+    let price = if lp_supply.is_zero() {
+        Uint128::from(10_u128.pow(-USD_VALUE_PRECISION as u32))
+    } else {
+        aum.multiply_ratio(10_u128.pow(basket.lp_decimals as u32), lp_supply)
+    };
+
+    Ok(LpPriceResponse { price, aum, lp_supply })
+}
+
+/// ## Description
+/// Returns the cw2 contract version, admin, LP token address, USD value precision and pause state
+/// in an object of type [`ConfigResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+pub fn query_config(deps: Deps) -> Result<ConfigResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+
+    Ok(ConfigResponse {
+        contract_version: get_contract_version(deps.storage)?.version,
+        admin: basket.admin,
+        lp_token_address: basket.lp_token_address,
+        usd_value_precision: USD_VALUE_PRECISION,
+        paused: basket.paused,
+    })
+}
+
+/// ## Description
+/// Returns the Terra tax deducted when the contract sends `asset`, e.g. as a withdrawal or swap
+/// payout, in an object of type [`EstimateTaxResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **asset** is an object of type [`Asset`].
+pub fn query_estimate_tax(deps: Deps, asset: Asset) -> Result<EstimateTaxResponse, ContractError> {
+    Ok(EstimateTaxResponse {
+        tax_amount: asset.compute_tax(&deps.querier)?,
+    })
+}
+
+/// ## Description
+/// Returns the assets a proportional withdrawal of `lp_amount` LP tokens would pay out, net of
+/// the mint/burn fee, in an object of type [`LpToAssetsResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **lp_amount** is an object of type [`Uint128`].
+pub fn query_lp_to_assets(deps: Deps, lp_amount: Uint128) -> Result<LpToAssetsResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let lp_supply = query_supply(&deps.querier, basket.lp_token_address.clone())?;
+    Ok(LpToAssetsResponse {
+        assets: proportional_redemption(&basket, lp_amount, lp_supply),
+    })
+}
+
+/// ## Description
+/// Returns the fewest LP tokens that [`withdraw_liquidity`] redeems for at least one unit of
+/// `ask_asset` in an object of type [`MinRedeemableLpResponse`]. Every rounding step of the
+/// withdrawal is undone rounding up. The fee is the one charged on the smallest redemption, so
+/// the amount is exact unless the basket is near a fee boundary.
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **ask_asset** is an object of type [`AssetInfo`]. This is the asset paid out.
+pub fn query_min_redeemable_lp(deps: Deps, env: Env, ask_asset: AssetInfo) -> Result<MinRedeemableLpResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let mut ask_asset = priced_basket_asset(&basket, Asset { info: ask_asset, amount: Uint128::zero() })?;
+
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let aum_value: Uint128 =
+        basket.calculate_aum(&deps.querier, &mut price_cache)?.to_Uint128(USD_VALUE_PRECISION)?;
+    let lp_supply = basket.total_tokens(&deps.querier, basket.lp_token_address.clone())?;
+    // No LP redeems anything from an empty basket
+    if aum_value.is_zero() {
+        return Err(ContractError::ZeroAumWithLpSupply);
+    }
+    ask_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    let decimals = ask_asset.query_decimals(&deps.querier)?;
+    let price = ask_asset.query_price(&deps.querier)?.to_Uint128(-decimals)?;
+
+    // Value after fees that converts to one unit of the asset
+    let unit_value = ceil_multiply_ratio(price, Uint128::new(1), Uint128::from(10_u64).pow(decimals as u32))?;
+    let fee_bps: Uint128 = calculate_fee_basis_points(
+        aum_value,
+        &basket,
+        basket.mint_burn_basis_points,
+        &[ask_asset.query_contract_value(&deps.querier)?],
+        &vec![unit_value],
+        std::slice::from_ref(&ask_asset.basket_asset),
+        Action::Ask,
+    )?[0];
+    let gross_value = ceil_multiply_ratio(unit_value, BASIS_POINTS_PRECISION, BASIS_POINTS_PRECISION.checked_sub(fee_bps)?)?;
+
+    Ok(MinRedeemableLpResponse {
+        lp_amount: ceil_multiply_ratio(gross_value, lp_supply, aum_value)?,
+    })
+}
+
+/// ## Description
+/// Returns the current value of the LP `user` minted against the USD they deposited for it in an
+/// object of type [`LpPnlResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **user** is an object of type [`String`].
+pub fn query_lp_pnl(deps: Deps, env: Env, user: String) -> Result<LpPnlResponse, ContractError> {
+    let user = addr_validate_to_lower(deps.api, &user)?;
+    let deposit = DEPOSITS.may_load(deps.storage, &user)?.unwrap_or(DepositInfo {
+        usd_deposited: Uint128::zero(),
+        lp_amount: Uint128::zero(),
+    });
+
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let lp_supply = query_supply(&deps.querier, basket.lp_token_address.clone())?;
+    let current_value_usd = if deposit.lp_amount.is_zero() || lp_supply.is_zero() {
+        Uint128::zero()
+    } else {
+        let aum = basket.calculate_aum(&deps.querier, &mut PriceCache::new(&basket, env.block.time))?.to_Uint128(USD_VALUE_PRECISION)?;
+        deposit.lp_amount.multiply_ratio(aum, lp_supply)
+    };
+
+    Ok(LpPnlResponse {
+        lp_amount: deposit.lp_amount,
+        cost_basis_usd: deposit.usd_deposited,
+        current_value_usd,
+        pnl_usd: safe_u128_to_i64(current_value_usd.u128())? - safe_u128_to_i64(deposit.usd_deposited.u128())?,
+    })
+}
+
+/// ## Description
+/// Returns the annual borrow rate of every basket asset in an object of type [`BorrowRatesResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+pub fn query_borrow_rates(deps: Deps) -> Result<BorrowRatesResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+
+    Ok(BorrowRatesResponse {
+        assets: basket
+            .assets
+            .iter()
+            .map(|asset| AssetBorrowRate {
+                info: asset.info.clone(),
+                borrow_rate_bps: borrow_rate_bps(&basket, asset),
+            })
+            .collect(),
+    })
+}
+
+/// ## Description
+/// Returns lifetime swap volume, AUM and LP supply in an object of type [`StatsResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+pub fn query_stats(deps: Deps, env: Env) -> Result<StatsResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+
+    Ok(StatsResponse {
+        cumulative_swap_volume_usd: basket.cumulative_swap_volume_usd,
+        aum: basket.calculate_aum(&deps.querier, &mut PriceCache::new(&basket, env.block.time))?.to_Uint128(USD_VALUE_PRECISION)?,
+        lp_supply: query_supply(&deps.querier, basket.lp_token_address.clone())?,
+    })
+}
+
+/// ## Description
+/// Returns how far each basket asset's value is from its target weight in an object of type
+/// [`AssetImbalancesResponse`].
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+pub fn query_asset_imbalances(deps: Deps, env: Env) -> Result<AssetImbalancesResponse, ContractError> {
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let aum_value: Uint128 =
+        basket.calculate_aum(&deps.querier, &mut price_cache)?.to_Uint128(USD_VALUE_PRECISION)?;
+
+    let imbalances = basket
+        .assets
+        .iter()
+        .map(|asset| {
+            let target_usd_value = target_usd_value(aum_value, &basket, asset);
+            let mut priced_asset = PricedAsset::new(
+                Asset { info: asset.info.clone(), amount: Uint128::zero() },
+                asset.clone(),
+            );
+            priced_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+            let current_usd_value = priced_asset.query_contract_value(&deps.querier)?;
+            let imbalance_usd_value = safe_u128_to_i64(current_usd_value.u128())?
+                - safe_u128_to_i64(target_usd_value.u128())?;
+            Ok(AssetImbalance {
+                info: asset.info.clone(),
+                target_usd_value,
+                current_usd_value,
+                imbalance_usd_value,
+            })
+        })
+        .collect::<Result<Vec<_>, ContractError>>()?;
+
+    Ok(AssetImbalancesResponse { imbalances })
+}
+
+/// Pairs `asset` with its [`BasketAsset`] so that it can be priced
+fn priced_basket_asset(basket: &Basket, asset: Asset) -> Result<PricedAsset, ContractError> {
+    let basket_asset = basket
+        .assets
+        .iter()
+        .find(|basket_asset| basket_asset.info.equal(&asset.info))
+        .ok_or(ContractError::AssetNotInBasket)?;
+    Ok(PricedAsset::new(asset, basket_asset.clone()))
+}
+
+/// ## Description
+/// Returns the amount of `ask_asset` that offering `offer_asset` would return, along with the fees charged.
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **offer_asset** is an object of type [`Asset`]. This is the exact amount being offered.
+///
+/// * **ask_asset** is an object of type [`AssetInfo`]. This is the asset being asked for.
+pub fn query_simulate_swap(
+    deps: Deps,
+    env: Env,
+    offer_asset: Asset,
+    ask_asset: AssetInfo,
+) -> Result<SimulationResponse, ContractError> {
+    if offer_asset.info.equal(&ask_asset) {
+        return Err(ContractError::SameAsset);
+    }
+
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let mut offer_asset = priced_basket_asset(&basket, offer_asset)?;
+    let mut ask_asset = priced_basket_asset(&basket, Asset { info: ask_asset, amount: Uint128::zero() })?;
+
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let prices = basket.get_prices(&deps.querier, &mut price_cache)?;
+    offer_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    ask_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    let simulation = simulate_swap(&deps.querier, &basket, &prices, &mut price_cache, &mut offer_asset, &mut ask_asset)?;
+    Ok(SimulationResponse {
+        return_amount: simulation.return_amount,
+        offer_fee_bps: simulation.offer_fee_bps,
+        ask_fee_bps: simulation.ask_fee_bps,
+    })
+}
+
+/// ## Description
+/// Returns the marginal rate of a swap from `offer` to `ask` in an object of type
+/// [`SpotPriceResponse`]. It is the ratio of the two oracle prices, adjusted for the difference
+/// in token decimals, less the fees an empty swap would be charged at the basket's current balance.
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **offer** is an object of type [`AssetInfo`]. This is the asset being offered.
+///
+/// * **ask** is an object of type [`AssetInfo`]. This is the asset being asked for.
+pub fn query_spot_price(deps: Deps, env: Env, offer: AssetInfo, ask: AssetInfo) -> Result<SpotPriceResponse, ContractError> {
+    if offer.equal(&ask) {
+        return Err(ContractError::SameAsset);
+    }
+
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let mut offer_asset = priced_basket_asset(&basket, Asset { info: offer, amount: Uint128::zero() })?;
+    let mut ask_asset = priced_basket_asset(&basket, Asset { info: ask, amount: Uint128::zero() })?;
+
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let prices = basket.get_prices(&deps.querier, &mut price_cache)?;
+    let offer_price = offer_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    let ask_price = ask_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    let offer_decimals = offer_asset.query_decimals(&deps.querier)?;
+    let ask_decimals = ask_asset.query_decimals(&deps.querier)?;
+
+    // An empty offer moves no reserves, leaving just the fees at the current balance
+    let simulation = simulate_swap(&deps.querier, &basket, &prices, &mut price_cache, &mut offer_asset, &mut ask_asset)?;
+    let fee_bps = simulation.offer_fee_bps + simulation.ask_fee_bps;
+
+    // Whole ask tokens per whole offer token, net of fees
+    let ratio = offer_price.div(&ask_price, SPOT_PRICE_EXPO)?.to_Uint128(SPOT_PRICE_EXPO)?;
+    let ratio = ratio.multiply_ratio(BASIS_POINTS_PRECISION.checked_sub(fee_bps)?, BASIS_POINTS_PRECISION);
+    // Shifting the exponent by the decimals difference turns it into ask units per offer unit
+    let price = PythPrice::new(Price {
+        price: safe_u128_to_i64(ratio.u128())?,
+        conf: 0,
+        expo: SPOT_PRICE_EXPO + ask_decimals - offer_decimals,
+    })
+    .to_Decimal()?;
+
+    Ok(SpotPriceResponse {
+        price,
+        offer_fee_bps: simulation.offer_fee_bps,
+        ask_fee_bps: simulation.ask_fee_bps,
+    })
+}
+
+/// ## Description
+/// Returns the fee that offering or asking `amount` of `asset` would be charged against the
+/// current reserves in an object of type [`FeePreviewResponse`]. The base fee is the basket's swap
+/// fee, so stable to stable swaps are charged less than previewed.
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **asset** is an object of type [`AssetInfo`].
+///
+/// * **amount** is an object of type [`Uint128`].
+///
+/// * **action** is an object of type [`String`], either `offer` or `ask`.
+pub fn query_fee_preview(
+    deps: Deps,
+    env: Env,
+    asset: AssetInfo,
+    amount: Uint128,
+    action: String,
+) -> Result<FeePreviewResponse, ContractError> {
+    let action = match action.as_str() {
+        "offer" => Action::Offer,
+        "ask" => Action::Ask,
+        _ => return Err(ContractError::UnknownAction { action }),
+    };
+
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let mut priced_asset = priced_basket_asset(&basket, Asset { info: asset, amount })?;
+
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let initial_aum_value: Uint128 =
+        basket.calculate_aum(&deps.querier, &mut price_cache)?.to_Uint128(USD_VALUE_PRECISION)?;
+    priced_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    let value = priced_asset.query_value(&deps.querier)?;
+    let reserve_value = priced_asset.query_contract_value(&deps.querier)?;
+    if matches!(action, Action::Ask) && value > reserve_value {
+        return Err(ContractError::InsufficientReserves);
+    }
+
+    let fee_bps = calculate_fee_basis_points(
+        initial_aum_value,
+        &basket,
+        basket.swap_fee_basis_points,
+        &[reserve_value],
+        &vec![value],
+        std::slice::from_ref(&priced_asset.basket_asset),
+        action,
+    )?[0];
+
+    Ok(FeePreviewResponse { value, fee_bps })
+}
+
+/// ## Description
+/// Returns the amount of `offer_info` that must be offered to receive exactly `ask_asset`, along
+/// with the fees charged. The required offer is rounded up and covers the Terra tax on a native ask.
+/// ## Params
+/// * **deps** is an object of type [`Deps`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **ask_asset** is an object of type [`Asset`]. This is the exact amount being asked for.
+///
+/// * **offer_info** is an object of type [`AssetInfo`]. This is the asset being offered.
+pub fn query_reverse_simulate_swap(
+    deps: Deps,
+    env: Env,
+    ask_asset: Asset,
+    offer_info: AssetInfo,
+) -> Result<ReverseSimulationResponse, ContractError> {
+    if offer_info.equal(&ask_asset.info) {
+        return Err(ContractError::SameAsset);
+    }
+
+    // Native asks are sent net of Terra tax, see `swap_exact_out`
+    let ask_asset = Asset { amount: ask_asset.add_tax(&deps.querier)?, info: ask_asset.info };
+
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let mut offer_asset = priced_basket_asset(&basket, Asset { info: offer_info, amount: Uint128::zero() })?;
+    let mut ask_asset = priced_basket_asset(&basket, ask_asset)?;
+
+    // Every refinement round reuses the same oracle prices
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let prices = basket.get_prices(&deps.querier, &mut price_cache)?;
+    offer_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    ask_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    let (offer_amount, simulation) =
+        reverse_simulate_swap(&deps.querier, &basket, &prices, &mut price_cache, &mut offer_asset, &mut ask_asset)?;
+    Ok(ReverseSimulationResponse {
+        offer_amount,
+        offer_fee_bps: simulation.offer_fee_bps,
+        ask_fee_bps: simulation.ask_fee_bps,
+    })
+}
+
+/// ## Description
+/// Returns [`ContractError::StableDepegged`] if `asset` is a stable token whose USD price is further
+/// from 1 USD than the basket's `stable_peg_tolerance_bps`. The fee model prices stables at par, so
+/// swapping a depegged one would let the pool be drained cheaply.
+/// ## Params
+/// * **querier** is an object of type [`QuerierWrapper`].
+///
+/// * **basket** is an object of type [`Basket`].
+///
+/// * **asset** is an object of type [`BasketAsset`]. This is one leg of the swap.
+///
+/// * **price_cache** is an object of type [`PriceCache`].
+fn assert_stable_pegged(
+    querier: &QuerierWrapper,
+    basket: &Basket,
+    asset: &BasketAsset,
+    price_cache: &mut PriceCache,
+) -> Result<(), ContractError> {
+    if !asset.stable_token || basket.stable_peg_tolerance_bps.is_zero() {
+        return Ok(());
+    }
+
+    // 1 USD at the exponent of the price, so that no precision is lost comparing them
+    let price = price_cache.get_price(querier, asset)?;
+    let one_usd = if price.expo < 0 {
+        Price {
+            price: 10_i64.checked_pow(price.expo.unsigned_abs()).ok_or(ContractError::FailedCast)?,
+            conf: 0,
+            expo: price.expo,
+        }
+    } else {
+        Price { price: 1, conf: 0, expo: 0 }
+    };
+    if price_deviation_exceeds(one_usd, price, basket.stable_peg_tolerance_bps)? {
+        return Err(ContractError::StableDepegged);
+    }
+    Ok(())
+}
+
+/// ## Description
+/// Returns [`ContractError::Expired`] if the block time is past `deadline`, given in unix seconds.
+/// ## Params
+/// * **env** is an object of type [`Env`].
+///
+/// * **deadline** is an [`Option`] field of type [`u64`].
+fn assert_deadline(env: &Env, deadline: Option<u64>) -> Result<(), ContractError> {
+    match deadline {
+        Some(deadline) if env.block.time.seconds() > deadline => Err(ContractError::Expired),
+        _ => Ok(()),
+    }
+}
+
+/// ## Description
+/// Receives a message of type [`Cw20ReceiveMsg`] and processes it depending on the received template.
+/// If the template is not found in the received message, then an [`ContractError`] is returned,
+/// otherwise it returns the [`Response`] with the specified attributes if the operation was successful.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **cw20_msg** is an object of type [`Cw20ReceiveMsg`]. This is the CW20 message that has to be processed.
+pub fn receive_cw20(
+    deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     cw20_msg: Cw20ReceiveMsg,
 ) -> Result<Response, ContractError> {
@@ -299,10 +2026,14 @@ pub fn receive_cw20(
             max_spread,
             to,
             ask_asset,
+            deadline,
         }) => {
+            assert_deadline(&env, deadline)?;
+
             // Only asset contract can execute this message
             let mut authorized: bool = false;
             let basket = BASKET.load(deps.storage)?;
+            basket.assert_aum_live()?;
 
             for asset in basket.assets {
                 if let AssetInfo::Token { contract_addr, .. } = &asset.info {
@@ -337,22 +2068,336 @@ pub fn receive_cw20(
                 ask_asset,
             )
         }
-        Ok(Cw20HookMsg::WithdrawLiquidity { asset }) => withdraw_liquidity(
-            deps,
-            env,
-            info,
-            Addr::unchecked(cw20_msg.sender),
-            cw20_msg.amount,
-            asset,
-        ),
-        Err(err) => Err(ContractError::Std(err)),
+        Ok(Cw20HookMsg::WithdrawLiquidity { asset, min_out, deadline, receiver }) => {
+            assert_deadline(&env, deadline)?;
+            let receiver = if let Some(receiver) = receiver {
+                Some(addr_validate_to_lower(deps.api, &receiver)?)
+            } else {
+                None
+            };
+            withdraw_liquidity(
+                deps,
+                env,
+                info,
+                Addr::unchecked(cw20_msg.sender),
+                cw20_msg.amount,
+                asset,
+                min_out,
+                receiver,
+            )
+        }
+        Ok(Cw20HookMsg::WithdrawLiquidityProportional { deadline }) => {
+            assert_deadline(&env, deadline)?;
+            withdraw_liquidity_proportional(
+                deps,
+                env,
+                info,
+                Addr::unchecked(cw20_msg.sender),
+                cw20_msg.amount,
+            )
+        }
+        Ok(Cw20HookMsg::WithdrawUsdValue { target_usd, ask_asset, min_out, deadline }) => {
+            assert_deadline(&env, deadline)?;
+            withdraw_usd_value(
+                deps,
+                env,
+                info,
+                Addr::unchecked(cw20_msg.sender),
+                cw20_msg.amount,
+                target_usd,
+                ask_asset,
+                min_out,
+            )
+        }
+        Err(err) => Err(ContractError::Std(err)),
+    }
+}
+
+/// ## Description
+/// Performs an swap operation with the specified parameters. The trader must approve the
+/// pool contract to transfer offer assets from their wallet.
+/// Returns an [`ContractError`] on failure, otherwise returns the [`Response`] with the specified attributes if the operation was successful.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **sender** is an object of type [`Addr`]. This is the sender of the swap operation.
+///
+/// * **offer_asset** is an object of type [`Asset`]. Proposed asset for swapping.
+///
+/// * **belief_price** is an object of type [`Option<Decimal>`]. Used to calculate the maximum swap spread.
+///   When set, the return it implies is reported as `expected_return`.
+///
+/// * **max_spread** is an object of type [`Option<Decimal>`]. Sets the maximum spread of the swap operation.
+///
+/// * **to** is an object of type [`Option<Addr>`]. Sets the recipient of the swap operation.
+/// NOTE - the address that wants to swap should approve the pair contract to pull the offer token.
+#[allow(clippy::too_many_arguments)]
+pub fn swap(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    sender: Addr,
+    offer_asset: Asset,
+    belief_price: Option<Decimal>,
+    _max_spread: Option<Decimal>,
+    to: Option<Addr>,
+    ask_asset: AssetInfo,
+) -> Result<Response, ContractError> {
+    // Swapping an asset for itself would only charge fees on both legs
+    if offer_asset.info.equal(&ask_asset) {
+        return Err(ContractError::SameAsset);
+    }
+
+    // A zero offer would be a no-op swap that still emits events
+    if offer_asset.amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount);
+    }
+
+    // Ensure native token was sent, and nothing else alongside it
+    offer_asset.assert_sent_native_token_balance(&info)?;
+    assert_no_unexpected_funds(&info, std::slice::from_ref(&offer_asset))?;
+
+    // Load basket singleton, get assets
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for asset in basket.assets.iter_mut() {
+        // If the asset is a token contract, then we need to execute a TransferFrom msg to receive assets
+        if let AssetInfo::Token { contract_addr, .. } = &asset.info {
+            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                    owner: info.sender.to_string(),
+                    recipient: env.contract.address.to_string(),
+                    amount: offer_asset.amount,
+                })?,
+                funds: vec![],
+            }));
+        }
+    }
+
+    // Locate both legs once, reserves are updated through these indices below
+    let offer_index = basket
+        .assets
+        .iter()
+        .position(|asset| asset.info == offer_asset.info)
+        .ok_or(ContractError::AssetNotInBasket)?;
+    let ask_index = basket
+        .assets
+        .iter()
+        .position(|asset| asset.info == ask_asset)
+        .ok_or(ContractError::AssetNotInBasket)?;
+
+    let mut offer_asset = PricedAsset::new(offer_asset, basket.assets[offer_index].clone());
+    let mut ask_asset = PricedAsset::new(Asset{info: ask_asset, amount: Uint128::zero()}, basket.assets[ask_index].clone());
+
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let prices = basket.get_prices(&deps.querier, &mut price_cache)?;
+    offer_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    ask_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    assert_stable_pegged(&deps.querier, &basket, &offer_asset.basket_asset, &mut price_cache)?;
+    assert_stable_pegged(&deps.querier, &basket, &ask_asset.basket_asset, &mut price_cache)?;
+    let SwapSimulation {
+        offer_value,
+        return_amount: return_asset_amount,
+        offer_fee_bps,
+        ask_fee_bps,
+        spread_amount,
+    } = simulate_swap(&deps.querier, &basket, &prices, &mut price_cache, &mut offer_asset, &mut ask_asset)?;
+
+    // CW20 offers arrive through the token contract, the trader is the sender it reports
+    let trader = if offer_asset.asset.is_native_token() { &info.sender } else { &sender };
+    check_swap_cooldown(deps.storage, &basket, trader, offer_value, env.block.time.seconds())?;
+
+    // Construct asset type and convert to message to `to` or `sender`
+    let return_asset = Asset {
+        info: ask_asset.asset.info.clone(),
+        amount: return_asset_amount,
+    };
+    let receiver = to.unwrap_or_else(|| sender.clone());
+    let messages: Vec<CosmosMsg> = vec![return_asset.into_msg(&deps.querier, receiver.clone())?];
+
+    basket.assets[offer_index].available_reserves += offer_asset.asset.amount;
+    basket.assets[ask_index].available_reserves -= return_asset_amount;
+    basket.cumulative_swap_volume_usd = basket.cumulative_swap_volume_usd.checked_add(offer_value)?;
+
+    // Save state
+    BASKET.save(deps.storage, &basket)?;
+
+    let response = Response::new()
+        .add_messages(
+            // 1. send collateral tokens from the contract to a user
+            // 2. send inactive commission fees to the Maker contract
+            messages,
+        )
+        .add_attribute("action", "swap")
+        .add_attribute("sender", sender.as_str())
+        .add_attribute("receiver", receiver.as_str())
+        .add_attribute("offer_asset", offer_asset.asset.info.to_string())
+        .add_attribute("ask_asset", ask_asset.asset.info.to_string())
+        .add_attribute("offer_amount", offer_asset.asset.amount.to_string())
+        .add_attribute("return_asset_amount", return_asset_amount.to_string())
+        .add_attribute("offer_bps", offer_fee_bps.to_string())
+        .add_attribute("ask_bps", ask_fee_bps.to_string())
+        .add_attribute("usd_value", offer_value.to_string())
+        .add_attribute("spread_amount", spread_amount.to_string());
+
+    // Lets UIs show the actual return against what the user expected, no spread is enforced here
+    Ok(match belief_price {
+        Some(belief_price) => {
+            let expected_return = offer_asset.asset.amount * belief_price;
+            response.add_attribute("expected_return", expected_return.to_string())
+        }
+        None => response,
+    })
+}
+
+/// ## Description
+/// Swaps just enough of `offer_asset_info` to receive exactly `ask_asset`. Native offers may be
+/// over-sent, any native funds not needed for the swap are refunded to the sender. CW20 offers
+/// are pulled with a TransferFrom of exactly the required amount. A native ask is grossed up for
+/// Terra tax, so the receiver gets `ask_asset` after tax and the offer pays for the tax.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **offer_asset_info** is an object of type [`AssetInfo`]. This is the asset being offered.
+///
+/// * **ask_asset** is an object of type [`Asset`]. This is the exact amount the user will receive.
+///
+/// * **max_offer_amount** is an object of type [`Uint128`]. The swap fails if more than this would be offered.
+///
+/// * **to** is an object of type [`Option<Addr>`]. Sets the recipient of the swap operation.
+pub fn swap_exact_out(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    offer_asset_info: AssetInfo,
+    ask_asset: Asset,
+    max_offer_amount: Uint128,
+    to: Option<Addr>,
+) -> Result<Response, ContractError> {
+    if offer_asset_info.equal(&ask_asset.info) {
+        return Err(ContractError::SameAsset);
+    }
+
+    if ask_asset.amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount);
+    }
+
+    // Only the native offer may be attached, the amount needed is checked once it is known
+    let offer_asset = Asset { info: offer_asset_info, amount: Uint128::zero() };
+    assert_no_unexpected_funds(&info, std::slice::from_ref(&offer_asset))?;
+
+    // Natives are sent net of Terra tax, see `Asset::into_msg`, so the pool pays out the tax on top
+    let return_amount = ask_asset.amount;
+    let ask_asset = Asset { amount: ask_asset.add_tax(&deps.querier)?, info: ask_asset.info };
+    let tax_amount = ask_asset.amount - return_amount;
+
+    let mut basket: Basket = BASKET.load(deps.storage)?;
+    let mut offer_asset = priced_basket_asset(&basket, offer_asset)?;
+    let mut ask_asset = priced_basket_asset(&basket, ask_asset)?;
+
+    // Every refinement round reuses the same oracle prices
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let prices = basket.get_prices(&deps.querier, &mut price_cache)?;
+    offer_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    ask_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+    assert_stable_pegged(&deps.querier, &basket, &offer_asset.basket_asset, &mut price_cache)?;
+    assert_stable_pegged(&deps.querier, &basket, &ask_asset.basket_asset, &mut price_cache)?;
+    let (offer_amount, simulation) =
+        reverse_simulate_swap(&deps.querier, &basket, &prices, &mut price_cache, &mut offer_asset, &mut ask_asset)?;
+    if offer_amount > max_offer_amount {
+        return Err(ContractError::MaxSpreadAssertion);
+    }
+    check_swap_cooldown(deps.storage, &basket, &info.sender, simulation.offer_value, env.block.time.seconds())?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    match &offer_asset.asset.info {
+        // Pull exactly the required offer
+        AssetInfo::Token { contract_addr } => {
+            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                    owner: info.sender.to_string(),
+                    recipient: env.contract.address.to_string(),
+                    amount: offer_amount,
+                })?,
+                funds: vec![],
+            }));
+        }
+        // Refund whatever was sent on top of the required offer
+        AssetInfo::NativeToken { denom } => {
+            let sent = info
+                .funds
+                .iter()
+                .find(|coin| coin.denom == *denom)
+                .map(|coin| coin.amount)
+                .unwrap_or_default();
+            if sent < offer_amount {
+                return Err(ContractError::Std(StdError::generic_err(
+                    "Native token balance is insufficient for the required offer",
+                )));
+            }
+            let refund = Asset {
+                info: offer_asset.asset.info.clone(),
+                amount: sent - offer_amount,
+            };
+            if !refund.amount.is_zero() {
+                messages.push(refund.into_msg(&deps.querier, info.sender.clone())?);
+            }
+        }
+    }
+
+    let receiver = to.unwrap_or_else(|| info.sender.clone());
+    messages.push(ask_asset.asset.clone().into_msg(&deps.querier, receiver.clone())?);
+
+    if let Some(offer_basket_asset) = basket
+        .assets
+        .iter_mut()
+        .find(|asset| offer_asset.asset.info.equal(&asset.info))
+    {
+        offer_basket_asset.available_reserves += offer_amount;
+    }
+
+    if let Some(ask_basket_asset) = basket
+        .assets
+        .iter_mut()
+        .find(|asset| ask_asset.asset.info.equal(&asset.info))
+    {
+        ask_basket_asset.available_reserves = ask_basket_asset.available_reserves.checked_sub(ask_asset.asset.amount)?;
     }
+    basket.cumulative_swap_volume_usd = basket.cumulative_swap_volume_usd.checked_add(simulation.offer_value)?;
+
+    BASKET.save(deps.storage, &basket)?;
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "swap_exact_out")
+        .add_attribute("sender", info.sender.as_str())
+        .add_attribute("receiver", receiver.as_str())
+        .add_attribute("offer_asset", offer_asset.asset.info.to_string())
+        .add_attribute("ask_asset", ask_asset.asset.info.to_string())
+        .add_attribute("offer_amount", offer_amount.to_string())
+        .add_attribute("return_asset_amount", return_amount.to_string())
+        .add_attribute("tax_amount", tax_amount.to_string())
+        .add_attribute("offer_bps", simulation.offer_fee_bps.to_string())
+        .add_attribute("ask_bps", simulation.ask_fee_bps.to_string())
+        .add_attribute("usd_value", simulation.offer_value.to_string())
+        .add_attribute("spread_amount", simulation.spread_amount.to_string()))
 }
 
 /// ## Description
-/// Performs an swap operation with the specified parameters. The trader must approve the
-/// pool contract to transfer offer assets from their wallet.
-/// Returns an [`ContractError`] on failure, otherwise returns the [`Response`] with the specified attributes if the operation was successful.
+/// Swaps `offer_asset` through every asset of `route` in turn, e.g. A→B→C for a route of B and C,
+/// and sends the last hop's return to the sender. Each hop is priced against the reserves left by
+/// the previous one and charged its own fees.
 /// ## Params
 /// * **deps** is an object of type [`DepsMut`].
 ///
@@ -360,81 +2405,188 @@ pub fn receive_cw20(
 ///
 /// * **info** is an object of type [`MessageInfo`].
 ///
-/// * **sender** is an object of type [`Addr`]. This is the sender of the swap operation.
-///
-/// * **offer_asset** is an object of type [`Asset`]. Proposed asset for swapping.
-///
-/// * **belief_price** is an object of type [`Option<Decimal>`]. Used to calculate the maximum swap spread.
+/// * **offer_asset** is an object of type [`Asset`]. This is the amount offered to the first hop.
 ///
-/// * **max_spread** is an object of type [`Option<Decimal>`]. Sets the maximum spread of the swap operation.
+/// * **route** is an array of [`AssetInfo`]. This is the ask asset of every hop.
 ///
-/// * **to** is an object of type [`Option<Addr>`]. Sets the recipient of the swap operation.
-/// NOTE - the address that wants to swap should approve the pair contract to pull the offer token.
-#[allow(clippy::too_many_arguments)]
-pub fn swap(
+/// * **minimum_receive** is an [`Option`] field of type [`Uint128`]. The least amount of the
+///   last ask asset the swap may return.
+pub fn swap_route(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    sender: Addr,
     offer_asset: Asset,
-    _belief_price: Option<Decimal>,
-    _max_spread: Option<Decimal>,
-    to: Option<Addr>,
-    ask_asset: AssetInfo,
+    route: Vec<AssetInfo>,
+    minimum_receive: Option<Uint128>,
 ) -> Result<Response, ContractError> {
-    // Ensure native token was sent
+    if route.is_empty() {
+        return Err(ContractError::EmptyRoute);
+    }
+    if offer_asset.amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount);
+    }
+
+    // Ensure native token was sent, and nothing else alongside it
     offer_asset.assert_sent_native_token_balance(&info)?;
+    assert_no_unexpected_funds(&info, std::slice::from_ref(&offer_asset))?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if let AssetInfo::Token { contract_addr } = &offer_asset.info {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: info.sender.to_string(),
+                recipient: env.contract.address.to_string(),
+                amount: offer_asset.amount,
+            })?,
+            funds: vec![],
+        }));
+    }
 
-    // Load basket singleton, get assets
     let mut basket: Basket = BASKET.load(deps.storage)?;
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let prices = basket.get_prices(&deps.querier, &mut price_cache)?;
+
+    // Every hop offers what the previous one returned
+    let mut hop_offer = offer_asset.clone();
+    let mut total_fee_value = Uint128::zero();
+    let mut route_offer_value = None;
+    for ask_info in route.iter() {
+        if hop_offer.info.equal(ask_info) {
+            return Err(ContractError::SameAsset);
+        }
+        let mut offer = priced_basket_asset(&basket, hop_offer.clone())?;
+        let mut ask = priced_basket_asset(&basket, Asset { info: ask_info.clone(), amount: Uint128::zero() })?;
+        offer.query_price_cached(&deps.querier, &mut price_cache)?;
+        ask.query_price_cached(&deps.querier, &mut price_cache)?;
+        assert_stable_pegged(&deps.querier, &basket, &offer.basket_asset, &mut price_cache)?;
+        assert_stable_pegged(&deps.querier, &basket, &ask.basket_asset, &mut price_cache)?;
+        let simulation = simulate_swap(&deps.querier, &basket, &prices, &mut price_cache, &mut offer, &mut ask)?;
+        route_offer_value.get_or_insert(simulation.offer_value);
+
+        // USD value withheld as fees, matching the post-fee value `simulate_swap` converts
+        let fee_bps = simulation.offer_fee_bps + simulation.ask_fee_bps;
+        total_fee_value += simulation.offer_value
+            - simulation.offer_value.multiply_ratio(BASIS_POINTS_PRECISION - fee_bps, BASIS_POINTS_PRECISION);
+
+        for basket_asset in basket.assets.iter_mut() {
+            if basket_asset.info.equal(&hop_offer.info) {
+                basket_asset.available_reserves = basket_asset.available_reserves.checked_add(hop_offer.amount)?;
+            } else if basket_asset.info.equal(ask_info) {
+                basket_asset.available_reserves =
+                    basket_asset.available_reserves.checked_sub(simulation.return_amount)?;
+            }
+        }
+        basket.cumulative_swap_volume_usd = basket.cumulative_swap_volume_usd.checked_add(simulation.offer_value)?;
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-    for asset in basket.assets.iter_mut() {
-        // If the asset is a token contract, then we need to execute a TransferFrom msg to receive assets
-        if let AssetInfo::Token { contract_addr, .. } = &asset.info {
-            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: contract_addr.to_string(),
-                msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
-                    owner: info.sender.to_string(),
-                    recipient: env.contract.address.to_string(),
-                    amount: offer_asset.amount,
-                })?,
-                funds: vec![],
-            }));
+        hop_offer = Asset {
+            info: ask_info.clone(),
+            amount: simulation.return_amount,
+        };
+    }
+
+    // Enforce the sender's minimum on the final return
+    if let Some(minimum_receive) = minimum_receive {
+        if hop_offer.amount < minimum_receive {
+            return Err(ContractError::MaxSpreadAssertion);
         }
     }
+    // The route is one swap for the cooldown, sized by what was offered into its first hop
+    check_swap_cooldown(
+        deps.storage,
+        &basket,
+        &info.sender,
+        route_offer_value.unwrap_or_default(),
+        env.block.time.seconds(),
+    )?;
+    messages.push(hop_offer.clone().into_msg(&deps.querier, info.sender.clone())?);
 
-    let offer_basket_asset = match basket.assets.iter().find(|asset| {asset.info == offer_asset.info}) {
-        Some(asset) => asset.clone(),
-        None => return Err(ContractError::AssetNotInBasket),
-    };
+    BASKET.save(deps.storage, &basket)?;
 
-    let ask_basket_asset = match basket.assets.iter().find(|asset| {asset.info == ask_asset}) {
-        Some(asset) => asset.clone(),
-        None => return Err(ContractError::AssetNotInBasket),
-    };
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "swap_route")
+        .add_attribute("sender", info.sender.as_str())
+        .add_attribute("offer_asset", offer_asset.to_string())
+        .add_attribute(
+            "route",
+            route.iter().map(|info| info.to_string()).collect::<Vec<String>>().join(","),
+        )
+        .add_attribute("return_asset", hop_offer.to_string())
+        .add_attribute("fee_usd_value", total_fee_value.to_string()))
+}
+
+/// Outcome of pricing a swap against the current state of the basket
+pub struct SwapSimulation {
+    /// USD value of the offer, in units of `USD_VALUE_PRECISION`
+    pub offer_value: Uint128,
+    /// Amount of ask asset returned to the user, net of fees
+    pub return_amount: Uint128,
+    pub offer_fee_bps: Uint128,
+    pub ask_fee_bps: Uint128,
+    /// Amount of ask asset the offer would return at the oracle price, less `return_amount`
+    pub spread_amount: Uint128,
+}
+
+/// ## Description
+/// Prices a swap of `offer_asset` into `ask_asset` without mutating any state. This is the
+/// pricing used by [`swap`] and the swap simulation queries.
+/// ## Params
+/// * **querier** is an object of type [`QuerierWrapper`].
+///
+/// * **basket** is the [`Basket`] being traded against.
+///
+/// * **prices** is an array of [`Price`], the basket's asset prices from [`Basket::get_prices`].
+///
+/// * **price_cache** is the [`PriceCache`] of the call, reused for token decimals.
+///
+/// * **offer_asset** is the [`PricedAsset`] offered by the user, including the offer amount.
+///
+/// * **ask_asset** is the [`PricedAsset`] requested by the user. Its amount is ignored.
+pub fn simulate_swap(
+    querier: &QuerierWrapper,
+    basket: &Basket,
+    prices: &[Price],
+    price_cache: &mut PriceCache,
+    offer_asset: &mut PricedAsset,
+    ask_asset: &mut PricedAsset,
+) -> Result<SwapSimulation, ContractError> {
+    // Either leg may be halted by the admin, e.g. for a depegged stable
+    for basket_asset in [&offer_asset.basket_asset, &ask_asset.basket_asset] {
+        if !basket_asset.swap_enabled {
+            return Err(ContractError::SwapDisabled { asset: basket_asset.to_string() });
+        }
+    }
 
-    let mut offer_asset = PricedAsset::new(offer_asset, offer_basket_asset);
-    let mut ask_asset = PricedAsset::new(Asset{info: ask_asset, amount: Uint128::zero()}, ask_basket_asset);
+    let initial_aum_value =
+        Uint128::new(basket.calculate_aum_with_prices(querier, prices, price_cache)?.pyth_price.price as u128);
+    let user_offer_value = offer_asset.query_value(querier)?;
 
-    let initial_aum_value = Uint128::new(basket.calculate_aum(&deps.querier)?.pyth_price.price as u128);
-    let user_offer_value = offer_asset.query_value(&deps.querier)?;
+    // Swaps between two stable assets are charged the cheaper stable swap fee
+    let base_fee_bps: Uint128 =
+        if offer_asset.basket_asset.stable_token && ask_asset.basket_asset.stable_token {
+            basket.stable_swap_fee_basis_points
+        } else {
+            basket.swap_fee_basis_points
+        };
     let offer_fee_bps: Uint128 = calculate_fee_basis_points(
         initial_aum_value,
-        &basket,
-        &[offer_asset.query_contract_value(&deps.querier)?],
+        basket,
+        base_fee_bps,
+        &[offer_asset.query_contract_value(querier)?],
         &vec![user_offer_value],
-        &[offer_asset.basket_asset.clone()],
+        std::slice::from_ref(&offer_asset.basket_asset),
         Action::Offer,
-    )[0];
+    )?[0];
     let ask_fee_bps: Uint128 = calculate_fee_basis_points(
         initial_aum_value,
-        &basket,
-        &[ask_asset.query_contract_value(&deps.querier)?],
+        basket,
+        base_fee_bps,
+        &[ask_asset.query_contract_value(querier)?],
         &vec![user_offer_value],
-        &[ask_asset.basket_asset.clone()],
+        std::slice::from_ref(&ask_asset.basket_asset),
         Action::Ask,
-    )[0];
+    )?[0];
 
     // Calculate post-fee USD value, then convert USD value to number of tokens.
     let return_asset_value = user_offer_value.multiply_ratio(
@@ -442,55 +2594,72 @@ pub fn swap(
         BASIS_POINTS_PRECISION,
     );
     // Get value of ask per unit usd, e.g. microUSD
-    let ask_per_unit_usd = ask_asset.query_price(&deps.querier)?.pyth_price.price as u128;
+    let ask_per_unit_usd = ask_asset.query_price(querier)?.pyth_price.price as u128;
     // The price of a lamport is 10^ask_decimals lower, so multiply refund_value by appropriate power of 10 then divide by ask price
-    let return_asset_amount =
-        return_asset_value.multiply_ratio(10_u128.pow(ask_asset.query_decimals(&deps.querier)? as u32), ask_per_unit_usd);
+    let ask_unit = 10_u128.pow(ask_asset.query_decimals(querier)? as u32);
+    let return_amount = return_asset_value.multiply_ratio(ask_unit, ask_per_unit_usd);
+
+    // What the offer would fetch at the oracle price without fees, the difference is the price impact
+    let oracle_return_amount = user_offer_value.multiply_ratio(ask_unit, ask_per_unit_usd);
+
+    Ok(SwapSimulation {
+        offer_value: user_offer_value,
+        return_amount,
+        offer_fee_bps,
+        ask_fee_bps,
+        spread_amount: oracle_return_amount.saturating_sub(return_amount),
+    })
+}
 
-    // Construct asset type and convert to message to `to` or `sender`
-    let return_asset = Asset {
-        info: ask_asset.asset.info.clone(),
-        amount: return_asset_amount,
-    };
-    let receiver = to.unwrap_or_else(|| sender.clone());
-    let messages: Vec<CosmosMsg> = vec![return_asset.into_msg(&deps.querier, receiver.clone())?];
+/// Upper bound on the fee-refinement rounds used when reverse simulating a swap
+const MAX_REVERSE_SIMULATION_ROUNDS: u32 = 10;
 
-    match basket
-        .assets
-        .iter_mut()
-        .find(|asset| offer_asset.asset.info.equal(&asset.info))
-    {
-        Some(offer_basket_asset) => offer_basket_asset.available_reserves += offer_asset.asset.amount,
-        None => {}
-    }
+/// ## Description
+/// Finds the smallest offer amount of `offer_asset` that returns at least `ask_asset.asset.amount`
+/// of the ask asset. Every conversion rounds the required offer up, so feeding the result back
+/// into [`simulate_swap`] always yields at least the requested ask amount.
+///
+/// Fees depend on the size of the offer, so the offer is refined until the fees it is charged
+/// are covered. `prices` are the basket's asset prices from [`Basket::get_prices`] and
+/// `price_cache` the call's cache, both shared by every round.
+pub fn reverse_simulate_swap(
+    querier: &QuerierWrapper,
+    basket: &Basket,
+    prices: &[Price],
+    price_cache: &mut PriceCache,
+    offer_asset: &mut PricedAsset,
+    ask_asset: &mut PricedAsset,
+) -> Result<(Uint128, SwapSimulation), ContractError> {
+    let ask_amount = ask_asset.asset.amount;
+
+    // USD value the ask side must be worth after fees
+    let ask_per_unit_usd = Uint128::new(ask_asset.query_price(querier)?.pyth_price.price as u128);
+    let ask_decimals = ask_asset.query_decimals(querier)?;
+    let ask_value = ceil_multiply_ratio(
+        ask_amount,
+        ask_per_unit_usd,
+        Uint128::from(10_u128.pow(ask_decimals as u32)),
+    )?;
+
+    // Start from a fee-less offer and gross it up by the fees it would be charged
+    let mut offer_value = ask_value;
+    for _ in 0..MAX_REVERSE_SIMULATION_ROUNDS {
+        offer_asset.asset.amount = offer_asset.query_amount_for_value(querier, offer_value)?;
+        let simulation = simulate_swap(querier, basket, prices, price_cache, offer_asset, ask_asset)?;
+        if simulation.return_amount >= ask_amount {
+            return Ok((offer_asset.asset.amount, simulation));
+        }
 
-    match basket
-        .assets
-        .iter_mut()
-        .find(|asset| ask_asset.asset.info.equal(&asset.info))
-    {
-        Some(offer_asset) => offer_asset.available_reserves -= return_asset_amount,
-        None => {}
+        let fee_bps = simulation.offer_fee_bps + simulation.ask_fee_bps;
+        let next_offer_value = ceil_multiply_ratio(
+            ask_value,
+            BASIS_POINTS_PRECISION,
+            BASIS_POINTS_PRECISION.checked_sub(fee_bps)?,
+        )?;
+        offer_value = next_offer_value.max(offer_value + Uint128::new(1));
     }
 
-    // Save state
-    BASKET.save(deps.storage, &basket)?;
-
-    Ok(Response::new()
-        .add_messages(
-            // 1. send collateral tokens from the contract to a user
-            // 2. send inactive commission fees to the Maker contract
-            messages,
-        )
-        .add_attribute("action", "swap")
-        .add_attribute("sender", sender.as_str())
-        .add_attribute("receiver", receiver.as_str())
-        .add_attribute("offer_asset", offer_asset.asset.info.to_string())
-        .add_attribute("ask_asset", ask_asset.asset.info.to_string())
-        .add_attribute("offer_amount", offer_asset.asset.amount.to_string())
-        .add_attribute("return_asset_amount", return_asset_amount.to_string())
-        .add_attribute("offer_bps", offer_fee_bps.to_string())
-        .add_attribute("ask_bps", ask_fee_bps.to_string()))
+    Err(ContractError::MaxSpreadAssertion)
 }
 
 // cases to consider
@@ -507,6 +2676,8 @@ pub fn swap(
 ///
 /// * `initial_aum_value` - The total value (normalized in USD) of the Basket's assets
 /// * `basket` - The Basket of assets being traded against
+/// * `base_fee_bps` - The fee charged before any penalty, e.g. the basket's swap fee, or its
+///   stable swap fee when trading between two stable assets.
 /// * `initial_reserve_values` - The reserve values (normalized in USD) for each BasketAsset
 /// being traded against. This includes occupied and unoccupied assets in the pool.
 /// * `offer_or_ask_values` - The USD amount the user wants to trade for each BasketAsset
@@ -521,19 +2692,27 @@ pub fn swap(
 /// CHECK: that we should calculate the current reserves to compare against target reserves using
 /// only the available asset, relies on how AUM is calculated.
 ///
-/// This returns.
+/// Returns [`ContractError::WeightsOutOfSync`] if an asset's weight exceeds the basket's total
+/// weights, which would put its target above the whole AUM.
 pub fn calculate_fee_basis_points(
     initial_aum_value: Uint128,
     basket: &Basket,
+    base_fee_bps: Uint128,
     initial_reserve_values: &[Uint128],
     offer_or_ask_values: &Vec<Uint128>,
     offer_or_ask_assets: &[BasketAsset],
     action: Action,
-) -> Vec<Uint128> {
+) -> Result<Vec<Uint128>, ContractError> {
+    if offer_or_ask_assets.iter().any(|asset| asset.token_weight > basket.get_total_weights()) {
+        return Err(ContractError::WeightsOutOfSync);
+    }
+
     // Compute new aum_value
     let new_aum_value: Uint128 = match action {
         Action::Offer => initial_aum_value + offer_or_ask_values.iter().sum::<Uint128>(),
-        Action::Ask => initial_aum_value - offer_or_ask_values.iter().sum::<Uint128>(),
+        Action::Ask => initial_aum_value
+            .checked_sub(offer_or_ask_values.iter().sum::<Uint128>())
+            .map_err(|_| ContractError::InsufficientReserves)?,
     };
 
     // Compute updated reserve value by adding or subtracting diff_usd_value based on action
@@ -546,8 +2725,8 @@ pub fn calculate_fee_basis_points(
         Action::Ask => initial_reserve_values
             .iter()
             .zip(offer_or_ask_values)
-            .map(|(&a, &b)| a.checked_sub(b).expect("ask too large"))
-            .collect(),
+            .map(|(&a, &b)| a.checked_sub(b).map_err(|_| ContractError::InsufficientReserves))
+            .collect::<Result<Vec<Uint128>, ContractError>>()?,
     };
 
     let mut fee_bps: Vec<Uint128> = vec![];
@@ -559,14 +2738,14 @@ pub fn calculate_fee_basis_points(
         // First depositor should not be hit with a fee
         if  initial_reserve_value.is_zero() {
             fee_bps.push(Uint128::zero());
-            break
+            continue
         }
 
         // Compute target value based on weight, so that we may compare to the updated value
-        let initial_target_lp_usd_value: Uint128 = initial_aum_value
-            .multiply_ratio(offer_or_ask_asset.token_weight, basket.get_total_weights());
-        let new_target_lp_usd_value: Uint128 = new_aum_value
-            .multiply_ratio(offer_or_ask_asset.token_weight, basket.get_total_weights());
+        let initial_target_lp_usd_value: Uint128 =
+            target_usd_value(initial_aum_value, basket, &offer_or_ask_asset);
+        let new_target_lp_usd_value: Uint128 =
+            target_usd_value(new_aum_value, basket, &offer_or_ask_asset);
 
         // Calculate the initial and new distance from the target value
         let initial_distance: Uint128 = initial_target_lp_usd_value.max(initial_reserve_value)
@@ -578,19 +2757,54 @@ pub fn calculate_fee_basis_points(
             Uint256::from_uint128(new_distance) * Uint256::from_uint128(initial_target_lp_usd_value) <=
             Uint256::from_uint128(initial_distance) * Uint256::from_uint128(new_target_lp_usd_value);
 
-        if improvement {
-            fee_bps.push(BASE_FEE_IN_BASIS_POINTS.multiply_ratio(
-                initial_target_lp_usd_value - initial_distance.min(new_target_lp_usd_value),
+        // The basket sets a separate penalty for stable assets
+        let penalty_bps: Uint128 = if offer_or_ask_asset.stable_token {
+            basket.stable_tax_basis_points
+        } else {
+            basket.tax_basis_points
+        };
+
+        let imbalance_fee_bps = if improvement {
+            base_fee_bps.multiply_ratio(
+                initial_target_lp_usd_value
+                    .checked_sub(initial_distance.min(new_target_lp_usd_value))
+                    .map_err(|_| ContractError::WeightsOutOfSync)?,
                 initial_target_lp_usd_value,
-            ));
+            )
         } else {
-            fee_bps.push(BASE_FEE_IN_BASIS_POINTS + PENALTY_IN_BASIS_POINTS.multiply_ratio(
+            base_fee_bps + penalty_bps.multiply_ratio(
                 new_distance.min(new_target_lp_usd_value),
                 new_target_lp_usd_value,
-            ));
-        }
+            )
+        };
+        fee_bps.push(imbalance_fee_bps + utilization_fee_bps(basket, &offer_or_ask_asset));
+    }
+    Ok(fee_bps)
+}
+
+/// Share of an asset's reserves backing positions, `occupied_reserves / available_reserves`
+/// capped at one, zero without reserves. Used by the reserves query and both rate models
+pub fn utilization(asset: &BasketAsset) -> Decimal {
+    if asset.available_reserves.is_zero() {
+        return Decimal::zero();
+    }
+    let occupied = asset.occupied_reserves.min(asset.available_reserves);
+    Decimal::from_ratio(occupied, asset.available_reserves)
+}
+
+/// Annual borrow rate of an asset's occupied reserves, `base + slope * utilization`
+pub fn borrow_rate_bps(basket: &Basket, asset: &BasketAsset) -> Uint128 {
+    basket.borrow_rate_basis_points + basket.borrow_rate_slope_basis_points * utilization(asset)
+}
+
+/// Surcharge for an asset whose reserves are occupied by positions, `base + slope * utilization`.
+/// Idle assets pay none
+pub fn utilization_fee_bps(basket: &Basket, asset: &BasketAsset) -> Uint128 {
+    let utilization = utilization(asset);
+    if utilization.is_zero() {
+        return Uint128::zero();
     }
-    fee_bps
+    basket.utilization_fee_basis_points + basket.utilization_fee_slope_basis_points * utilization
 }
 
 pub enum Action {
@@ -598,6 +2812,11 @@ pub enum Action {
     Ask,
 }
 
+/// USD value `asset` should hold in a basket worth `aum_value`, according to its weight
+pub fn target_usd_value(aum_value: Uint128, basket: &Basket, asset: &BasketAsset) -> Uint128 {
+    aum_value.multiply_ratio(asset.token_weight, basket.get_total_weights())
+}
+
 /// ## Description
 /// Provides liquidity in the pair with the specified input parameters.
 /// Returns a [`ContractError`] on failure, otherwise returns a [`Response`] with the specified
@@ -614,77 +2833,242 @@ pub enum Action {
 /// * **slippage_tolerance** is an [`Option`] field of type [`Decimal`]. It is used to specify how much
 /// the pool price can move until the provide liquidity transaction goes through.
 ///
+/// * **min_lp_out** is an [`Option`] field of type [`Uint128`]. The least amount of LP tokens
+///   the deposit may mint before it is rejected.
+///
 /// * **receiver** is an [`Option`] field of type [`String`]. This is the receiver of the LP tokens.
 /// If no custom receiver is specified, the pair will mint LP tokens for the function caller.
+///
+/// * **auto_stake** is an [`Option`] field of type [`bool`]. Determines whether the LP tokens are
+///   staked in the basket's generator on behalf of the receiver instead of being sent to them.
 // NOTE - the address that wants to provide liquidity should approve the pair contract to pull its relevant tokens.
+#[allow(clippy::too_many_arguments)]
 pub fn provide_liquidity(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     offer_assets: Vec<Asset>,
     _slippage_tolerance: Option<Decimal>,
+    min_lp_out: Option<Uint128>,
     receiver: Option<String>,
+    auto_stake: Option<bool>,
 ) -> Result<Response, ContractError> {
+    // Reject deposits that would not add anything to the basket
+    if offer_assets.iter().all(|asset| asset.amount.is_zero()) {
+        return Err(ContractError::InvalidZeroAmount);
+    }
+
+    // Check assets for valid formatting
     for asset in &offer_assets {
-        // Check assets for valid formatting
         asset.info.check(deps.api)?;
-
-        // Validate amount of native tokens transferred
-        asset.assert_sent_native_token_balance(&info)?;
     }
 
+    // Validate the native coins transferred. Coins outside of the offer would otherwise be stuck
+    // in the contract
+    assert_sent_native_funds(&info, &offer_assets)?;
+
     // Load basket and gather assets
-    let mut basket: Basket = BASKET.load(deps.storage)?;
-    let mut basket_assets = basket.assets.clone();
+    let basket: Basket = BASKET.load(deps.storage)?;
+
+    // Auto staking needs somewhere to stake the LP tokens
+    let auto_stake = auto_stake.unwrap_or(false);
+    if auto_stake && basket.generator_address.is_none() {
+        return Err(ContractError::AutoStakeError);
+    }
+
+    // Grab relevant asset assets in basket. Unwhitelisted assets are rejected here, before any
+    // TransferFrom messages are built
+    let offer_priced_assets: Vec<PricedAsset> = match_offer_assets(&basket, &offer_assets)?;
 
     let mut messages: Vec<CosmosMsg> = vec![];
-    for (i, asset) in basket_assets.iter_mut().enumerate() {
-        // If the asset is a token contract, then we need to execute a TransferFrom msg to receive basket_assets
-        if let AssetInfo::Token { contract_addr, .. } = &asset.info {
+    for offer_asset in &offer_assets {
+        // If the asset is a token contract, then we need to execute a TransferFrom msg to receive it
+        if let AssetInfo::Token { contract_addr, .. } = &offer_asset.info {
+            if offer_asset.amount.is_zero() {
+                continue;
+            }
             messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: contract_addr.to_string(),
                 msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
                     owner: info.sender.to_string(),
                     recipient: env.contract.address.to_string(),
-                    amount: offer_assets[i].amount,
+                    amount: offer_asset.amount,
                 })?,
                 funds: vec![],
             }));
         }
     }
 
-    // Grab relevant asset assets in basket, zipped with price
-    let mut offer_priced_assets: Vec<PricedAsset> = {
-        let mut v: Vec<PricedAsset> = vec![];
+    // Fee-on-transfer tokens may deliver less than the TransferFrom amount. Their balance is
+    // recorded now and the deposit is credited in the reply, with what actually arrived
+    let mut balances_before: Vec<(AssetInfo, Uint128)> = vec![];
+    for offer_asset in &offer_priced_assets {
+        if let AssetInfo::Token { contract_addr } = &offer_asset.asset.info {
+            if offer_asset.basket_asset.fee_on_transfer
+                && !offer_asset.asset.amount.is_zero()
+                && !balances_before.iter().any(|(info, _)| info.equal(&offer_asset.asset.info))
+            {
+                balances_before.push((
+                    offer_asset.asset.info.clone(),
+                    query_token_balance(&deps.querier, contract_addr.clone(), env.contract.address.clone())?,
+                ));
+            }
+        }
+    }
+
+    if !balances_before.is_empty() {
+        // Fee-on-transfer tokens are only ever CW20s, so there is a TransferFrom to reply to
+        let last_transfer = messages.pop().expect("no TransferFrom for a fee-on-transfer deposit");
+        PENDING_DEPOSIT.save(
+            deps.storage,
+            &PendingDeposit {
+                sender: info.sender.clone(),
+                offer_assets: offer_assets.clone(),
+                balances_before,
+                min_lp_out,
+                receiver,
+                auto_stake,
+            },
+        )?;
+        lock(deps.storage)?;
+        return Ok(Response::new()
+            .add_messages(messages)
+            .add_submessage(SubMsg {
+                msg: last_transfer,
+                id: DEPOSIT_REPLY_ID,
+                gas_limit: None,
+                reply_on: ReplyOn::Success,
+            })
+            .add_attributes(vec![
+                attr("action", "provide_liquidity"),
+                attr("sender", info.sender.as_str()),
+                attr("offer_asset", format!("{:?}", &offer_assets)),
+            ]));
+    }
 
-        for offer_asset in &offer_assets {
-            v.push(
-                match basket.assets
-                    .iter()
-                    .find(|asset| asset.info.equal(&offer_asset.info))
-                {
-                    Some(asset) => PricedAsset::new(
-                        offer_asset.clone(),
-                        asset.clone(),
-                    ),
-                    None => return Err(ContractError::AssetNotInBasket),
-                },
-            )
+    credit_deposit(deps, env, info.sender, basket, offer_priced_assets, min_lp_out, receiver, auto_stake, messages)
+}
+
+/// ## Description
+/// Deposits `value_usd` of the native asset with the largest gap below its target value, where
+/// the deposit earns the largest rebate. The caller attaches at least that much of the asset, any
+/// excess is refunded.
+/// ## Params
+/// * **deps** is an object of type [`DepsMut`].
+///
+/// * **env** is an object of type [`Env`].
+///
+/// * **info** is an object of type [`MessageInfo`].
+///
+/// * **value_usd** is an object of type [`Uint128`]. Value to deposit, in units of USD_VALUE_PRECISION.
+///
+/// * **slippage_tolerance** is an [`Option`] field of type [`Decimal`].
+pub fn deposit_to_underweight(
+    mut deps: DepsMut,
+    env: Env,
+    mut info: MessageInfo,
+    value_usd: Uint128,
+    slippage_tolerance: Option<Decimal>,
+) -> Result<Response, ContractError> {
+    if value_usd.is_zero() {
+        return Err(ContractError::InvalidZeroAmount);
+    }
+
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let aum_value: Uint128 =
+        basket.calculate_aum(&deps.querier, &mut price_cache)?.to_Uint128(USD_VALUE_PRECISION)?;
+
+    // Only native assets can be attached to the message
+    let mut underweight: Option<(Uint128, PricedAsset)> = None;
+    for asset in basket.assets.iter().filter(|asset| asset.info.is_native_token()) {
+        let mut priced_asset = PricedAsset::new(
+            Asset { info: asset.info.clone(), amount: Uint128::zero() },
+            asset.clone(),
+        );
+        priced_asset.query_price_cached(&deps.querier, &mut price_cache)?;
+        let current_usd_value = priced_asset.query_contract_value(&deps.querier)?;
+        let gap = target_usd_value(aum_value, &basket, asset).saturating_sub(current_usd_value);
+        let largest = underweight.as_ref().map(|(largest, _)| *largest).unwrap_or_default();
+        if gap > largest {
+            underweight = Some((gap, priced_asset));
         }
-        v
+    }
+    let (_, mut underweight) = underweight.ok_or(ContractError::NoUnderweightAsset)?;
+
+    let deposit = Asset {
+        info: underweight.asset.info.clone(),
+        amount: underweight.query_amount_for_value(&deps.querier, value_usd)?,
+    };
+    assert_no_unexpected_funds(&info, std::slice::from_ref(&deposit))?;
+    let sent = info.funds.iter().map(|coin| coin.amount).sum::<Uint128>();
+    if sent < deposit.amount {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Native token balance is insufficient for the required deposit",
+        )));
+    }
+    let refund = Asset {
+        info: deposit.info.clone(),
+        amount: sent - deposit.amount,
     };
 
+    // Deposit exactly the required amount, as if only that much had been attached
+    let sender = info.sender.clone();
+    if let AssetInfo::NativeToken { denom } = &deposit.info {
+        info.funds = vec![Coin { denom: denom.clone(), amount: deposit.amount }];
+    }
+    let mut response = provide_liquidity(
+        deps.branch(),
+        env,
+        info,
+        vec![deposit.clone()],
+        slippage_tolerance,
+        None,
+        None,
+        None,
+    )?;
+    if !refund.amount.is_zero() {
+        response = response.add_message(refund.into_msg(&deps.querier, sender)?);
+    }
+
+    Ok(response
+        .add_attribute("underweight_asset", deposit.info.to_string())
+        .add_attribute("underweight_amount", deposit.amount.to_string()))
+}
+
+/// Values a deposit of `offer_assets` against the basket, credits it to the reserves and mints LP
+/// for the receiver. The deposit must already be in the contract, or be transferred in by
+/// `messages`, which are sent ahead of the mint.
+#[allow(clippy::too_many_arguments)]
+fn credit_deposit(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    mut basket: Basket,
+    mut offer_priced_assets: Vec<PricedAsset>,
+    min_lp_out: Option<Uint128>,
+    receiver: Option<String>,
+    auto_stake: bool,
+    mut messages: Vec<CosmosMsg>,
+) -> Result<Response, ContractError> {
+    let offer_assets: Vec<Asset> = offer_priced_assets.iter().map(|asset| asset.asset.clone()).collect();
+
+    // Price the whole basket once, offer assets reuse those prices
+    let mut price_cache = PriceCache::new(&basket, env.block.time);
+    let initial_aum_value: Uint128 =
+        basket.calculate_aum(&deps.querier, &mut price_cache)?.to_Uint128(USD_VALUE_PRECISION)?;
+
     // Price of one token --> Value of assets
     let offer_asset_values_in_contract = match offer_priced_assets
         .iter_mut()
         .map(|asset| {
+            asset.query_price_cached(&deps.querier, &mut price_cache)?;
             asset.query_contract_value(&deps.querier)
         })
         .collect::<Result<Vec<_>, ContractError>>() {
             Ok(v) => v,
             Err(e) => return Err(e),
         };
-    let initial_aum_value: Uint128 = basket.calculate_aum(&deps.querier)?.to_Uint128(USD_VALUE_PRECISION)?;
 
     // Value of user deposits
     let user_deposit_values: Vec<Uint128> = match offer_priced_assets
@@ -698,6 +3082,12 @@ pub fn provide_liquidity(
         };
     let total_user_deposit_value: Uint128 = user_deposit_values.iter().sum();
 
+    // Dust deposits only probe fee behavior, so they are rejected. Seeding an empty basket is not
+    // exempt
+    if total_user_deposit_value < basket.min_deposit_value_usd {
+        return Err(ContractError::DepositTooSmall);
+    }
+
     // Retrieve LP token supply
     let lp_supply: Uint128 = query_supply(&deps.querier, basket.lp_token_address.clone())?;
 
@@ -705,42 +3095,54 @@ pub fn provide_liquidity(
     let tokens_to_mint: Uint128 = if lp_supply.is_zero() {
         // Handle deposit into empty basket at 1:1 USD_VALUE_PRECISION mint. First deposit gets zero fees
         total_user_deposit_value.multiply_ratio(
-            10_u128.pow(LP_DECIMALS as u32),
+            10_u128.pow(basket.lp_decimals as u32),
             10_u128.pow(-USD_VALUE_PRECISION as u32),
         )
     } else {
         // Handle deposit into nonempty basket
 
-        // This is the number of tokens to mint before any fees
-        let pre_fee: Uint128 =
-            total_user_deposit_value.multiply_ratio(lp_supply, initial_aum_value);
+        // Outstanding LP with nothing backing it cannot be priced, and would divide by zero below
+        if initial_aum_value.is_zero() {
+            return Err(ContractError::ZeroAumWithLpSupply);
+        }
 
         // Gather fee bps for all deposit assets
         let fee_bps: Vec<Uint128> = calculate_fee_basis_points(
             initial_aum_value,
             &basket,
+            basket.mint_burn_basis_points,
             &offer_asset_values_in_contract,
             &user_deposit_values,
-            &basket.match_basket_assets(&offer_assets.to_asset_info()),
+            &basket.match_basket_assets(&offer_assets.to_asset_info(&basket.assets)?)?,
             Action::Offer,
-        );
+        )?;
 
-        // Calculate all fees: fee per deposit asset
+        // Calculate all fees: USD value of the fee per deposit asset, rounded up
         let fees: Vec<Uint128> = user_deposit_values
             .iter()
             .zip(fee_bps)
-            .map(|(value, bps)| {
-                value.multiply_ratio(BASIS_POINTS_PRECISION - bps, BASIS_POINTS_PRECISION)
-            })
-            .collect();
+            .map(|(value, bps)| ceil_multiply_ratio(*value, bps, BASIS_POINTS_PRECISION))
+            .collect::<Result<Vec<_>, ContractError>>()?;
 
-        let post_fee = pre_fee - fees.iter().sum::<Uint128>();
-        post_fee.multiply_ratio(
-            10_u128.pow(LP_DECIMALS as u32),
-            10_u128.pow(-USD_VALUE_PRECISION as u32),
-        )
+        // Mint LP in proportion to the post-fee value added to the basket
+        let post_fee_value = total_user_deposit_value - fees.iter().sum::<Uint128>();
+        post_fee_value.multiply_ratio(lp_supply, initial_aum_value)
     };
 
+    // Enforce the depositor's minimum LP output
+    if let Some(min_lp_out) = min_lp_out {
+        if tokens_to_mint < min_lp_out {
+            return Err(ContractError::MaxSlippageAssertion);
+        }
+    }
+
+    // Keep the LP supply under the basket's cap, if any
+    if let Some(max_lp_supply) = basket.max_lp_supply {
+        if lp_supply.checked_add(tokens_to_mint)? > max_lp_supply {
+            return Err(ContractError::LpSupplyCapExceeded);
+        }
+    }
+
 
     // Update 
     offer_assets.iter().for_each(|offer_asset| {
@@ -755,14 +3157,16 @@ pub fn provide_liquidity(
     });
 
     // Mint LP tokens for the sender or for the receiver (if set)
-    let receiver = receiver.unwrap_or_else(|| info.sender.to_string());
+    let receiver = addr_validate_to_lower(deps.api, &receiver.unwrap_or_else(|| sender.to_string()))?;
+    record_deposit(deps.storage, &receiver, total_user_deposit_value, tokens_to_mint)?;
     messages.extend(
         mint_liquidity_token_message(
             deps.as_ref(),
             &basket,
             env,
-            addr_validate_to_lower(deps.api, &receiver)?,
+            receiver.clone(),
             tokens_to_mint,
+            auto_stake,
         )
         .map_err(|_| ContractError::LpMintFailed)?,
     );
@@ -772,19 +3176,69 @@ pub fn provide_liquidity(
     // Return response with attributes
     Ok(Response::new().add_messages(messages).add_attributes(vec![
         attr("action", "provide_liquidity"),
-        attr("sender", info.sender.as_str()),
+        attr("sender", sender.as_str()),
         attr("receiver", receiver.as_str()),
         attr("offer_asset", format!("{:?}", &offer_assets)),
         attr("tokens_to_mint", tokens_to_mint.to_string()),
+        attr("usd_value", total_user_deposit_value.to_string()),
     ]))
 }
 
+/// Pairs each offered asset with its basket asset, failing on assets the basket does not hold
+fn match_offer_assets(basket: &Basket, offer_assets: &[Asset]) -> Result<Vec<PricedAsset>, ContractError> {
+    offer_assets
+        .iter()
+        .map(|offer_asset| {
+            match basket.assets.iter().find(|asset| asset.info.equal(&offer_asset.info)) {
+                Some(asset) => Ok(PricedAsset::new(offer_asset.clone(), asset.clone())),
+                None => Err(ContractError::AssetNotInBasket),
+            }
+        })
+        .collect()
+}
+
+/// Credits a deposit of fee-on-transfer tokens once their TransferFroms went through, see
+/// `provide_liquidity`. Each such token is credited with the balance it actually added to the
+/// contract instead of the declared amount.
+fn deposit_after_transfer(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending: PendingDeposit = PENDING_DEPOSIT.load(deps.storage)?;
+    PENDING_DEPOSIT.remove(deps.storage);
+    unlock(deps.storage)?;
+
+    let mut offer_assets = pending.offer_assets;
+    for (info, balance_before) in pending.balances_before {
+        if let AssetInfo::Token { contract_addr } = &info {
+            let balance = query_token_balance(&deps.querier, contract_addr.clone(), env.contract.address.clone())?;
+            if let Some(offer_asset) = offer_assets.iter_mut().find(|asset| asset.info.equal(&info)) {
+                offer_asset.amount = balance.checked_sub(balance_before)?;
+            }
+        }
+    }
+    if offer_assets.iter().all(|asset| asset.amount.is_zero()) {
+        return Err(ContractError::InvalidZeroAmount);
+    }
+
+    let basket: Basket = BASKET.load(deps.storage)?;
+    let offer_priced_assets = match_offer_assets(&basket, &offer_assets)?;
+    credit_deposit(
+        deps,
+        env,
+        pending.sender,
+        basket,
+        offer_priced_assets,
+        pending.min_lp_out,
+        pending.receiver,
+        pending.auto_stake,
+        vec![],
+    )
+}
+
 /// ## Description
 /// Mint LP tokens for a beneficiary and auto stake the tokens in the Generator contract (if auto staking is specified).
 /// # Params
 /// * **deps** is an object of type [`Deps`].
 ///
-/// * **config** is an object of type [`Config`].
+/// * **basket** is an object of type [`Basket`].
 ///
 /// * **env** is an object of type [`Env`].
 ///
@@ -797,22 +3251,47 @@ pub fn provide_liquidity(
 fn mint_liquidity_token_message(
     _deps: Deps,
     basket: &Basket,
-    _env: Env,
+    env: Env,
     recipient: Addr,
     amount: Uint128,
+    auto_stake: bool,
 ) -> Result<Vec<CosmosMsg>, ContractError> {
     // Retrieve lp token contract address
     let lp_token = basket.lp_token_address.clone();
 
     // Mint to Recipient
-    Ok(vec![CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: lp_token.to_string(),
-        msg: to_binary(&Cw20ExecuteMsg::Mint {
-            recipient: recipient.to_string(),
-            amount,
-        })?,
-        funds: vec![],
-    })])
+    if !auto_stake {
+        return Ok(vec![CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: lp_token.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: recipient.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        })]);
+    }
+
+    // Mint to the basket, then stake in the Generator on behalf of the recipient
+    let generator = basket.generator_address.clone().ok_or(ContractError::AutoStakeError)?;
+    Ok(vec![
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: lp_token.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: env.contract.address.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        }),
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: lp_token.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: generator.to_string(),
+                amount,
+                msg: to_binary(&GeneratorHookMsg::DepositFor(recipient))?,
+            })?,
+            funds: vec![],
+        }),
+    ])
 }
 
 // TODO: should pass in an enum that is either offer, ask, USD, and check the expo of the price going in
@@ -1,4 +1,4 @@
-use cosmwasm_std::{OverflowError, StdError};
+use cosmwasm_std::{OverflowError, StdError, Uint128};
 use thiserror::Error;
 
 /// ## Description
@@ -11,6 +11,9 @@ pub enum ContractError {
     #[error("Unauthorized")]
     Unauthorized,
 
+    #[error("The basket's LP token has already been initialized")]
+    AlreadyInitialized,
+
     #[error("Operation non supported")]
     NonSupported,
 
@@ -44,21 +47,137 @@ pub enum ContractError {
     #[error("Unable to retrieve pyth price")]
     OracleQueryFailed,
 
+    #[error("Oracle price is {age_seconds}s old, more than the {max_age}s allowed")]
+    StalePrice { age_seconds: u64, max_age: u64 },
+
+    #[error("Oracle price is not trading")]
+    PriceNotTrading,
+
+    #[error("A price multiplication, division or inversion overflowed or divided by zero")]
+    PriceArithmeticFailed,
+
+    #[error("The primary and backup oracle prices deviate too much")]
+    OracleDeviationTooHigh,
+
     #[error("Failed to cast between types safely")]
     FailedCast,
 
     #[error("Failed to mint lp token")]
     LpMintFailed,
 
+    #[error("Auto staking requires the basket to have a generator address")]
+    AutoStakeError,
+
     #[error("The asset the user is asking for is not in this basket")]
     AssetNotInBasket,
 
+    #[error("Unknown fee action {action}, expected offer or ask")]
+    UnknownAction {
+        action: String,
+    },
+
+    #[error("The ask is worth more than the asset's reserves")]
+    InsufficientReserves,
+
+    #[error("Redeeming the requested value takes {lp_needed} LP tokens, more than were sent")]
+    InsufficientLpSent {
+        lp_needed: Uint128,
+    },
+
+    #[error("The offer and ask assets of a swap must be different")]
+    SameAsset,
+
+    #[error("Large swaps from the same address must be {cooldown_seconds} seconds apart, {remaining_seconds} left")]
+    SwapCooldown {
+        cooldown_seconds: u64,
+        remaining_seconds: u64,
+    },
+
+    #[error("A swap route needs at least one hop")]
+    EmptyRoute,
+
+    #[error("No native asset is below its target weight")]
+    NoUnderweightAsset,
+
+    #[error("Swaps are disabled for {asset}")]
+    SwapDisabled {
+        asset: String,
+    },
+
+    #[error("The basket is paused")]
+    Paused,
+
+    #[error("The basket is waiting on the reply of an earlier operation")]
+    Reentrancy,
+
+    #[error("The AUM is frozen, only withdrawals are open")]
+    AumFrozen,
+
+    #[error("The transaction was executed after its deadline")]
+    Expired,
+
+    #[error("{asset} still holds reserves and cannot be removed")]
+    AssetNotEmpty {
+        asset: String,
+    },
+
+    #[error("{asset} still holds reserves, remove the asset instead of setting its weight to zero")]
+    ZeroWeightWithReserves {
+        asset: String,
+    },
+
+    #[error("An asset's weight exceeds the basket's total weights")]
+    WeightsOutOfSync,
+
+    #[error("The basket has outstanding LP tokens but no assets under management")]
+    ZeroAumWithLpSupply,
+
     #[error("The user's deposit amount exceeds the reserve limit of one or more of the assets")]
     DepositLimitExceeded,
+
+    #[error("The user's deposit is worth less than the basket's minimum deposit value")]
+    DepositTooSmall,
+
+    #[error("The deposit would mint LP tokens past the basket's LP supply cap")]
+    LpSupplyCapExceeded,
     
     #[error("When pricing an asset, found a negative price")]
     NegativePrice,
 
+    #[error("When pricing an asset, found a price of zero")]
+    ZeroPrice,
+
+    #[error("A stable asset of the swap is trading too far from 1 USD")]
+    StableDepegged,
+
+    #[error("An asset's Pyth oracle has no price id or an invalid contract address")]
+    InvalidOracleConfig,
+
+    #[error("{hex} is not a 32 byte hex Pyth price id")]
+    InvalidPriceId {
+        hex: String,
+    },
+
+    #[error("The LP token symbol must be 3-12 characters, each a letter or a dash")]
+    InvalidLpTokenSymbol,
+
+    #[error("The LP token decimals must be between the USD precision and 18")]
+    InvalidLpDecimals,
+
+    #[error("The LP token code id must be nonzero")]
+    InvalidCodeId,
+
+    #[error("Stub oracles cannot be used in a production basket")]
+    StubOracleInProduction,
+
+    #[error("The basket has no pyth contract to post price updates to")]
+    PythNotConfigured,
+
+    #[error("Posting the price update failed: {reason}")]
+    PriceUpdateFailed {
+        reason: String,
+    },
+
     #[error("Failed to query token decimals")]
     FailedToQueryTokenDecimals,
 
@@ -1,11 +1,13 @@
 use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    from_binary, from_slice, to_binary, Coin, OwnedDeps, Querier, QuerierResult, QueryRequest,
-    SystemError, SystemResult, Uint128, WasmQuery,
+    from_binary, from_slice, to_binary, Coin, Decimal, OwnedDeps, Querier, QuerierResult,
+    QueryRequest, SystemError, SystemResult, Uint128, WasmQuery,
 };
 use cw20::{BalanceResponse, Cw20QueryMsg, TokenInfoResponse};
+use pyth_sdk_terra::{PriceFeed, PriceFeedResponse, PriceIdentifier, QueryMsg as PythQueryMsg};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use terra_cosmwasm::TerraQueryWrapper;
+use terra_cosmwasm::{TaxCapResponse, TaxRateResponse, TerraQuery, TerraQueryWrapper, TerraRoute};
 use crate::contract::LP_DECIMALS;
 
 /// mock_dependencies is a drop-in replacement for cosmwasm_std::testing::mock_dependencies.
@@ -26,18 +28,75 @@ pub fn mock_dependencies(
 pub struct WasmMockQuerier {
     base: MockQuerier<TerraQueryWrapper>,
     token_querier: TokenQuerier,
+    tax_querier: TaxQuerier,
+    pyth_querier: PythQuerier,
+}
+
+#[derive(Clone, Default)]
+pub struct PythQuerier {
+    // Price feeds served by each mocked pyth contract, keyed by price id
+    price_feeds: HashMap<String, HashMap<PriceIdentifier, PriceFeed>>,
+    // Number of price feed queries served so far
+    hits: Cell<u32>,
+}
+
+impl PythQuerier {
+    pub fn new(price_feeds: &[(&String, &PriceFeed)]) -> Self {
+        let mut price_feeds_map: HashMap<String, HashMap<PriceIdentifier, PriceFeed>> =
+            HashMap::new();
+        for (contract_addr, price_feed) in price_feeds.iter() {
+            price_feeds_map
+                .entry(contract_addr.to_string())
+                .or_default()
+                .insert(price_feed.id, **price_feed);
+        }
+        PythQuerier {
+            price_feeds: price_feeds_map,
+            hits: Cell::new(0),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct TaxQuerier {
+    rate: Decimal,
+    // Tax cap for each native denom
+    caps: HashMap<String, Uint128>,
+}
+
+impl TaxQuerier {
+    pub fn new(rate: Decimal, caps: &[(&String, &Uint128)]) -> Self {
+        TaxQuerier {
+            rate,
+            caps: caps_to_map(caps),
+        }
+    }
+}
+
+pub(crate) fn caps_to_map(caps: &[(&String, &Uint128)]) -> HashMap<String, Uint128> {
+    let mut owner_map: HashMap<String, Uint128> = HashMap::new();
+    for (denom, cap) in caps.iter() {
+        owner_map.insert(denom.to_string(), **cap);
+    }
+    owner_map
 }
 
 #[derive(Clone, Default)]
 pub struct TokenQuerier {
     // This lets us iterate over all pairs that match the first string
     balances: HashMap<String, HashMap<String, Uint128>>,
+    // Decimals reported by each token, LP_DECIMALS when not set
+    decimals: HashMap<String, u8>,
+    // Number of token info queries served so far by each token
+    token_info_hits: RefCell<HashMap<String, u32>>,
 }
 
 impl TokenQuerier {
     pub fn new(balances: &[(&String, &[(&String, &Uint128)])]) -> Self {
         TokenQuerier {
             balances: balances_to_map(balances),
+            decimals: HashMap::new(),
+            token_info_hits: RefCell::new(HashMap::new()),
         }
     }
 }
@@ -76,9 +135,22 @@ impl Querier for WasmMockQuerier {
 impl WasmMockQuerier {
     pub fn handle_query(&self, request: &QueryRequest<TerraQueryWrapper>) -> QuerierResult {
         match &request {
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg })
+                if self.pyth_querier.price_feeds.contains_key(contract_addr) =>
+            {
+                let PythQueryMsg::PriceFeed { id } = from_binary(msg).unwrap();
+                self.pyth_querier.hits.set(self.pyth_querier.hits.get() + 1);
+                match self.pyth_querier.price_feeds[contract_addr].get(&id) {
+                    Some(price_feed) => SystemResult::Ok(
+                        to_binary(&PriceFeedResponse { price_feed: *price_feed }).into(),
+                    ),
+                    None => SystemResult::Err(SystemError::Unknown {}),
+                }
+            }
             QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => {
                 match from_binary(&msg).unwrap() {
                     Cw20QueryMsg::TokenInfo {} => {
+                        *self.token_querier.token_info_hits.borrow_mut().entry(contract_addr.to_string()).or_default() += 1;
                         let balances: &HashMap<String, Uint128> =
                             match self.token_querier.balances.get(contract_addr) {
                                 Some(balances) => balances,
@@ -96,7 +168,12 @@ impl WasmMockQuerier {
                             to_binary(&TokenInfoResponse {
                                 name: "lp".to_string(),
                                 symbol: "lp".to_string(),
-                                decimals: LP_DECIMALS,
+                                decimals: self
+                                    .token_querier
+                                    .decimals
+                                    .get(contract_addr)
+                                    .copied()
+                                    .unwrap_or(LP_DECIMALS),
                                 total_supply: total_supply,
                             })
                             .into(),
@@ -123,6 +200,31 @@ impl WasmMockQuerier {
                     _ => panic!("DO NOT ENTER HERE"),
                 }
             }
+            QueryRequest::Custom(TerraQueryWrapper { route, query_data }) => {
+                if route == &TerraRoute::Treasury {
+                    match query_data {
+                        TerraQuery::TaxRate {} => {
+                            let res = TaxRateResponse {
+                                rate: self.tax_querier.rate,
+                            };
+                            SystemResult::Ok(to_binary(&res).into())
+                        }
+                        TerraQuery::TaxCap { denom } => {
+                            let cap = self
+                                .tax_querier
+                                .caps
+                                .get(denom)
+                                .copied()
+                                .unwrap_or_default();
+                            let res = TaxCapResponse { cap };
+                            SystemResult::Ok(to_binary(&res).into())
+                        }
+                        _ => panic!("DO NOT ENTER HERE"),
+                    }
+                } else {
+                    panic!("DO NOT ENTER HERE")
+                }
+            }
             _ => self.base.handle_query(request),
         }
     }
@@ -133,11 +235,44 @@ impl WasmMockQuerier {
         WasmMockQuerier {
             base,
             token_querier: TokenQuerier::default(),
+            tax_querier: TaxQuerier::default(),
+            pyth_querier: PythQuerier::default(),
         }
     }
 
     // Configure the mint whitelist mock querier
     pub fn with_token_balances(&mut self, balances: &[(&String, &[(&String, &Uint128)])]) {
+        let decimals = std::mem::take(&mut self.token_querier.decimals);
+        let token_info_hits = std::mem::take(&mut self.token_querier.token_info_hits);
         self.token_querier = TokenQuerier::new(balances);
+        self.token_querier.decimals = decimals;
+        self.token_querier.token_info_hits = token_info_hits;
+    }
+
+    // Configure the decimals reported by individual tokens
+    pub fn with_token_decimals(&mut self, decimals: &[(&String, u8)]) {
+        for (contract_addr, decimals) in decimals.iter() {
+            self.token_querier.decimals.insert(contract_addr.to_string(), *decimals);
+        }
+    }
+
+    // Configure the tax rate and per-denom tax caps
+    pub fn with_tax(&mut self, rate: Decimal, caps: &[(&String, &Uint128)]) {
+        self.tax_querier = TaxQuerier::new(rate, caps);
+    }
+
+    // Configure the price feeds served by mocked pyth contracts
+    pub fn with_price_feeds(&mut self, price_feeds: &[(&String, &PriceFeed)]) {
+        self.pyth_querier = PythQuerier::new(price_feeds);
+    }
+
+    // Number of price feed queries served by the mocked pyth contracts
+    pub fn pyth_query_count(&self) -> u32 {
+        self.pyth_querier.hits.get()
+    }
+
+    // Number of token info queries, for supply or decimals, served by a mocked token
+    pub fn token_info_query_count(&self, contract_addr: &str) -> u32 {
+        self.token_querier.token_info_hits.borrow().get(contract_addr).copied().unwrap_or(0)
     }
 }
@@ -1,7 +1,8 @@
 use crate::asset::{Asset, AssetInfo};
-use crate::state::{OracleInterface, TickerData};
-use cosmwasm_std::{Addr, Decimal, Uint128};
+use crate::state::{OracleInterface, OracleSource, Quote, TickerData};
+use cosmwasm_std::{Addr, Binary, Decimal, Uint128};
 use cw20::{Cw20Coin, Cw20ReceiveMsg, MinterResponse};
+use pyth_sdk_terra::Price;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -29,17 +30,66 @@ pub struct InstantiateMsg {
     pub min_profit_time: Uint128,
     /// account that can make changes to the exchange
     pub admin: Addr,
+    /// max deviation between an asset's primary and backup oracle prices, zero disables the check
+    pub max_oracle_deviation_bps: Uint128,
+    /// Generator contract LP tokens are staked in for deposits with auto_stake
+    pub generator_address: Option<Addr>,
+    /// smallest USD value a deposit may add, in units of USD_VALUE_PRECISION, zero disables the check
+    pub min_deposit_value_usd: Uint128,
+    /// max deviation of a stable asset's price from 1 USD before swaps with it are blocked, zero disables the check
+    pub stable_peg_tolerance_bps: Uint128,
+    /// Pyth contract that UpdatePricesThenSwap posts price updates to
+    pub pyth_contract_address: Option<Addr>,
+    /// Reference asset AUM and LP value are denominated in, USD when unset
+    pub quote: Option<Quote>,
+    /// Most LP tokens that may be outstanding, deposits minting past it are rejected
+    pub max_lp_supply: Option<Uint128>,
+    /// Name of the LP token, defaults to "{name}-LP"
+    pub lp_token_name: Option<String>,
+    /// Symbol of the LP token, defaults to "TLP". 3-12 letters or dashes, as cw20 requires
+    pub lp_token_symbol: Option<String>,
+    /// Decimals of the LP token, defaults to 9. Must be between 6, the USD precision, and 18
+    pub lp_decimals: Option<u8>,
+    /// fee added for assets with reserves occupied by positions, defaults to zero
+    pub utilization_fee_basis_points: Option<Uint128>,
+    /// fee added per 100% utilization (occupied / available reserves) on top of the base
+    /// utilization fee, defaults to zero
+    pub utilization_fee_slope_basis_points: Option<Uint128>,
+    /// annual borrow rate charged on occupied reserves, defaults to zero
+    pub borrow_rate_basis_points: Option<Uint128>,
+    /// annual borrow rate added per 100% utilization on top of the base rate, defaults to zero
+    pub borrow_rate_slope_basis_points: Option<Uint128>,
+    /// Rejects stub oracles, which price off a constant and are only meant for testing
+    #[serde(default)]
+    pub production: bool,
     /// The token contract code ID used for the tokens in the pool
     pub token_code_id: u64,
 }
 
+/// Migrates the basket to the current contract version
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
 pub enum ExecuteMsg {
     DepositLiquidity {
         assets: Vec<Asset>,
         slippage_tolerance: Option<Decimal>,
+        /// Fail if fewer LP tokens than this would be minted
+        min_lp_out: Option<Uint128>,
         receiver: Option<String>,
+        /// Stake the minted LP tokens in the generator on behalf of the receiver
+        auto_stake: Option<bool>,
+        /// Fail if executed after this unix timestamp, in seconds
+        deadline: Option<u64>,
+    },
+    /// Deposit `value_usd`, in units of USD_VALUE_PRECISION, of the native asset furthest below
+    /// its target weight. At least that much of the asset must be attached, the rest is refunded
+    DepositToUnderweight {
+        value_usd: Uint128,
+        slippage_tolerance: Option<Decimal>,
     },
     Receive(Cw20ReceiveMsg),
     Swap {
@@ -49,14 +99,159 @@ pub enum ExecuteMsg {
         max_spread: Option<Decimal>,
         to: Option<Addr>,
         ask_asset: AssetInfo,
+        /// Fail if executed after this unix timestamp, in seconds
+        deadline: Option<u64>,
+    },
+    /// Post fresh Pyth prices, then swap on them once the update succeeded
+    UpdatePricesThenSwap {
+        price_update_data: Vec<Binary>,
+        swap: Box<SwapParams>,
+    },
+    SwapExactOut {
+        offer_asset_info: AssetInfo,
+        ask_asset: Asset,
+        max_offer_amount: Uint128,
+        to: Option<Addr>,
+    },
+    /// Swap through the basket along `route`, the ask asset of every hop, each hop offering the
+    /// previous hop's return. Fails if the last hop returns less than `minimum_receive`
+    SwapRoute {
+        offer_asset: Asset,
+        route: Vec<AssetInfo>,
+        minimum_receive: Option<Uint128>,
+    },
+    SetAssetSwapEnabled {
+        asset: AssetInfo,
+        enabled: bool,
+    },
+    SetPaused {
+        paused: bool,
+    },
+    UpdateAssetWeights {
+        weights: Vec<(AssetInfo, Uint128)>,
+    },
+    AddAsset {
+        asset: InstantiateAssetInfo,
+    },
+    RemoveAsset {
+        asset: AssetInfo,
+    },
+    UpdateOracle {
+        asset: AssetInfo,
+        oracle: OracleInterface,
+        backup_oracle: Option<OracleInterface>,
+    },
+    SetManualPrice {
+        asset: AssetInfo,
+        price: i64,
+        expo: i32,
+    },
+    SetGenerator {
+        generator_address: Option<String>,
+    },
+    SetLpSupplyCap {
+        max_lp_supply: Option<Uint128>,
+    },
+    /// Make addresses wait `cooldown_seconds` between swaps worth at least `large_swap_usd`, in
+    /// units of USD_VALUE_PRECISION. Unsetting `large_swap_usd` lifts the cooldown. Admin only
+    SetSwapCooldown {
+        large_swap_usd: Option<Uint128>,
+        cooldown_seconds: u64,
+    },
+    /// Accrue borrow fees on occupied reserves up to the current block
+    AccrueInterest {},
+    /// Pin the basket's AUM to `value`, in units of USD_VALUE_PRECISION, while the oracles are
+    /// down. Withdrawals redeem LP at this value, while deposits and swaps are held until
+    /// unfrozen. Admin only
+    FreezeAum {
+        value: Uint128,
+    },
+    /// Go back to pricing the AUM from the oracles. Admin only
+    UnfreezeAum {},
+    /// Reject Pyth prices published more than `max_price_age` seconds before the block. Unsetting
+    /// it accepts prices of any age. Admin only
+    SetMaxPriceAge {
+        max_price_age: Option<u64>,
     },
 }
 
+/// The fields of [`ExecuteMsg::Swap`], for messages that swap as part of a larger operation
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SwapParams {
+    pub sender: Addr,
+    pub offer_asset: Asset,
+    pub belief_price: Option<Decimal>,
+    pub max_spread: Option<Decimal>,
+    pub to: Option<Addr>,
+    pub ask_asset: AssetInfo,
+    /// Fail if executed after this unix timestamp, in seconds
+    pub deadline: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    // Basket returns the basket as a json-encoded string
+    // Basket returns the public view of the basket, with current asset prices
     Basket {},
+    // RawBasket returns the basket as stored, including internal reserve and funding fields
+    RawBasket {},
+    // SimulateSwap returns the amount of ask asset received for an exact offer
+    SimulateSwap {
+        offer_asset: Asset,
+        ask_asset: AssetInfo,
+    },
+    // ReverseSimulateSwap returns the amount of offer asset required to receive an exact ask
+    ReverseSimulateSwap {
+        ask_asset: Asset,
+        offer_info: AssetInfo,
+    },
+    // AssetImbalances returns how far each asset's value is from its target weight
+    AssetImbalances {},
+    // AssetPrice returns the current oracle price of a single basket asset
+    AssetPrice {
+        asset: AssetInfo,
+    },
+    // Stats returns lifetime swap volume alongside the current AUM and LP supply
+    Stats {},
+    // Aum returns the value of all assets under management with its confidence interval
+    Aum {},
+    // Reserves returns the split of every asset's reserves and how much of them is in use
+    Reserves {},
+    // FeePreview returns the fee for offering ("offer") or asking ("ask") an amount of an asset
+    FeePreview {
+        asset: AssetInfo,
+        amount: Uint128,
+        action: String,
+    },
+    // GlobalShorts returns the open short size of every asset and its value at the oracle price
+    GlobalShorts {},
+    // LpPrice returns the value of one whole LP token
+    LpPrice {},
+    // Config returns the contract version, admin, LP token and whether the basket is paused
+    Config {},
+    // BorrowRates returns the current annual borrow rate of every asset
+    BorrowRates {},
+    // EstimateTax returns the Terra tax deducted when the contract sends `asset`
+    EstimateTax {
+        asset: Asset,
+    },
+    // LpPnl returns the value of a user's deposited LP against what they deposited for it
+    LpPnl {
+        user: String,
+    },
+    // LpToAssets returns the assets a proportional withdrawal of `lp_amount` would pay out
+    LpToAssets {
+        lp_amount: Uint128,
+    },
+    // SpotPrice returns the marginal rate of a swap from `offer` to `ask`, net of fees
+    SpotPrice {
+        offer: AssetInfo,
+        ask: AssetInfo,
+    },
+    // MinRedeemableLp returns the fewest LP tokens a withdrawal of `ask_asset` pays out for
+    MinRedeemableLp {
+        ask_asset: AssetInfo,
+    },
 }
 
 // We define a custom struct for each query response
@@ -65,6 +260,241 @@ pub struct CountResponse {
     pub count: u8,
 }
 
+/// Public view of a basket
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BasketResponse {
+    /// Name of Basket
+    pub name: String,
+    /// Assets with their current prices
+    pub assets: Vec<BasketAssetResponse>,
+    /// fee for non-stable asset perp
+    pub tax_basis_points: Uint128,
+    /// fee for stable asset perp
+    pub stable_tax_basis_points: Uint128,
+    /// base fee for mint/burning lp token
+    pub mint_burn_basis_points: Uint128,
+    /// base fee for swap
+    pub swap_fee_basis_points: Uint128,
+    /// base fee for swaping between stable assets
+    pub stable_swap_fee_basis_points: Uint128,
+    /// references position fees, not for funding rate, nor for getting in/out of a position
+    pub margin_fee_basis_points: Uint128,
+    /// fee for getting liquidated, goes to liquidator in USD
+    pub liquidation_fee_usd: Uint128,
+    /// LP token address
+    pub lp_token_address: Addr,
+}
+
+/// Public view of a basket asset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BasketAssetResponse {
+    /// AssetInfo
+    pub info: AssetInfo,
+    /// The weight of this token in the LP
+    pub token_weight: Uint128,
+    /// Unoccupied + occupied amount of the asset in the pool
+    pub available_reserves: Uint128,
+    /// Amount of the asset reserved by positions
+    pub occupied_reserves: Uint128,
+    /// Amount of the asset the pool owns from fees
+    pub fee_reserves: Uint128,
+    /// Current price of one whole token, as valued in the AUM
+    pub price: Price,
+    /// Oracle `price` came from
+    pub oracle_source: OracleSource,
+}
+
+/// Distance of every basket asset from its target weight
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetImbalancesResponse {
+    pub imbalances: Vec<AssetImbalance>,
+}
+
+/// Distance of a basket asset from its target weight, with USD values at `USD_VALUE_PRECISION`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetImbalance {
+    pub info: AssetInfo,
+    /// AUM * weight / total weights
+    pub target_usd_value: Uint128,
+    /// Value of the asset's available and occupied reserves
+    pub current_usd_value: Uint128,
+    /// current_usd_value - target_usd_value, positive when the asset is overweight
+    pub imbalance_usd_value: i64,
+}
+
+/// Current oracle price of a basket asset, worth `price * 10^expo` USD give or take `conf * 10^expo`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetPriceResponse {
+    pub price: i64,
+    pub expo: i32,
+    pub conf: u64,
+}
+
+/// Assets under management, worth `aum` USD give or take `conf`, both at `USD_VALUE_PRECISION`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AumResponse {
+    pub aum: Uint128,
+    pub conf: Uint128,
+}
+
+/// Value of the basket's LP token, all USD values at `USD_VALUE_PRECISION`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LpPriceResponse {
+    /// Value of one whole LP token, i.e. 10^lp_decimals units. Without LP supply, the price the
+    /// first deposit mints at
+    pub price: Uint128,
+    pub aum: Uint128,
+    pub lp_supply: Uint128,
+}
+
+/// Configuration of the basket contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    /// Version stored by cw2 at instantiation or the latest migration
+    pub contract_version: String,
+    pub admin: Addr,
+    pub lp_token_address: Addr,
+    /// Exponent of every USD value the contract reports, i.e. `USD_VALUE_PRECISION`
+    pub usd_value_precision: i32,
+    pub paused: bool,
+}
+
+/// Fee a hypothetical trade of one asset would be charged
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeePreviewResponse {
+    /// USD value of the previewed amount, in units of USD_VALUE_PRECISION
+    pub value: Uint128,
+    pub fee_bps: Uint128,
+}
+
+/// Reserve breakdown of every basket asset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReservesResponse {
+    pub assets: Vec<AssetReserves>,
+}
+
+/// Reserve breakdown of a basket asset, amounts in the asset's own units
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetReserves {
+    pub info: AssetInfo,
+    pub available_reserves: Uint128,
+    pub occupied_reserves: Uint128,
+    pub fee_reserves: Uint128,
+    pub max_asset_amount: Uint128,
+    /// occupied_reserves / available_reserves, the utilization fees and borrow rates are charged on
+    pub utilization: Decimal,
+}
+
+/// Aggregate short interest of the basket
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GlobalShortsResponse {
+    pub assets: Vec<AssetShorts>,
+    /// Sum of every asset's short_notional_usd
+    pub total_short_notional_usd: Uint128,
+}
+
+/// Short interest in a basket asset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetShorts {
+    pub info: AssetInfo,
+    /// Open short size, in the asset's own units
+    pub global_short_size: Uint128,
+    /// Value of global_short_size at the current oracle price, at `USD_VALUE_PRECISION`
+    pub short_notional_usd: Uint128,
+}
+
+/// Terra tax on a transfer sent by the contract, zero for CW20 assets
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EstimateTaxResponse {
+    pub tax_amount: Uint128,
+}
+
+/// Gain or loss on the LP a user minted, with USD values at `USD_VALUE_PRECISION`. LP received by
+/// transfer is not included
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LpPnlResponse {
+    /// LP minted by the user's deposits and not yet withdrawn
+    pub lp_amount: Uint128,
+    /// USD deposited for `lp_amount`
+    pub cost_basis_usd: Uint128,
+    /// Current value of `lp_amount`
+    pub current_value_usd: Uint128,
+    /// current_value_usd - cost_basis_usd, positive on a gain
+    pub pnl_usd: i64,
+}
+
+/// Assets a proportional withdrawal would pay out, net of the mint/burn fee
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LpToAssetsResponse {
+    pub assets: Vec<Asset>,
+}
+
+/// Smallest withdrawal of an asset that does not round down to nothing
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MinRedeemableLpResponse {
+    /// Fewest LP tokens that redeem at least one unit of the asset, net of fees
+    pub lp_amount: Uint128,
+}
+
+/// Current borrow rates of every basket asset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BorrowRatesResponse {
+    pub assets: Vec<AssetBorrowRate>,
+}
+
+/// Current borrow rate of a basket asset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetBorrowRate {
+    pub info: AssetInfo,
+    /// Annual rate charged on occupied reserves, in basis points
+    pub borrow_rate_bps: Uint128,
+}
+
+/// Lifetime and current basket statistics, with USD values at `USD_VALUE_PRECISION`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StatsResponse {
+    /// USD value offered in all swaps so far
+    pub cumulative_swap_volume_usd: Uint128,
+    /// Value of all assets under management
+    pub aum: Uint128,
+    /// Outstanding LP tokens
+    pub lp_supply: Uint128,
+}
+
+/// Result of simulating a swap with an exact offer amount
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulationResponse {
+    /// Amount of ask asset the offer would return, net of fees
+    pub return_amount: Uint128,
+    /// Fee charged on the offer side of the swap
+    pub offer_fee_bps: Uint128,
+    /// Fee charged on the ask side of the swap
+    pub ask_fee_bps: Uint128,
+}
+
+/// Marginal exchange rate between two basket assets
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SpotPriceResponse {
+    /// Units of the ask asset received per unit of the offer asset, in each token's smallest
+    /// denomination, by a vanishingly small swap net of fees
+    pub price: Decimal,
+    /// Fee charged on the offer side of the swap
+    pub offer_fee_bps: Uint128,
+    /// Fee charged on the ask side of the swap
+    pub ask_fee_bps: Uint128,
+}
+
+/// Result of simulating a swap with an exact ask amount
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReverseSimulationResponse {
+    /// Amount of offer asset required to receive the ask amount, rounded up
+    pub offer_amount: Uint128,
+    /// Fee charged on the offer side of the swap
+    pub offer_fee_bps: Uint128,
+    /// Fee charged on the ask side of the swap
+    pub ask_fee_bps: Uint128,
+}
+
 #[derive(PartialEq, Clone, Default)]
 pub struct MsgInstantiateContractResponse {
     // message fields
@@ -297,6 +727,20 @@ pub struct InstantiateAssetInfo {
     pub backup_oracle: OracleInterface,
     /// Pyth address information for the asset
     pub ticker_data: TickerData,
+    /// If the asset can be swapped, enabled unless specified
+    #[serde(default = "default_swap_enabled")]
+    pub swap_enabled: bool,
+    /// If AUM and fees should use the oracle's EMA price instead of the spot price, off unless specified
+    #[serde(default)]
+    pub use_ema_price: bool,
+    /// If the asset is a CW20 that may deliver less than the amount transferred, off unless specified.
+    /// Deposits of it are credited with the balance actually received
+    #[serde(default)]
+    pub fee_on_transfer: bool,
+}
+
+fn default_swap_enabled() -> bool {
+    true
 }
 
 /// This structure describes the parameters used for a message
@@ -327,7 +771,48 @@ pub enum Cw20HookMsg {
         max_spread: Option<Decimal>,
         to: Option<String>,
         ask_asset: AssetInfo,
+        /// Fail if executed after this unix timestamp, in seconds
+        deadline: Option<u64>,
     },
     /// Withdraw liquidity from the pool
-    WithdrawLiquidity { asset: AssetInfo },
+    WithdrawLiquidity {
+        asset: AssetInfo,
+        /// Fail if less than this amount of `asset` would be returned
+        min_out: Option<Uint128>,
+        /// Fail if executed after this unix timestamp, in seconds
+        deadline: Option<u64>,
+        /// Address to send the redeemed asset to, defaults to the sender
+        receiver: Option<String>,
+    },
+    /// Withdraw liquidity from the pool as a slice of every basket asset
+    WithdrawLiquidityProportional {
+        /// Fail if executed after this unix timestamp, in seconds
+        deadline: Option<u64>,
+    },
+    /// Withdraw `target_usd` worth of `ask_asset` after fees, in units of USD_VALUE_PRECISION.
+    /// Only the LP needed is burned, the rest is sent back
+    WithdrawUsdValue {
+        target_usd: Uint128,
+        ask_asset: AssetInfo,
+        /// Fail if less than this amount of `ask_asset` would be returned
+        min_out: Option<Uint128>,
+        /// Fail if executed after this unix timestamp, in seconds
+        deadline: Option<u64>,
+    },
+}
+
+/// Messages the pyth contract accepts to post new prices
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PythExecuteMsg {
+    /// Verify the given price update VAAs and store their prices
+    UpdatePriceFeeds { data: Vec<Binary> },
+}
+
+/// Messages the generator contract accepts through a CW20 `Send` of LP tokens
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GeneratorHookMsg {
+    /// Stake the sent LP tokens on behalf of the given address
+    DepositFor(Addr),
 }
@@ -1,4 +1,4 @@
-use cosmwasm_std::Uint128;
+use cosmwasm_std::{Decimal, Uint128};
 use crate::error::ContractError;
 
 #[derive(Copy, Clone, Debug)]
@@ -22,4 +22,61 @@ impl PythPrice {
     
         Ok(Uint128::new(self.pyth_price.price as u128))
     }
+
+    // Confidence interval around the price, in the same units as `to_Uint128`. For an AUM this is
+    // the aggregate uncertainty of all the asset prices
+    #[allow(non_snake_case)]
+    pub fn conf_to_Uint128(&self, expected_expo: i32) -> Result<Uint128, ContractError> {
+        // Check for expected expo
+        if self.pyth_price.expo != expected_expo { return Err(ContractError::IncorrectDecimals { expo: self.pyth_price.expo, expected_expo }) }
+
+        Ok(Uint128::new(self.pyth_price.conf as u128))
+    }
+
+    // Price as a decimal number, `price * 10^expo`, keeping the fraction `to_Uint128` truncates.
+    // Digits past Decimal's 18 fractional places are floored
+    #[allow(non_snake_case)]
+    pub fn to_Decimal(&self) -> Result<Decimal, ContractError> {
+        // Check for positive price
+        if self.pyth_price.price < 0 { return Err(ContractError::NegativePrice) }
+
+        let price = Uint128::new(self.pyth_price.price as u128);
+        let scale = Uint128::new(
+            10_u128.checked_pow(self.pyth_price.expo.unsigned_abs()).ok_or(ContractError::FailedCast)?,
+        );
+        if self.pyth_price.expo < 0 {
+            return Ok(Decimal::from_ratio(price, scale));
+        }
+
+        // Decimal stores its value times 10^18 in a Uint128
+        let value = price.checked_mul(scale)?;
+        if value > Uint128::MAX / Uint128::new(1_000_000_000_000_000_000) {
+            return Err(ContractError::FailedCast);
+        }
+        Ok(Decimal::from_ratio(value, 1_u128))
+    }
+
+    // Product of two prices, e.g. token/USD times USD/quote for token/quote, scaled to `result_expo`
+    pub fn mul(&self, other: &PythPrice, result_expo: i32) -> Result<PythPrice, ContractError> {
+        self.pyth_price
+            .mul(&other.pyth_price)
+            .and_then(|price| price.scale_to_exponent(result_expo))
+            .map(PythPrice::new)
+            .ok_or(ContractError::PriceArithmeticFailed)
+    }
+
+    // Quotient of two prices, e.g. offer/USD over ask/USD for offer/ask, scaled to `result_expo`
+    pub fn div(&self, other: &PythPrice, result_expo: i32) -> Result<PythPrice, ContractError> {
+        self.pyth_price
+            .div(&other.pyth_price)
+            .and_then(|price| price.scale_to_exponent(result_expo))
+            .map(PythPrice::new)
+            .ok_or(ContractError::PriceArithmeticFailed)
+    }
+
+    // Inverse price, e.g. USD/token for a token/USD price, in the same exponent
+    pub fn invert(&self) -> Result<PythPrice, ContractError> {
+        let one = PythPrice::new(pyth_sdk_terra::Price { price: 1, conf: 0, expo: 0 });
+        one.div(self, self.pyth_price.expo)
+    }
 }
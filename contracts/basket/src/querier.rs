@@ -2,7 +2,7 @@ use crate::asset::AssetInfo;
 use crate::error::ContractError;
 use cosmwasm_std::{to_binary, Addr, QuerierWrapper, QueryRequest, Uint128, WasmQuery};
 
-use cw20::{Cw20QueryMsg, TokenInfoResponse};
+use cw20::{BalanceResponse, Cw20QueryMsg, TokenInfoResponse};
 
 // It's defined at https://github.com/terra-money/core/blob/d8e277626e74f9d6417dcd598574686882f0274c/types/assets/assets.go#L15
 const NATIVE_TOKEN_PRECISION: u8 = 6;
@@ -21,6 +21,26 @@ pub fn query_supply(querier: &QuerierWrapper, contract_addr: Addr) -> Result<Uin
     Ok(res.total_supply)
 }
 
+/// Returns the balance of a specific token held by an account.
+/// ## Params
+/// * **querier** is an object of type [`QuerierWrapper`].
+///
+/// * **contract_addr** is an object of type [`Addr`] which is the token contract address.
+///
+/// * **account_addr** is an object of type [`Addr`] which is the account holding the tokens.
+pub fn query_token_balance(
+    querier: &QuerierWrapper,
+    contract_addr: Addr,
+    account_addr: Addr,
+) -> Result<Uint128, ContractError> {
+    let res: BalanceResponse = querier.query_wasm_smart(
+        contract_addr,
+        &Cw20QueryMsg::Balance { address: account_addr.to_string() },
+    )?;
+
+    Ok(res.balance)
+}
+
 /// Returns the number of decimals that a token has.
 /// ## Params
 /// * **querier** is an object of type [`QuerierWrapper`].
@@ -3,16 +3,18 @@ use serde::{Deserialize, Serialize};
 
 use crate::asset::{Asset, AssetInfo, safe_u128_to_i64};
 use crate::price::PythPrice;
-use crate::contract::USD_VALUE_PRECISION;
+use crate::contract::{BASIS_POINTS_PRECISION, LP_DECIMALS, USD_VALUE_PRECISION};
 use crate::error::ContractError;
-use crate::msg::{InstantiateAssetInfo, InstantiateMsg};
+use crate::msg::{InstantiateAssetInfo, InstantiateMsg, SwapParams};
 use crate::querier::{query_supply, query_token_precision};
-use cosmwasm_std::{Addr, QuerierWrapper, StdResult, Uint128};
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Api, Coin, QuerierWrapper, StdResult, Timestamp, Uint128};
+use cw_storage_plus::{Item, Map};
 use phf::phf_map;
+use std::fmt;
 use pyth_sdk_terra::{query_price_feed, Price, PriceFeed, PriceIdentifier, PriceStatus};
 
-/// Basket of assets
+/// Basket of assets. Fields added after the first release default to their behaviour before
+/// they existed, so that baskets stored by earlier versions still load
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Basket {
     /// Assets
@@ -39,6 +41,87 @@ pub struct Basket {
     pub admin: Addr,
     /// LP token address
     pub lp_token_address: Addr,
+    /// set once the LP token's instantiation reply has been handled
+    #[serde(default = "default_true")]
+    pub lp_token_initialized: bool,
+    /// halts deposits, withdrawals and swaps while set
+    #[serde(default)]
+    pub paused: bool,
+    /// sum of all asset weights, kept in sync whenever assets or weights change
+    #[serde(default)]
+    pub total_weights: Uint128,
+    /// max deviation between an asset's primary and backup oracle prices, zero disables the check
+    #[serde(default)]
+    pub max_oracle_deviation_bps: Uint128,
+    /// lifetime USD value offered in swaps, in units of USD_VALUE_PRECISION
+    #[serde(default)]
+    pub cumulative_swap_volume_usd: Uint128,
+    /// generator contract LP tokens are staked in for deposits with auto_stake
+    #[serde(default)]
+    pub generator_address: Option<Addr>,
+    /// smallest USD value a deposit may add, in units of USD_VALUE_PRECISION, zero disables the check.
+    /// The first deposit into an empty basket is held to the same floor
+    #[serde(default)]
+    pub min_deposit_value_usd: Uint128,
+    /// max deviation of a stable asset's price from 1 USD before swaps into or out of it are
+    /// blocked, zero disables the check
+    #[serde(default)]
+    pub stable_peg_tolerance_bps: Uint128,
+    /// pyth contract that UpdatePricesThenSwap posts price updates to
+    #[serde(default)]
+    pub pyth_contract_address: Option<Addr>,
+    /// reference asset AUM and LP value are denominated in, USD when unset. All `usd` values,
+    /// fees and limits of the basket are then in units of the quote
+    #[serde(default)]
+    pub quote: Option<Quote>,
+    /// most LP tokens that may be outstanding, deposits minting past it are rejected. Unset
+    /// leaves the supply uncapped
+    #[serde(default)]
+    pub max_lp_supply: Option<Uint128>,
+    /// decimals of the LP token. The first deposit mints 10^lp_decimals LP per USD
+    #[serde(default = "default_lp_decimals")]
+    pub lp_decimals: u8,
+    /// fee added for assets with reserves occupied by positions
+    #[serde(default)]
+    pub utilization_fee_basis_points: Uint128,
+    /// fee added per 100% utilization (occupied / available reserves), on top of the base
+    /// utilization fee
+    #[serde(default)]
+    pub utilization_fee_slope_basis_points: Uint128,
+    /// annual borrow rate charged on occupied reserves
+    #[serde(default)]
+    pub borrow_rate_basis_points: Uint128,
+    /// annual borrow rate added per 100% utilization, on top of the base rate
+    #[serde(default)]
+    pub borrow_rate_slope_basis_points: Uint128,
+    /// block time, in seconds, borrow fees have been accrued up to
+    #[serde(default)]
+    pub last_borrow_accrual_time: u64,
+    /// AUM set by the admin during an oracle outage, in units of USD_VALUE_PRECISION. While set,
+    /// it is used in place of the AUM priced from the oracles
+    #[serde(default)]
+    pub frozen_aum: Option<Uint128>,
+    /// USD value, in units of USD_VALUE_PRECISION, from which a swap counts as large. Unset
+    /// leaves every swap unrestricted
+    #[serde(default)]
+    pub large_swap_threshold_usd: Option<Uint128>,
+    /// seconds an address must wait after a large swap before swapping large again
+    #[serde(default)]
+    pub swap_cooldown_seconds: u64,
+    /// seconds after its publish time from which a Pyth price is rejected as stale. Unset
+    /// accepts prices of any age
+    #[serde(default)]
+    pub max_price_age: Option<u64>,
+}
+
+/// Baskets and assets stored before a flag existed behaved as if it was set
+fn default_true() -> bool {
+    true
+}
+
+/// Baskets stored before `lp_decimals` existed minted with the default LP decimals
+fn default_lp_decimals() -> u8 {
+    LP_DECIMALS
 }
 
 /// Represents whitelisted assets on the dex
@@ -92,6 +175,34 @@ pub struct BasketAsset {
 
     /// Pyth Oracle Data regarding the basket asset
     pub ticker_data: TickerData,
+
+    /// Flag for whether this asset can currently be swapped
+    #[serde(default = "default_true")]
+    pub swap_enabled: bool,
+
+    /// Flag for whether AUM and fee calculations price this asset with the oracle's EMA price
+    #[serde(default)]
+    pub use_ema_price: bool,
+
+    /// Flag for whether this CW20 may deliver less than the amount transferred, so that deposits
+    /// are credited with the balance actually received
+    #[serde(default)]
+    pub fee_on_transfer: bool,
+
+    /// Decimals of the token, stored when the asset is added. Unset for assets stored before
+    /// decimals were, until `migrate` fills them in
+    #[serde(default)]
+    pub decimals: Option<u8>,
+}
+
+impl fmt::Display for BasketAsset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (weight {}, available {}, occupied {}, fees {})",
+            self.info, self.token_weight, self.available_reserves, self.occupied_reserves, self.fee_reserves
+        )
+    }
 }
 
 impl BasketAsset {
@@ -140,43 +251,233 @@ impl BasketAsset {
             available_reserves,
             /// Pyth Oracle Data regarding the basket asset
             ticker_data: asset_info.ticker_data,
+            // Flag for whether this asset can currently be swapped
+            swap_enabled: asset_info.swap_enabled,
+            // Flag for whether AUM and fee calculations price this asset with the oracle's EMA price
+            use_ema_price: asset_info.use_ema_price,
+            // Flag for whether deposits of this CW20 are credited with the balance actually received
+            fee_on_transfer: asset_info.fee_on_transfer,
+            // Queried by whoever adds the asset to a basket
+            decimals: None,
         }
     }
+
+    /// Price used to value this asset's reserves for AUM and fee calculations: the oracle's EMA
+    /// price if `use_ema_price` is set, the spot price otherwise. Swap execution always uses spot.
+    /// Falls back to the backup oracle when the primary one fails. When both are available and
+    /// `max_oracle_deviation_bps` is nonzero, prices further apart than that are rejected rather
+    /// than picking one.
+    pub fn get_valuation_price(
+        &self,
+        querier: &QuerierWrapper,
+        max_oracle_deviation_bps: Uint128,
+        price_cache: &mut PriceCache,
+    ) -> Result<Price, ContractError> {
+        Ok(self.get_valuation_price_with_source(querier, max_oracle_deviation_bps, price_cache)?.0)
+    }
+
+    /// Same as `get_valuation_price`, also returning which oracle the price came from
+    pub fn get_valuation_price_with_source(
+        &self,
+        querier: &QuerierWrapper,
+        max_oracle_deviation_bps: Uint128,
+        price_cache: &mut PriceCache,
+    ) -> Result<(Price, OracleSource), ContractError> {
+        let source = |oracle: &OracleInterface, fallback: OracleSource| match oracle {
+            OracleInterface::Manual { .. } => OracleSource::Manual,
+            _ if self.use_ema_price => OracleSource::Ema,
+            _ => fallback,
+        };
+        let (now, max_price_age) = (price_cache.now, price_cache.max_price_age);
+        let query = |oracle: &OracleInterface| {
+            if self.use_ema_price {
+                oracle.get_ema_price(querier, now, max_price_age)
+            } else {
+                oracle.get_price(querier, now, max_price_age)
+            }
+        };
+
+        // The primary spot price is the one swaps and deposits value assets with, so it is shared
+        // through the cache
+        let primary = if self.use_ema_price {
+            query(&self.oracle)
+        } else {
+            price_cache.get_price(querier, self)
+        };
+
+        let price = match primary {
+            Ok(price) if max_oracle_deviation_bps.is_zero() => price,
+            Ok(price) => match query(&self.backup_oracle) {
+                Ok(backup_price) => {
+                    check_oracle_deviation(price, backup_price, max_oracle_deviation_bps)?;
+                    price
+                }
+                Err(_) => price,
+            },
+            Err(_) => {
+                let price = query(&self.backup_oracle)?;
+                return Ok((price, source(&self.backup_oracle, OracleSource::Backup)));
+            }
+        };
+        Ok((price, source(&self.oracle, OracleSource::Primary)))
+    }
+}
+
+/// Oracle an asset's valuation price came from. Manual oracles are reported as `Manual` and EMA
+/// prices as `Ema`, whether they are the primary or backup oracle
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleSource {
+    Primary,
+    Backup,
+    Ema,
+    Manual,
+}
+
+/// Reference asset a basket is priced in, see `Basket::quote`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Quote {
+    pub info: AssetInfo,
+    /// USD price of the quote asset
+    pub oracle: OracleInterface,
+}
+
+/// Spot prices of each asset's primary oracle and token decimals, keyed by asset. One cache lives
+/// for a single execute call or query, so that AUM and [`crate::asset::PricedAsset`] valuations
+/// query each feed and token once. The default cache prices in USD and accepts prices of any
+/// age, `PriceCache::new` prices in the basket's quote and rejects prices older than its
+/// `max_price_age` at the block time
+#[derive(Clone, Debug, Default)]
+pub struct PriceCache {
+    prices: Vec<(AssetInfo, Price)>,
+    decimals: Vec<(AssetInfo, u8)>,
+    quote: Option<Quote>,
+    quote_price: Option<Price>,
+    now: u64,
+    max_price_age: Option<u64>,
+}
+
+impl PriceCache {
+    pub fn new(basket: &Basket, block_time: Timestamp) -> Self {
+        PriceCache {
+            quote: basket.quote.clone(),
+            now: block_time.seconds(),
+            max_price_age: basket.max_price_age,
+            ..PriceCache::default()
+        }
+    }
+
+    /// Converts a USD `price` into units of the quote, querying the quote price on first use. The
+    /// exponent of `price` is kept. Prices are returned unchanged when pricing in USD
+    pub fn to_quote(&mut self, querier: &QuerierWrapper, price: Price) -> Result<Price, ContractError> {
+        let quote = match &self.quote {
+            Some(quote) => quote,
+            None => return Ok(price),
+        };
+        let quote_price = match self.quote_price {
+            Some(quote_price) => quote_price,
+            None => {
+                let quote_price = quote.oracle.get_price(querier, self.now, self.max_price_age)?;
+                if quote_price.price < 0 {
+                    return Err(ContractError::NegativePrice);
+                }
+                if quote_price.price == 0 {
+                    return Err(ContractError::ZeroPrice);
+                }
+                self.quote_price = Some(quote_price);
+                quote_price
+            }
+        };
+        price
+            .get_price_in_quote(&quote_price, price.expo)
+            .ok_or(ContractError::OracleQueryFailed)
+    }
+
+    /// Returns the current price of `asset`'s primary oracle, querying it on first use
+    pub fn get_price(&mut self, querier: &QuerierWrapper, asset: &BasketAsset) -> Result<Price, ContractError> {
+        if let Some((_, price)) = self.prices.iter().find(|(info, _)| info.equal(&asset.info)) {
+            return Ok(*price);
+        }
+        let price = asset.oracle.get_price(querier, self.now, self.max_price_age)?;
+        self.prices.push((asset.info.clone(), price));
+        Ok(price)
+    }
+
+    /// Returns the decimals of `asset`, as stored on it or else by querying the token on first use
+    pub fn get_decimals(&mut self, querier: &QuerierWrapper, asset: &BasketAsset) -> Result<u8, ContractError> {
+        if let Some(decimals) = asset.decimals {
+            return Ok(decimals);
+        }
+        if let Some((_, decimals)) = self.decimals.iter().find(|(info, _)| info.equal(&asset.info)) {
+            return Ok(*decimals);
+        }
+        let decimals = query_token_precision(querier, &asset.info)?;
+        self.decimals.push((asset.info.clone(), decimals));
+        Ok(decimals)
+    }
+}
+
+/// Errors with `OracleDeviationTooHigh` if `backup` differs from `primary` by more than
+/// `max_deviation_bps` basis points of `primary`
+fn check_oracle_deviation(primary: Price, backup: Price, max_deviation_bps: Uint128) -> Result<(), ContractError> {
+    if price_deviation_exceeds(primary, backup, max_deviation_bps)? {
+        return Err(ContractError::OracleDeviationTooHigh);
+    }
+    Ok(())
+}
+
+/// Whether `price` differs from `reference` by more than `max_deviation_bps` basis points of
+/// `reference`. Prices that cannot be brought to the exponent of `reference` count as deviating
+pub(crate) fn price_deviation_exceeds(
+    reference: Price,
+    price: Price,
+    max_deviation_bps: Uint128,
+) -> Result<bool, ContractError> {
+    let price = match price.scale_to_exponent(reference.expo) {
+        Some(price) => price,
+        None => return Ok(true),
+    };
+    let deviation = Uint128::from((reference.price as i128 - price.price as i128).unsigned_abs());
+    Ok(deviation.checked_mul(BASIS_POINTS_PRECISION)?
+        > max_deviation_bps.checked_mul(Uint128::from(reference.price.unsigned_abs()))?)
 }
 
 pub trait ToAssetInfo {
-    fn to_asset_info(&self) -> Vec<AssetInfo>;
+    /// Maps every element onto the info of the matching asset in `basket_assets`
+    fn to_asset_info(&self, basket_assets: &[BasketAsset]) -> Result<Vec<AssetInfo>, ContractError>;
 }
 
 impl ToAssetInfo for Vec<BasketAsset> {
-    fn to_asset_info(&self) -> Vec<AssetInfo> {
+    fn to_asset_info(&self, basket_assets: &[BasketAsset]) -> Result<Vec<AssetInfo>, ContractError> {
         let mut v: Vec<AssetInfo> = vec![];
         for asset in self.iter() {
             v.push(
-                self.iter()
+                basket_assets
+                    .iter()
                     .find(|basket_asset| basket_asset.info.equal(&asset.info))
-                    .expect("an asset was not found in the basket")
+                    .ok_or(ContractError::AssetNotInBasket)?
                     .info
                     .clone(),
             )
         }
-        v
+        Ok(v)
     }
 }
 
 impl ToAssetInfo for Vec<Asset> {
-    fn to_asset_info(&self) -> Vec<AssetInfo> {
+    fn to_asset_info(&self, basket_assets: &[BasketAsset]) -> Result<Vec<AssetInfo>, ContractError> {
         let mut v: Vec<AssetInfo> = vec![];
         for asset in self.iter() {
             v.push(
-                self.iter()
+                basket_assets
+                    .iter()
                     .find(|basket_asset| basket_asset.info.equal(&asset.info))
-                    .expect("an asset was not found in the basket")
+                    .ok_or(ContractError::AssetNotInBasket)?
                     .info
                     .clone(),
             )
         }
-        v
+        Ok(v)
     }
 }
 
@@ -186,8 +487,15 @@ pub struct AumResult {
     pub exponent: i32,
 }
 
+impl fmt::Display for Basket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({} assets, total weight {})", self.name, self.assets.len(), self.total_weights)
+    }
+}
+
 impl Basket {
     pub fn new(assets: Vec<BasketAsset>, msg: &InstantiateMsg) -> Self {
+        let total_weights = assets.iter().map(|asset| asset.token_weight).sum();
         Basket {
             assets,
             name: msg.name.clone(),
@@ -201,59 +509,128 @@ impl Basket {
             min_profit_time: msg.min_profit_time,
             admin: msg.admin.clone(),
             lp_token_address: Addr::unchecked(""),
+            lp_token_initialized: false,
+            paused: false,
+            total_weights,
+            max_oracle_deviation_bps: msg.max_oracle_deviation_bps,
+            cumulative_swap_volume_usd: Uint128::zero(),
+            generator_address: msg.generator_address.clone(),
+            min_deposit_value_usd: msg.min_deposit_value_usd,
+            stable_peg_tolerance_bps: msg.stable_peg_tolerance_bps,
+            pyth_contract_address: msg.pyth_contract_address.clone(),
+            quote: msg.quote.clone(),
+            max_lp_supply: msg.max_lp_supply,
+            lp_decimals: msg.lp_decimals.unwrap_or(LP_DECIMALS),
+            utilization_fee_basis_points: msg.utilization_fee_basis_points.unwrap_or_default(),
+            utilization_fee_slope_basis_points: msg.utilization_fee_slope_basis_points.unwrap_or_default(),
+            borrow_rate_basis_points: msg.borrow_rate_basis_points.unwrap_or_default(),
+            borrow_rate_slope_basis_points: msg.borrow_rate_slope_basis_points.unwrap_or_default(),
+            last_borrow_accrual_time: 0,
+            frozen_aum: None,
+            large_swap_threshold_usd: None,
+            swap_cooldown_seconds: 0,
+            max_price_age: None,
         }
     }
 
     pub fn get_total_weights(&self) -> Uint128 {
-        let mut total_weights = Uint128::zero();
-        for asset in &self.assets {
-            total_weights += asset.token_weight;
-        }
-        total_weights
+        self.total_weights
     }
 
-    pub fn match_basket_assets(&self, asset_infos: &[AssetInfo]) -> Vec<BasketAsset> {
+    /// Recomputes the cached total weights. Must be called after adding or removing assets, or
+    /// changing their weights
+    pub fn update_total_weights(&mut self) {
+        self.total_weights = self.assets.iter().map(|asset| asset.token_weight).sum();
+    }
+
+    pub fn match_basket_assets(&self, asset_infos: &[AssetInfo]) -> Result<Vec<BasketAsset>, ContractError> {
         let mut v: Vec<BasketAsset> = vec![];
         for asset in asset_infos.iter() {
             v.push(
                 self.assets
                     .iter()
                     .find(|basket_asset| basket_asset.info.equal(asset))
-                    .expect("an asset was not found in the basket")
+                    .ok_or(ContractError::AssetNotInBasket)?
                     .clone(),
             )
         }
-        v
+        Ok(v)
     }
 
     // CHECK: that we should take the value of the token account as AUM and not the general reserves from the
     // available asset account
-    pub fn calculate_aum(&self, querier: &QuerierWrapper) -> Result<PythPrice, ContractError> {
+    pub fn calculate_aum(
+        &self,
+        querier: &QuerierWrapper,
+        price_cache: &mut PriceCache,
+    ) -> Result<PythPrice, ContractError> {
+        // A frozen AUM doesn't need the oracles at all
+        if let Some(frozen_aum) = self.frozen_aum_price()? {
+            return Ok(frozen_aum);
+        }
+        let prices = self.get_prices(querier, price_cache)?;
+        self.calculate_aum_with_prices(querier, &prices, price_cache)
+    }
+
+    /// Same as `calculate_aum`, using `prices` already fetched with `get_prices` so that a
+    /// single execute call only queries the oracles once. Token decimals come from `price_cache`
+    pub fn calculate_aum_with_prices(
+        &self,
+        querier: &QuerierWrapper,
+        prices: &[Price],
+        price_cache: &mut PriceCache,
+    ) -> Result<PythPrice, ContractError> {
+        if let Some(frozen_aum) = self.frozen_aum_price()? {
+            return Ok(frozen_aum);
+        }
+
         // Build amounts: input to price_basket
-        let tokens: Vec<(BasketAsset, Price)> = self
+        // Following pyth naming convention of amount, but does not make much sense.
+        // fee_reserves are left out: they belong to the protocol, not to LPs
+        let amounts: Vec<(Price, i64, i32)> = self
             .assets
             .iter()
-            .cloned()
-            .zip(self.get_prices(querier)?)
-            .collect();
-        // Following pyth naming convention of amount, but does not make much sense
-        let amounts: &[(Price, i64, i32)] = &tokens
-            .iter()
+            .zip(prices.iter().copied())
             .map(|(basket_asset, price)| {
-                (
-                    *price,
+                Ok((
+                    price,
                     safe_u128_to_i64(
-                        basket_asset.occupied_reserves.u128()
-                            + basket_asset.available_reserves.u128(),
-                    )
-                    .unwrap(),
-                    -(query_token_precision(querier, &basket_asset.info).unwrap() as i32),
-                )
+                        basket_asset
+                            .occupied_reserves
+                            .checked_add(basket_asset.available_reserves)?
+                            .u128(),
+                    )?,
+                    -(price_cache.get_decimals(querier, basket_asset)? as i32),
+                ))
             })
-            .collect::<Vec<(Price, i64, i32)>>();
+            .collect::<Result<Vec<(Price, i64, i32)>, ContractError>>()?;
 
         // Construct aum Price result
-        Ok(PythPrice::new(Price::price_basket(amounts, USD_VALUE_PRECISION).expect("Failed to price the basket of assets under management (calculate_aum)")))
+        Ok(PythPrice::new(
+            Price::price_basket(&amounts, USD_VALUE_PRECISION).ok_or(ContractError::OracleQueryFailed)?,
+        ))
+    }
+
+    /// Returns `AumFrozen` while the admin has frozen the AUM. Anything priced off the oracles,
+    /// or off the AUM against live prices, is held off until it is unfrozen
+    pub fn assert_aum_live(&self) -> Result<(), ContractError> {
+        match self.frozen_aum {
+            Some(_) => Err(ContractError::AumFrozen),
+            None => Ok(()),
+        }
+    }
+
+    /// The frozen AUM as a price in units of USD_VALUE_PRECISION, if the admin has frozen it
+    fn frozen_aum_price(&self) -> Result<Option<PythPrice>, ContractError> {
+        self.frozen_aum
+            .map(|value| {
+                Ok(PythPrice::new(Price {
+                    price: safe_u128_to_i64(value.u128())?,
+                    conf: 0,
+                    expo: USD_VALUE_PRECISION,
+                }))
+            })
+            .transpose()
     }
 
     /// Calculates total number of lp tokens
@@ -267,9 +644,10 @@ impl Basket {
         &self,
         lp_amount: Uint128,
         querier: &QuerierWrapper,
+        price_cache: &mut PriceCache,
     ) -> Result<Uint128, ContractError> {
         // Calculate aum in USD, in units of USD_VALUE_PRECISION
-        let aum_value: Uint128 = self.calculate_aum(querier)?.to_Uint128(USD_VALUE_PRECISION)?;
+        let aum_value: Uint128 = self.calculate_aum(querier, price_cache)?.to_Uint128(USD_VALUE_PRECISION)?;
 
         // Calculate value of lp_amount lp tokens in USD, in units of USD_VALUE_PRECISION
         let redeem_value: Uint128 =
@@ -283,19 +661,56 @@ impl Basket {
         &self,
         querier: &QuerierWrapper,
     ) -> Result<Vec<PriceFeed>, ContractError> {
-        let mut v = vec![];
-        for asset in &self.assets {
-            v.push(asset.oracle.get_price_feed(querier)?);
+        let mut v: Vec<PriceFeed> = vec![];
+        for (i, asset) in self.assets.iter().enumerate() {
+            // Assets sharing an oracle only query it once
+            let price_feed = match self.assets[..i].iter().position(|other| other.oracle == asset.oracle) {
+                Some(j) => v[j],
+                None => asset.oracle.get_price_feed(querier)?,
+            };
+            v.push(price_feed);
         }
 
         Ok(v)
     }
 
     // This uses `get_price_feeds` and goes a step further to unwrap `Price`s.
-    pub fn get_prices(&self, querier: &QuerierWrapper) -> Result<Vec<Price>, ContractError> {
-        let mut v = vec![];
-        for asset in &self.assets {
-            v.push(asset.oracle.get_price(querier)?);
+    // Each asset is priced with its valuation price, see `BasketAsset::get_valuation_price`,
+    // in units of the basket's quote.
+    pub fn get_prices(
+        &self,
+        querier: &QuerierWrapper,
+        price_cache: &mut PriceCache,
+    ) -> Result<Vec<Price>, ContractError> {
+        Ok(self
+            .get_prices_with_sources(querier, price_cache)?
+            .into_iter()
+            .map(|(price, _)| price)
+            .collect())
+    }
+
+    /// Same as `get_prices`, also returning which oracle priced each asset
+    pub fn get_prices_with_sources(
+        &self,
+        querier: &QuerierWrapper,
+        price_cache: &mut PriceCache,
+    ) -> Result<Vec<(Price, OracleSource)>, ContractError> {
+        let mut v: Vec<(Price, OracleSource)> = vec![];
+        for (i, asset) in self.assets.iter().enumerate() {
+            // Assets sharing oracles and price mode only query them once
+            let price = match self.assets[..i].iter().position(|other| {
+                other.oracle == asset.oracle
+                    && other.backup_oracle == asset.backup_oracle
+                    && other.use_ema_price == asset.use_ema_price
+            }) {
+                Some(j) => v[j],
+                None => {
+                    let (price, source) =
+                        asset.get_valuation_price_with_source(querier, self.max_oracle_deviation_bps, price_cache)?;
+                    (price_cache.to_quote(querier, price)?, source)
+                }
+            };
+            v.push(price);
         }
 
         Ok(v)
@@ -312,6 +727,12 @@ pub enum OracleInterface {
         price: i64,
         expo: i32,
     },
+    /// Emergency price kept up to date by the basket admin, for assets whose feed broke
+    Manual {
+        price: i64,
+        expo: i32,
+        set_by: Addr,
+    },
 }
 
 impl OracleInterface {
@@ -320,11 +741,29 @@ impl OracleInterface {
         Self::Pyth { addr, price_id }
     }
 
+    /// Same as `from_pyth`, parsing the price id from hex, with or without a `0x` prefix
+    pub fn from_pyth_hex(addr: Addr, hex: &str) -> Result<Self, ContractError> {
+        let price_id = PriceIdentifier::from_hex(hex.strip_prefix("0x").unwrap_or(hex))
+            .map_err(|_| ContractError::InvalidPriceId { hex: hex.to_string() })?;
+        Ok(Self::from_pyth(addr, price_id))
+    }
+
     /// Construct a dummy oracle that will yield the given price
     pub fn from_dummy(price: i64, expo: i32) -> Self {
         Self::Stub { price, expo }
     }
 
+    /// Returns `InvalidOracleConfig` if a Pyth oracle has an unset price id or an invalid
+    /// contract address. Stub and manual oracles need no configuration
+    pub fn check(&self, api: &dyn Api) -> Result<(), ContractError> {
+        if let Self::Pyth { addr, price_id } = self {
+            if *price_id == PriceIdentifier::new([0; 32]) || api.addr_validate(addr.as_str()).is_err() {
+                return Err(ContractError::InvalidOracleConfig);
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_price_feed(&self, querier: &QuerierWrapper) -> StdResult<PriceFeed> {
         match self {
             Self::Pyth { addr, price_id } => {
@@ -333,8 +772,8 @@ impl OracleInterface {
                 Ok(price_feed)
             }
 
-            // Create a dummy price feed wrapper for the stub price
-            Self::Stub { price, expo } => Ok(PriceFeed::new(
+            // Create a dummy price feed wrapper for the stub or manual price
+            Self::Stub { price, expo } | Self::Manual { price, expo, .. } => Ok(PriceFeed::new(
                 PriceIdentifier::new([0; 32]),
                 PriceStatus::Trading,
                 0,
@@ -353,31 +792,116 @@ impl OracleInterface {
         }
     }
 
-    /// This function currently is never used.
-    /// However it may make more sense to abstract out the usage of price_feeds with this,
-    /// so that users of Basket only ever have to work with Pyth Price structs instead of messing with PriceFeeds
-    pub fn get_price(&self, querier: &QuerierWrapper) -> Result<Price, ContractError> {
+    /// Returns the oracle's current price. A Pyth feed that is not trading fails with
+    /// `PriceNotTrading`, one published more than `max_age` seconds before `now` with `StalePrice`,
+    /// and one that cannot be queried with the querier's error. Stub and manual oracles return
+    /// their set price, which never goes stale
+    pub fn get_price(&self, querier: &QuerierWrapper, now: u64, max_age: Option<u64>) -> Result<Price, ContractError> {
         match self {
             Self::Pyth { addr, price_id } => {
                 let price_feed = query_price_feed(querier, addr.to_string(), *price_id)?.price_feed;
 
-                match price_feed.get_current_price() {
-                    Some(price) => Ok(price),
-                    None => Err(ContractError::OracleQueryFailed),
-                }
+                // The current price is only withheld while the feed is not trading
+                let price = match price_feed.get_current_price() {
+                    Some(price) => price,
+                    None => return Err(ContractError::PriceNotTrading),
+                };
+                check_price_age(&price_feed, now, max_age)?;
+                Ok(price)
             }
 
-            Self::Stub { price, expo } => Ok(Price {
+            Self::Stub { price, expo } | Self::Manual { price, expo, .. } => Ok(Price {
                 price: *price,
                 conf: 0,
                 expo: *expo,
             }),
         }
     }
+
+    /// Same as `get_price`, but yields the exponential moving average price, which is harder to
+    /// move with a single manipulated tick. Stub and manual oracles have no history, so their EMA is
+    /// their price.
+    pub fn get_ema_price(&self, querier: &QuerierWrapper, now: u64, max_age: Option<u64>) -> Result<Price, ContractError> {
+        match self {
+            Self::Pyth { addr, price_id } => {
+                let price_feed = query_price_feed(querier, addr.to_string(), *price_id)?.price_feed;
+
+                check_price_age(&price_feed, now, max_age)?;
+                match price_feed.get_ema_price() {
+                    Some(price) => Ok(price),
+                    None => Err(ContractError::OracleQueryFailed),
+                }
+            }
+
+            Self::Stub { .. } | Self::Manual { .. } => self.get_price(querier, now, max_age),
+        }
+    }
+}
+
+/// Errors with `StalePrice` if `price_feed` was published more than `max_age` seconds before `now`
+fn check_price_age(price_feed: &PriceFeed, now: u64, max_age: Option<u64>) -> Result<(), ContractError> {
+    if let Some(max_age) = max_age {
+        let age_seconds = now.saturating_sub(price_feed.publish_time.max(0) as u64);
+        if age_seconds > max_age {
+            return Err(ContractError::StalePrice { age_seconds, max_age });
+        }
+    }
+    Ok(())
 }
 
 pub const BASKET: Item<Basket> = Item::new("basket");
 
+/// A swap waiting on the price update posted ahead of it, see `ExecuteMsg::UpdatePricesThenSwap`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingSwap {
+    /// Caller of the original message and the funds it sent, which the swap is executed with
+    pub sender: Addr,
+    pub funds: Vec<Coin>,
+    pub swap: SwapParams,
+}
+
+pub const PENDING_SWAP: Item<PendingSwap> = Item::new("pending_swap");
+
+/// A deposit of fee-on-transfer tokens waiting on its TransferFroms, see `provide_liquidity`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingDeposit {
+    pub sender: Addr,
+    pub offer_assets: Vec<Asset>,
+    /// Contract balance of each fee-on-transfer token before the transfers
+    pub balances_before: Vec<(AssetInfo, Uint128)>,
+    pub min_lp_out: Option<Uint128>,
+    pub receiver: Option<String>,
+    pub auto_stake: bool,
+}
+
+pub const PENDING_DEPOSIT: Item<PendingDeposit> = Item::new("pending_deposit");
+
+/// USD deposited by an account and the LP its deposits minted, kept as the account's cost basis
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DepositInfo {
+    /// USD value deposited for the LP still held, in units of USD_VALUE_PRECISION
+    pub usd_deposited: Uint128,
+    /// LP minted by deposits and not yet withdrawn
+    pub lp_amount: Uint128,
+}
+
+/// The cost basis of each account's LP. Only deposits and withdrawals through the basket are
+/// seen: LP received by transfer has no cost basis, and LP sent away keeps its cost basis with
+/// the sender until they withdraw as much
+pub const DEPOSITS: Map<&Addr, DepositInfo> = Map::new("deposits");
+
+/// Block time, in seconds, of each address's last large swap, see
+/// [`Basket::large_swap_threshold_usd`]
+pub const LAST_LARGE_SWAP: Map<&Addr, u64> = Map::new("last_large_swap");
+
+/// Set while an operation waits on the reply of one of its submessages, i.e. a PENDING_SWAP or
+/// PENDING_DEPOSIT is outstanding. User actions are rejected meanwhile, so a token or oracle called
+/// by the submessage cannot act on the basket before the operation completes.
+///
+/// Plain messages are not covered: they run after their handler has saved its final state, so a
+/// handler must not rely on anything they do.
+pub const LOCK: Item<bool> = Item::new("lock");
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct TickerData {
     pub testnet_address: Addr,
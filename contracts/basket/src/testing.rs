@@ -1,11 +1,12 @@
 use crate::contract::{
-    calculate_fee_basis_points, execute, instantiate, query_basket, Action, LP_DECIMALS,
+    calculate_fee_basis_points, execute, instantiate, query, query_basket, reply, Action, BASIS_POINTS_PRECISION,
+    CONTRACT_VERSION, LP_DECIMALS,
 };
 use crate::error::ContractError;
 use crate::mock_querier::{mock_dependencies, WasmMockQuerier};
-use crate::state::OracleInterface;
+use crate::state::{OracleInterface, OracleSource, PriceCache};
 use crate::{
-    asset::{Asset, AssetInfo},
+    asset::{ceil_multiply_ratio, Asset, AssetInfo, PricedAsset},
     msg::*,
     state::{Basket, BasketAsset, TickerData},
 };
@@ -13,11 +14,12 @@ use crate::{
 use cosmwasm_std::{coins, Binary};
 use cosmwasm_std::testing::{mock_env, mock_info, MOCK_CONTRACT_ADDR, MockStorage, MockApi};
 use cosmwasm_std::{
-    OwnedDeps, attr, from_binary, to_binary, Addr, BalanceResponse, BankMsg, BankQuery, Coin, CosmosMsg,
-    QueryRequest, ReplyOn, StdError::GenericErr, SubMsg, Uint128, WasmMsg,
+    OwnedDeps, attr, from_binary, to_binary, Addr, BalanceResponse, BankMsg, BankQuery, Coin, ContractResult,
+    CosmosMsg, Decimal, QuerierWrapper, QueryRequest, Reply, ReplyOn, StdError::GenericErr, SubMsg,
+    SubMsgExecutionResponse, Uint128, WasmMsg,
 };
 use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse };
-use pyth_sdk_terra::PriceIdentifier;
+use pyth_sdk_terra::{Price, PriceFeed, PriceIdentifier, PriceStatus};
 
 const FAKE_LP_TOKEN_ADDRESS: &str = "lp-token-address";
 
@@ -42,6 +44,7 @@ fn instantiate_setup(sender: &str) -> OwnedDeps<MockStorage, MockApi, WasmMockQu
         info: luna_info.clone(),
         address: Addr::unchecked("luna_addr"),
         oracle: OracleInterface::from_dummy(100_000_000, -6),
+        is_asset_stable: false,
         ..create_instantiate_asset_info()
     });
     assets.push(InstantiateAssetInfo {
@@ -61,6 +64,38 @@ fn instantiate_setup(sender: &str) -> OwnedDeps<MockStorage, MockApi, WasmMockQu
     deps
 }
 
+/// Instantiate a luna/uusd basket and deposit 10 luna and 1000 uusd, each worth 1000 USD
+fn funded_basket_setup(sender: &str) -> OwnedDeps<MockStorage, MockApi, WasmMockQuerier> {
+    use crate::state::BASKET;
+    let mut deps = instantiate_setup(sender);
+
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.lp_token_address = Addr::unchecked(FAKE_LP_TOKEN_ADDRESS);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    for (amount, denom) in [(10_000_000, "luna"), (1_000_000_000, "uusd")] {
+        let depositor = mock_info("first_depositor", &coins(amount, denom));
+        let deposit_msg = ExecuteMsg::DepositLiquidity {
+            assets: vec![Asset {
+                info: AssetInfo::NativeToken { denom: denom.to_string() },
+                amount: Uint128::new(amount),
+            }],
+            slippage_tolerance: None,
+            min_lp_out: None,
+            receiver: None,
+            auto_stake: None,
+            deadline: None,
+        };
+        execute(deps.as_mut(), mock_env(), depositor, deposit_msg).unwrap();
+    }
+
+    deps.querier.with_token_balances(&[(
+        &String::from(FAKE_LP_TOKEN_ADDRESS),
+        &[(&String::from("first_depositor"), &Uint128::new(2_000_000_000_000))],
+    )]);
+    deps
+}
+
 #[test]
 fn proper_initialization() {
     let mut deps = mock_dependencies(&[]);
@@ -90,6 +125,9 @@ fn proper_initialization() {
         oracle: OracleInterface::from_dummy(100, 0),
         backup_oracle: OracleInterface::from_dummy(100, 0),
         ticker_data: create_ticker_data(),
+        swap_enabled: true,
+        use_ema_price: false,
+        fee_on_transfer: false,
     });
     let msg = InstantiateMsg {
         assets: assets,
@@ -113,6 +151,21 @@ fn proper_initialization() {
         min_profit_time: Uint128::new(1),
         /// account that can make changes to the exchange
         admin: Addr::unchecked("name"),
+        max_oracle_deviation_bps: Uint128::zero(),
+        generator_address: None,
+        min_deposit_value_usd: Uint128::zero(),
+        stable_peg_tolerance_bps: Uint128::zero(),
+        pyth_contract_address: None,
+        quote: None,
+        max_lp_supply: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_decimals: None,
+        utilization_fee_basis_points: None,
+        utilization_fee_slope_basis_points: None,
+        borrow_rate_basis_points: None,
+        borrow_rate_slope_basis_points: None,
+        production: false,
         /// The token contract code ID used for the tokens in the pool
         token_code_id: 10u64,
     };
@@ -121,7 +174,7 @@ fn proper_initialization() {
     // We can just call .unwrap() to assert this was a success
     let env = mock_env();
     let info = mock_info(sender, &[]);
-    let res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+    let res = instantiate(deps.as_mut(), env, info, msg.clone()).unwrap();
     assert_eq!(
         res.messages,
         vec![SubMsg {
@@ -139,7 +192,7 @@ fn proper_initialization() {
                 })
                 .unwrap(),
                 funds: vec![],
-                admin: None,
+                admin: Some("name".to_string()),
                 label: String::from("Tsunami LP Token"),
             }
             .into(),
@@ -169,7 +222,11 @@ fn proper_initialization() {
             occupied_reserves: Uint128::new(0),
             available_reserves: Uint128::new(0),
             fee_reserves: Uint128::new(0),
-            ticker_data: create_ticker_data()
+            ticker_data: create_ticker_data(),
+            swap_enabled: true,
+            use_ema_price: false,
+            fee_on_transfer: false,
+            decimals: Some(6),
         }]
     );
     assert_eq!(basket.tax_basis_points, Uint128::new(1));
@@ -181,6 +238,215 @@ fn proper_initialization() {
     assert_eq!(basket.liquidation_fee_usd, Uint128::new(1));
     assert_eq!(basket.min_profit_time, Uint128::new(1));
     assert_eq!(basket.admin, Addr::unchecked("name"));
+
+    // A custom LP token name and symbol are passed through to the token, and the token's
+    // migration admin follows the basket admin
+    let custom_msg = InstantiateMsg {
+        lp_token_name: Some("Blue Chip LP".to_string()),
+        lp_token_symbol: Some("BLUE-LP".to_string()),
+        admin: Addr::unchecked("other_admin"),
+        ..msg.clone()
+    };
+    let res = instantiate(deps.as_mut(), mock_env(), mock_info(sender, &[]), custom_msg).unwrap();
+    match &res.messages[0].msg {
+        CosmosMsg::Wasm(WasmMsg::Instantiate { msg, admin, .. }) => {
+            assert_eq!(admin, &Some("other_admin".to_string()));
+            let lp_msg: InstantiateLpMsg = from_binary(msg).unwrap();
+            assert_eq!(lp_msg.name, "Blue Chip LP");
+            assert_eq!(lp_msg.symbol, "BLUE-LP");
+        }
+        _ => panic!("Expected WasmMsg::Instantiate"),
+    }
+
+    // Symbols cw20 would reject are caught before the token is instantiated
+    for symbol in ["LP", "THIRTEENCHARS", "TLP1", "T_LP"] {
+        let bad_msg = InstantiateMsg {
+            lp_token_symbol: Some(symbol.to_string()),
+            ..msg.clone()
+        };
+        match instantiate(deps.as_mut(), mock_env(), mock_info(sender, &[]), bad_msg) {
+            Err(ContractError::InvalidLpTokenSymbol) => {}
+            x => {
+                panic!("Err(InvalidLpTokenSymbol) should have been returned, {:?} was returned instead", x);
+            }
+        }
+    }
+}
+
+/// Check that a basket with 6 decimal LP tokens mints one LP unit per USD_VALUE_PRECISION unit
+#[test]
+fn deposit_with_6_decimal_lp() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_token_balances(&[(
+        &String::from(FAKE_LP_TOKEN_ADDRESS),
+        &[(&String::from(MOCK_CONTRACT_ADDR), &Uint128::zero())],
+    )]);
+    let msg = InstantiateMsg {
+        assets: vec![InstantiateAssetInfo {
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            address: Addr::unchecked("ust_addr"),
+            oracle: OracleInterface::from_dummy(1_000_000, -6),
+            ..create_instantiate_asset_info()
+        }],
+        lp_decimals: Some(6),
+        ..create_instantiate_msg()
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info(sender, &[]), msg).unwrap();
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.lp_decimals, 6);
+    basket.lp_token_address = Addr::unchecked(FAKE_LP_TOKEN_ADDRESS);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let deposit = |deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, amount: u128| {
+        let deposit_msg = ExecuteMsg::DepositLiquidity {
+            assets: vec![Asset {
+                info: AssetInfo::NativeToken { denom: "uusd".to_string() },
+                amount: Uint128::new(amount),
+            }],
+            slippage_tolerance: None,
+            min_lp_out: None,
+            receiver: None,
+            auto_stake: None,
+            deadline: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(sender, &coins(amount, "uusd")), deposit_msg)
+            .unwrap()
+    };
+
+    // 1000 USD seeds the basket with 1000 LP at 6 decimals
+    let res = deposit(&mut deps, 1_000_000_000);
+    assert_eq!(res.attributes[4], attr("tokens_to_mint", "1000000000"));
+
+    // Later deposits mint pro rata, less the 15 bps fee
+    deps.querier.with_token_balances(&[(
+        &String::from(FAKE_LP_TOKEN_ADDRESS),
+        &[(&String::from(sender), &Uint128::new(1_000_000_000))],
+    )]);
+    let res = deposit(&mut deps, 1_000_000);
+    assert_eq!(res.attributes[4], attr("tokens_to_mint", "998500"));
+
+    // LP decimals below the USD precision or above cw20's max are rejected
+    for lp_decimals in [5, 19] {
+        let bad_msg = InstantiateMsg {
+            lp_decimals: Some(lp_decimals),
+            ..create_instantiate_msg()
+        };
+        match instantiate(deps.as_mut(), mock_env(), mock_info(sender, &[]), bad_msg) {
+            Err(ContractError::InvalidLpDecimals) => {}
+            x => {
+                panic!("Err(InvalidLpDecimals) should have been returned, {:?} was returned instead", x);
+            }
+        }
+    }
+}
+
+/// Check that a zero LP token code id is rejected at instantiation
+#[test]
+fn try_instantiate_zero_code_id() {
+    let mut deps = mock_dependencies(&[]);
+    let msg = InstantiateMsg {
+        token_code_id: 0,
+        ..create_instantiate_msg()
+    };
+    match instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg) {
+        Err(ContractError::InvalidCodeId) => {}
+        x => {
+            panic!("Err(InvalidCodeId) should have been returned, {:?} was returned instead", x);
+        }
+    }
+}
+
+/// Check that a production basket cannot be instantiated with stub oracles
+#[test]
+fn try_instantiate_production_with_stub_oracle() {
+    let mut deps = mock_dependencies(&[]);
+    let msg = InstantiateMsg {
+        production: true,
+        ..create_instantiate_msg()
+    };
+    match instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg) {
+        Err(ContractError::StubOracleInProduction) => {}
+        x => {
+            panic!("Err(StubOracleInProduction) should have been returned, {:?} was returned instead", x);
+        }
+    }
+
+    instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), create_instantiate_msg()).unwrap();
+}
+
+/// Check that the LP token instantiation reply sets the token once and rejects a second reply
+#[test]
+fn reply_only_initializes_once() {
+    use protobuf::Message;
+    let mut deps = instantiate_setup("addr0000");
+
+    let instantiate_reply = |contract_address: &str| {
+        let mut res = MsgInstantiateContractResponse::new();
+        res.contract_address = contract_address.to_string();
+        Reply {
+            id: 1,
+            result: ContractResult::Ok(SubMsgExecutionResponse {
+                events: vec![],
+                data: Some(res.write_to_bytes().unwrap().into()),
+            }),
+        }
+    };
+
+    let res = reply(deps.as_mut(), mock_env(), instantiate_reply(FAKE_LP_TOKEN_ADDRESS)).unwrap();
+    assert_eq!(res.attributes[0], attr("liquidity_token_addr", FAKE_LP_TOKEN_ADDRESS));
+
+    match reply(deps.as_mut(), mock_env(), instantiate_reply("other-lp-token")) {
+        Err(ContractError::AlreadyInitialized) => {}
+        x => {
+            panic!("Err(AlreadyInitialized) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.lp_token_address, Addr::unchecked(FAKE_LP_TOKEN_ADDRESS));
+    assert!(basket.lp_token_initialized);
+}
+
+/// Check that instantiating with a Pyth oracle missing its price id or contract address fails
+#[test]
+fn try_instantiate_malformed_pyth_oracle() {
+    let instantiate_with = |oracle: OracleInterface, backup_oracle: OracleInterface| {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg {
+            assets: vec![InstantiateAssetInfo {
+                info: AssetInfo::NativeToken {
+                    denom: "luna".to_string(),
+                },
+                address: Addr::unchecked("luna_addr"),
+                oracle,
+                backup_oracle,
+                ..create_instantiate_asset_info()
+            }],
+            ..create_instantiate_msg()
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg)
+    };
+    let stub = OracleInterface::from_dummy(100, 0);
+    let price_id = PriceIdentifier::new([1; 32]);
+
+    for (oracle, backup_oracle) in [
+        (OracleInterface::from_pyth(Addr::unchecked("pyth-contract"), PriceIdentifier::new([0; 32])), stub.clone()),
+        (OracleInterface::from_pyth(Addr::unchecked(""), price_id), stub.clone()),
+        (stub.clone(), OracleInterface::from_pyth(Addr::unchecked(""), price_id)),
+    ] {
+        match instantiate_with(oracle, backup_oracle) {
+            Err(ContractError::InvalidOracleConfig) => {}
+            x => {
+                panic!(
+                    "Err(InvalidOracleConfig) should have been returned, {:?} was returned instead",
+                    x
+                );
+            }
+        }
+    }
+
+    instantiate_with(OracleInterface::from_pyth(Addr::unchecked("pyth-contract"), price_id), stub).unwrap();
 }
 
 // Create a default instantiate msg
@@ -188,15 +454,30 @@ fn create_instantiate_msg() -> InstantiateMsg {
     InstantiateMsg {
         assets: vec![create_instantiate_asset_info()],
         name: "blue chip basket".to_string(),
-        tax_basis_points: Uint128::new(1),
-        stable_tax_basis_points: Uint128::new(1),
-        mint_burn_basis_points: Uint128::new(1),
-        swap_fee_basis_points: Uint128::new(1),
-        stable_swap_fee_basis_points: Uint128::new(1),
+        tax_basis_points: Uint128::new(15),
+        stable_tax_basis_points: Uint128::new(15),
+        mint_burn_basis_points: Uint128::new(15),
+        swap_fee_basis_points: Uint128::new(15),
+        stable_swap_fee_basis_points: Uint128::new(4),
         margin_fee_basis_points: Uint128::new(1),
         liquidation_fee_usd: Uint128::new(1),
         min_profit_time: Uint128::new(1),
         admin: Addr::unchecked("name"),
+        max_oracle_deviation_bps: Uint128::zero(),
+        generator_address: None,
+        min_deposit_value_usd: Uint128::zero(),
+        stable_peg_tolerance_bps: Uint128::zero(),
+        pyth_contract_address: None,
+        quote: None,
+        max_lp_supply: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_decimals: None,
+        utilization_fee_basis_points: None,
+        utilization_fee_slope_basis_points: None,
+        borrow_rate_basis_points: None,
+        borrow_rate_slope_basis_points: None,
+        production: false,
         token_code_id: 10u64,
     }
 }
@@ -236,6 +517,9 @@ fn create_instantiate_asset_info() -> InstantiateAssetInfo {
         oracle: OracleInterface::from_dummy(100, 0),
         backup_oracle: OracleInterface::from_dummy(100, 0),
         ticker_data: create_ticker_data(),
+        swap_enabled: true,
+        use_ema_price: false,
+        fee_on_transfer: false,
     }
 }
 
@@ -252,15 +536,30 @@ fn create_basket() -> Basket {
         &InstantiateMsg {
             assets: vec![create_instantiate_asset_info()],
             name: "blue chip basket".to_string(),
-            tax_basis_points: Uint128::new(1),
-            stable_tax_basis_points: Uint128::new(1),
-            mint_burn_basis_points: Uint128::new(1),
-            swap_fee_basis_points: Uint128::new(1),
-            stable_swap_fee_basis_points: Uint128::new(1),
+            tax_basis_points: Uint128::new(15),
+            stable_tax_basis_points: Uint128::new(15),
+            mint_burn_basis_points: Uint128::new(15),
+            swap_fee_basis_points: Uint128::new(15),
+            stable_swap_fee_basis_points: Uint128::new(4),
             margin_fee_basis_points: Uint128::new(1),
             liquidation_fee_usd: Uint128::new(1),
             min_profit_time: Uint128::new(1),
             admin: Addr::unchecked("name"),
+            max_oracle_deviation_bps: Uint128::zero(),
+            generator_address: None,
+            min_deposit_value_usd: Uint128::zero(),
+            stable_peg_tolerance_bps: Uint128::zero(),
+            pyth_contract_address: None,
+            quote: None,
+            max_lp_supply: None,
+            lp_token_name: None,
+            lp_token_symbol: None,
+            lp_decimals: None,
+            utilization_fee_basis_points: None,
+            utilization_fee_slope_basis_points: None,
+            borrow_rate_basis_points: None,
+            borrow_rate_slope_basis_points: None,
+            production: false,
             token_code_id: 10u64,
         },
     )
@@ -286,6 +585,10 @@ fn create_basket_asset() -> BasketAsset {
         fee_reserves: Uint128::new(0),
         available_reserves: Uint128::new(400),
         ticker_data: create_ticker_data(),
+        swap_enabled: true,
+        use_ema_price: false,
+        fee_on_transfer: false,
+        decimals: None,
     }
 }
 
@@ -308,6 +611,151 @@ fn create_basket_asset() -> BasketAsset {
 //     )
 // }
 
+/// Check that prices convert to decimals without truncating the fraction, for any exponent
+#[test]
+fn pyth_price_to_decimal() {
+    use crate::price::PythPrice;
+    use std::str::FromStr;
+    let to_decimal = |price: i64, expo: i32| PythPrice::new(Price { price, conf: 0, expo }).to_Decimal();
+
+    assert_eq!(to_decimal(1_234_567, -6).unwrap(), Decimal::from_str("1.234567").unwrap());
+    assert_eq!(to_decimal(12_345, -2).unwrap(), Decimal::from_str("123.45").unwrap());
+    assert_eq!(to_decimal(123, 2).unwrap(), Decimal::from_str("12300").unwrap());
+    assert_eq!(to_decimal(0, 2).unwrap(), Decimal::zero());
+
+    match to_decimal(-1_234_567, -6) {
+        Err(ContractError::NegativePrice) => {}
+        x => {
+            panic!("Err(NegativePrice) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    // Too large for a Decimal
+    match to_decimal(i64::MAX, 10) {
+        Err(ContractError::FailedCast) => {}
+        x => {
+            panic!("Err(FailedCast) should have been returned, {:?} was returned instead", x);
+        }
+    }
+}
+
+/// Check multiplying, dividing and inverting prices, keeping the requested exponent
+#[test]
+fn pyth_price_arithmetic() {
+    use crate::price::PythPrice;
+    let price = |price: i64, expo: i32| PythPrice::new(Price { price, conf: 0, expo });
+    let luna = price(100_000_000, -6);
+    let uusd = price(1_000_000, -6);
+
+    // 100 USD/luna is 0.01 luna/USD
+    let inverted = luna.invert().unwrap().pyth_price;
+    assert_eq!((inverted.price, inverted.expo), (10_000, -6));
+    let inverted = luna.invert().unwrap().invert().unwrap().pyth_price;
+    assert_eq!((inverted.price, inverted.expo), (100_000_000, -6));
+
+    // luna/uusd is 100, in any exponent
+    let quotient = luna.div(&uusd, -6).unwrap().pyth_price;
+    assert_eq!((quotient.price, quotient.expo), (100_000_000, -6));
+    let quotient = luna.div(&uusd, 0).unwrap().pyth_price;
+    assert_eq!((quotient.price, quotient.expo), (100, 0));
+
+    // 100 USD/luna times 0.5 quote/USD is 50 quote/luna
+    let product = luna.mul(&price(5, -1), -6).unwrap().pyth_price;
+    assert_eq!((product.price, product.expo), (50_000_000, -6));
+
+    // A zero price cannot be divided by, nor inverted
+    for res in [luna.div(&price(0, -6), -6), price(0, -6).invert()] {
+        match res {
+            Err(ContractError::PriceArithmeticFailed) => {}
+            x => {
+                panic!("Err(PriceArithmeticFailed) should have been returned, {:?} was returned instead", x);
+            }
+        }
+    }
+}
+
+/// Check that baskets and their assets print a readable summary
+#[test]
+fn display_basket_and_assets() {
+    let basket = create_basket();
+    assert_eq!(basket.to_string(), "blue chip basket (2 assets, total weight 2)");
+    let asset = basket.assets[0].to_string();
+    assert!(asset.starts_with("uluna "));
+    assert!(asset.contains("weight 1"));
+}
+
+/// Check that the utilization surcharge grows with the share of an asset's reserves in use
+#[test]
+fn utilization_fee_increases_with_utilization() {
+    let mut basket = create_basket();
+    basket.utilization_fee_basis_points = Uint128::new(5);
+    basket.utilization_fee_slope_basis_points = Uint128::new(100);
+
+    let fee_at = |occupied: u128| {
+        let mut basket_asset = create_basket_asset();
+        basket_asset.available_reserves = Uint128::new(1_000);
+        basket_asset.occupied_reserves = Uint128::new(occupied);
+        calculate_fee_basis_points(
+            Uint128::new(100_000),
+            &basket,
+            basket.swap_fee_basis_points,
+            &[Uint128::new(40_000)],
+            &vec![Uint128::new(1_000)],
+            &[basket_asset],
+            Action::Offer,
+        ).unwrap()[0]
+    };
+
+    // Idle reserves keep the imbalance fee of `slightly_improves_basket_add`, then base + slope * utilization
+    assert_eq!(fee_at(0), Uint128::new(12));
+    assert_eq!(fee_at(100), Uint128::new(27));
+    assert_eq!(fee_at(500), Uint128::new(67));
+    assert_eq!(fee_at(1_000), Uint128::new(117));
+}
+
+/// Check that total weights out of sync with the asset weights are reported as an error instead
+/// of panicking on the fee math
+#[test]
+fn fee_with_desynced_total_weights() {
+    use crate::state::BASKET;
+    let basket_asset = create_basket_asset();
+    let mut basket = create_basket();
+    basket.total_weights = basket_asset.token_weight - Uint128::new(1);
+    let fees = calculate_fee_basis_points(
+        Uint128::new(100_000),
+        &basket,
+        basket.swap_fee_basis_points,
+        &[Uint128::new(40_000)],
+        &vec![Uint128::new(1_000)],
+        &[basket_asset],
+        Action::Offer,
+    );
+    match fees {
+        Err(ContractError::WeightsOutOfSync) => {}
+        x => {
+            panic!("Err(WeightsOutOfSync) should have been returned, {:?} was returned instead", x);
+        }
+    }
+
+    // The same through a swap simulation
+    let mut deps = funded_basket_setup("addr0000");
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].token_weight = Uint128::new(3);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    let simulate_swap = QueryMsg::SimulateSwap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken { denom: "luna".to_string() },
+            amount: Uint128::new(1_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken { denom: "uusd".to_string() },
+    };
+    match query(deps.as_ref(), mock_env(), simulate_swap) {
+        Err(ContractError::WeightsOutOfSync) => {}
+        x => {
+            panic!("Err(WeightsOutOfSync) should have been returned, {:?} was returned instead", x);
+        }
+    }
+}
+
 #[test]
 fn slightly_improves_basket_add() {
     let basket_asset = create_basket_asset();
@@ -315,11 +763,12 @@ fn slightly_improves_basket_add() {
     let fees = calculate_fee_basis_points(
         Uint128::new(100_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(40_000)],
         &vec![Uint128::new(1_000)],
         &vec![basket_asset],
         Action::Offer,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(12)], fees);
 }
 
@@ -332,11 +781,12 @@ fn strongly_improves_basket_add() {
     let fees = calculate_fee_basis_points(
         Uint128::new(100_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(1_000)],
         &vec![Uint128::new(1_000)],
         &vec![basket_asset],
         Action::Offer,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(0)], fees);
 }
 
@@ -349,14 +799,49 @@ fn strongly_harms_basket_add() {
     let fees = calculate_fee_basis_points(
         Uint128::new(100_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(90_000)],
         &vec![Uint128::new(100_000)],
         &vec![basket_asset],
         Action::Offer,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(28)], fees);
 }
 
+#[test]
+fn configured_fees_harm_basket_add() {
+    let mut basket_asset = create_basket_asset();
+    let mut basket = create_basket();
+    basket.swap_fee_basis_points = Uint128::new(30);
+    basket.tax_basis_points = Uint128::new(50);
+    basket.stable_tax_basis_points = Uint128::new(5);
+    basket_asset.available_reserves = Uint128::new(500);
+
+    let fees = calculate_fee_basis_points(
+        Uint128::new(100_000),
+        &basket,
+        basket.swap_fee_basis_points,
+        &[Uint128::new(90_000)],
+        &vec![Uint128::new(100_000)],
+        &[basket_asset.clone()],
+        Action::Offer,
+    ).unwrap();
+    assert_eq!(vec![Uint128::new(75)], fees);
+
+    // Stable assets are penalized with the stable tax instead
+    basket_asset.stable_token = true;
+    let fees = calculate_fee_basis_points(
+        Uint128::new(100_000),
+        &basket,
+        basket.swap_fee_basis_points,
+        &[Uint128::new(90_000)],
+        &vec![Uint128::new(100_000)],
+        &[basket_asset],
+        Action::Offer,
+    ).unwrap();
+    assert_eq!(vec![Uint128::new(34)], fees);
+}
+
 #[test]
 fn lightly_harms_basket_add() {
     let mut basket_asset = create_basket_asset();
@@ -366,11 +851,12 @@ fn lightly_harms_basket_add() {
     let fees = calculate_fee_basis_points(
         Uint128::new(100_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(52_000)],
         &vec![Uint128::new(1_000)],
         &vec![basket_asset],
         Action::Offer,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(15)], fees);
 }
 
@@ -382,11 +868,12 @@ fn slightly_improves_basket_remove() {
     let fees = calculate_fee_basis_points(
         Uint128::new(100_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(60_000)],
         &vec![Uint128::new(1_000)],
         &vec![basket_asset],
         Action::Ask,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(12)], fees);
 }
 
@@ -399,11 +886,12 @@ fn strongly_improves_basket_remove() {
     let fees = calculate_fee_basis_points(
         Uint128::new(100_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(95_000)],
         &vec![Uint128::new(10_000)],
         &vec![basket_asset],
         Action::Ask,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(1)], fees);
 }
 
@@ -416,11 +904,12 @@ fn strongly_harms_basket_remove() {
     let fees = calculate_fee_basis_points(
         Uint128::new(100_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(10_000)],
         &vec![Uint128::new(1_000)],
         &vec![basket_asset],
         Action::Ask,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(27)], fees);
 }
 
@@ -432,11 +921,12 @@ fn initial_deposit() {
     let fees = calculate_fee_basis_points(
         Uint128::new(0),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(0)],
         &vec![Uint128::new(1_000)],
         &vec![basket_asset0],
         Action::Offer,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(0)], fees);
 }
 
@@ -449,11 +939,12 @@ fn improve_basket_add1() {
     let fees = calculate_fee_basis_points(
         Uint128::new(1_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(100), Uint128::new(900)],
         &vec![Uint128::new(9_900), Uint128::new(19_100)],
         &vec![basket_asset0, basket_asset1],
         Action::Offer,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(3), Uint128::new(3)], fees);
 }
 
@@ -466,11 +957,12 @@ fn lightly_harms_basket_remove() {
     let fees = calculate_fee_basis_points(
         Uint128::new(100_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(48_000)],
         &vec![Uint128::new(1_000)],
         &vec![basket_asset],
         Action::Ask,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(15)], fees);
 }
 
@@ -483,11 +975,12 @@ fn neutral_basket_remove() {
     let fees = calculate_fee_basis_points(
         Uint128::new(101_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(51_000)],
         &vec![Uint128::new(1_000)],
         &vec![basket_asset],
         Action::Ask,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(14)], fees);
 }
 
@@ -500,11 +993,12 @@ fn neutral_basket_add() {
     let fees = calculate_fee_basis_points(
         Uint128::new(99_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(49_000)],
         &vec![Uint128::new(1_000)],
         &vec![basket_asset],
         Action::Offer,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(14)], fees);
 }
 
@@ -517,11 +1011,12 @@ fn imbalanced_basket_big_double_balanced_add() {
     let fees = calculate_fee_basis_points(
         Uint128::new(10_000),
         &basket,
+        basket.swap_fee_basis_points,
         &vec![Uint128::new(9_900), Uint128::new(100)],
         &vec![Uint128::new(100_000), Uint128::new(100_000)],
         &vec![basket_asset],
         Action::Offer,
-    );
+    ).unwrap();
     assert_eq!(vec![Uint128::new(0)], fees);
 }
 
@@ -600,7 +1095,10 @@ fn single_asset_deposit() {
     let deposit_msg = ExecuteMsg::DepositLiquidity {
         assets: vec![deposit_asset],
         slippage_tolerance: None,
+        min_lp_out: None,
         receiver: None,
+        auto_stake: None,
+        deadline: None,
     };
 
     let _deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg).unwrap();
@@ -761,7 +1259,10 @@ fn two_precise_deposits_swap_and_withdraw() {
     let deposit_msg1 = ExecuteMsg::DepositLiquidity {
         assets: vec![deposit_asset1.clone()],
         slippage_tolerance: None,
+        min_lp_out: None,
         receiver: None,
+        auto_stake: None,
+        deadline: None,
     };
     let deposit_res1 =
         execute(deps.as_mut(), mock_env(), depositor1.clone(), deposit_msg1).unwrap();
@@ -783,7 +1284,10 @@ fn two_precise_deposits_swap_and_withdraw() {
     let deposit_msg2 = ExecuteMsg::DepositLiquidity {
         assets: vec![deposit_asset2.clone()],
         slippage_tolerance: None,
+        min_lp_out: None,
         receiver: None,
+        auto_stake: None,
+        deadline: None,
     };
 
     let deposit_res2 =
@@ -833,6 +1337,7 @@ fn two_precise_deposits_swap_and_withdraw() {
         to: None,
         max_spread: None,
         belief_price: None,
+        deadline: None,
     };
 
     let swapper = mock_info(sender, &coins(98_765_432, "uusd"));
@@ -883,6 +1388,7 @@ fn two_precise_deposits_swap_and_withdraw() {
         to: None,
         max_spread: None,
         belief_price: None,
+        deadline: None,
     };
 
     let swapper = mock_info(sender, &coins(987_654, "luna"));
@@ -929,6 +1435,9 @@ fn two_precise_deposits_swap_and_withdraw() {
             sender: sender.to_string(),
             msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
                 asset: basket.assets[1].info.clone(),
+                min_out: None,
+                deadline: None,
+                receiver: None,
             })
             .unwrap(),
         },
@@ -980,7 +1489,10 @@ fn test_withdraw_liquidity() {
     let deposit_msg = ExecuteMsg::DepositLiquidity {
         assets: vec![deposit_asset.clone()],
         slippage_tolerance: None,
+        min_lp_out: None,
         receiver: None,
+        auto_stake: None,
+        deadline: None,
     };
     let deposit_res =
         execute(deps.as_mut(), mock_env(), depositor.clone(), deposit_msg).unwrap();
@@ -1009,6 +1521,9 @@ fn test_withdraw_liquidity() {
             sender: sender.to_string(),
             msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
                 asset: basket.assets[1].info.clone(),
+                min_out: None,
+                deadline: None,
+                receiver: None,
             })
             .unwrap(),
         },
@@ -1024,6 +1539,66 @@ fn test_withdraw_liquidity() {
 }
 
 
+/// Deposit uusd and withdraw it all, returning the withdraw result for the given floor
+fn withdraw_with_min_out(min_out: Uint128) -> Result<cosmwasm_std::Response, ContractError> {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = instantiate_setup(sender);
+
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.lp_token_address = Addr::unchecked(FAKE_LP_TOKEN_ADDRESS);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let depositor = mock_info(sender, &coins(100_000_000, "uusd"));
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: basket.assets[1].info.clone(),
+            amount: Uint128::new(100_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    execute(deps.as_mut(), mock_env(), depositor, deposit_msg).unwrap();
+
+    deps.querier.with_token_balances(&[(
+        &String::from(FAKE_LP_TOKEN_ADDRESS),
+        &[(&String::from(MOCK_CONTRACT_ADDR), &Uint128::from(100000000000_u128))],
+    )]);
+    let withdraw = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        amount: Uint128::new(100_000_000_000),
+        sender: sender.to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            asset: basket.assets[1].info.clone(),
+            min_out: Some(min_out),
+            deadline: None,
+            receiver: None,
+        })
+        .unwrap(),
+    });
+
+    let withdrawer = mock_info(FAKE_LP_TOKEN_ADDRESS, &[]);
+    execute(deps.as_mut(), mock_env(), withdrawer, withdraw)
+}
+
+/// The withdrawal returns 99_850_000 uusd, so a floor at exactly that amount is satisfied
+#[test]
+fn withdraw_liquidity_min_out_satisfied() {
+    let withdraw_res = withdraw_with_min_out(Uint128::new(99_850_000)).unwrap();
+    assert_eq!(&withdraw_res.attributes[2].value, "99850000uusd");
+}
+
+/// A floor one unit above what the withdrawal returns is rejected
+#[test]
+fn withdraw_liquidity_min_out_violated() {
+    match withdraw_with_min_out(Uint128::new(99_850_001)) {
+        Err(ContractError::MaxSlippageAssertion) => {}
+        x => panic!("Err(MaxSlippageAssertion) should have been returned, {:?} was returned instead", x),
+    }
+}
+
 /// Make an initial deposit and then a subsequent deposit of equal amounts
 /// Check that the resulting pool reserves are the sum of the two deposits and match the contract balance
 /// Check that the second deposit has fees subtracted from the LP tokens they receive
@@ -1068,7 +1643,10 @@ fn multiple_deposits_and_swap_and_withdraw() {
     let deposit_msg1 = ExecuteMsg::DepositLiquidity {
         assets: vec![deposit_asset1.clone()],
         slippage_tolerance: None,
+        min_lp_out: None,
         receiver: None,
+        auto_stake: None,
+        deadline: None,
     };
     let deposit_res1 =
         execute(deps.as_mut(), mock_env(), depositor1.clone(), deposit_msg1).unwrap();
@@ -1080,6 +1658,7 @@ fn multiple_deposits_and_swap_and_withdraw() {
         attr("receiver", depositor1.sender.clone().as_str()),
         attr("offer_asset", format!("{:?}", &[deposit_asset1])),
         attr("tokens_to_mint", expected_lp_tokens1),
+        attr("usd_value", "1000000000"),
     ];
     for i in 0..expected_attributes.len() {
         let actual_attribute = deposit_res1.attributes[i].clone();
@@ -1090,7 +1669,10 @@ fn multiple_deposits_and_swap_and_withdraw() {
     let deposit_msg2 = ExecuteMsg::DepositLiquidity {
         assets: vec![deposit_asset2.clone()],
         slippage_tolerance: None,
+        min_lp_out: None,
         receiver: None,
+        auto_stake: None,
+        deadline: None,
     };
 
     let deposit_res2 =
@@ -1102,6 +1684,7 @@ fn multiple_deposits_and_swap_and_withdraw() {
         attr("receiver", depositor2.sender.clone().as_str()),
         attr("offer_asset", format!("{:?}", &[deposit_asset2.clone()])),
         attr("tokens_to_mint", expected_lp_tokens2),
+        attr("usd_value", "1000000000"),
     ];
     for i in 0..expected_attributes.len() {
         let actual_attribute = deposit_res2.attributes[i].clone();
@@ -1137,6 +1720,7 @@ fn multiple_deposits_and_swap_and_withdraw() {
         to: None,
         max_spread: None,
         belief_price: None,
+        deadline: None,
     };
 
     let swapper = mock_info("first_depositor", &coins(10_000_000, "uusd"));
@@ -1148,12 +1732,17 @@ fn multiple_deposits_and_swap_and_withdraw() {
     let swap_return_amount = &swap_res.attributes[6].value;
     let offer_bps = &swap_res.attributes[7].value;
     let ask_bps = &swap_res.attributes[8].value;
+    let swap_usd_value = &swap_res.attributes[9].value;
+    let spread_amount = &swap_res.attributes[10].value;
     assert_eq!(swap_offer_asset, "uusd");
     assert_eq!(swap_ask_asset, "luna");
     assert_eq!(swap_offer_amount, "10000000");
     assert_eq!(swap_return_amount, "99700");
     assert_eq!(offer_bps, "15");
     assert_eq!(ask_bps, "15");
+    assert_eq!(swap_usd_value, "10000000");
+    // 0.1 luna at the oracle price, less the 99_700 returned after fees
+    assert_eq!(spread_amount, "300");
 
     match &swap_res.messages[0].msg {
         CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
@@ -1178,6 +1767,9 @@ fn multiple_deposits_and_swap_and_withdraw() {
             sender: sender.to_string(),
             msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
                 asset: basket.assets[0].info.clone(),
+                min_out: None,
+                deadline: None,
+                receiver: None,
             })
             .unwrap(),
         },
@@ -1194,8 +1786,10 @@ fn multiple_deposits_and_swap_and_withdraw() {
 
     let withdraw_redemption_asset = &withdraw_res.attributes[2].value;
     let withdraw_fee_bps = &withdraw_res.attributes[3].value;
+    let withdraw_usd_value = &withdraw_res.attributes[4].value;
     assert_eq!(withdraw_redemption_asset, "9985luna");
     assert_eq!(withdraw_fee_bps, "15");
+    assert_eq!(withdraw_usd_value, "998514");
 
     match &withdraw_res.messages[0].msg {
         CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
@@ -1245,7 +1839,10 @@ fn try_deposit_insufficient_funds() {
     let deposit_msg = ExecuteMsg::DepositLiquidity {
         assets: vec![deposit_asset],
         slippage_tolerance: None,
+        min_lp_out: None,
         receiver: None,
+        auto_stake: None,
+        deadline: None,
     };
 
     let deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg);
@@ -1276,7 +1873,10 @@ fn try_deposit_exceeding_limit() {
     let deposit_msg = ExecuteMsg::DepositLiquidity {
         assets: vec![deposit_asset],
         slippage_tolerance: None,
+        min_lp_out: None,
         receiver: None,
+        auto_stake: None,
+        deadline: None,
     };
 
     let deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg);
@@ -1291,7 +1891,6 @@ fn try_deposit_exceeding_limit() {
     }
 }
 
-#[ignore = "we don't implement the whitelist yet"]
 /// Check that depositing an asset the basket wasn't initialized with fails
 #[test]
 fn try_deposit_unwhitelisted_asset() {
@@ -1314,7 +1913,10 @@ fn try_deposit_unwhitelisted_asset() {
     let deposit_msg = ExecuteMsg::DepositLiquidity {
         assets: vec![deposit_asset],
         slippage_tolerance: None,
+        min_lp_out: None,
         receiver: None,
+        auto_stake: None,
+        deadline: None,
     };
 
     let deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg);
@@ -1329,14 +1931,4147 @@ fn try_deposit_unwhitelisted_asset() {
     }
 }
 
+/// Check that a deposit mixing a whitelisted and an unwhitelisted asset fails cleanly
 #[test]
-fn test_from_binary() {
-    match from_binary(&Binary::from_base64("eyJ3aXRoZHJhd19saXF1aWRpdHkiOnsiYXNzZXQiOnsibmF0aXZlX3Rva2VuIjp7ImRlbm9tIjoidXVzZCJ9fX19").unwrap()) {
-        Ok(Cw20HookMsg::WithdrawLiquidity { asset }) => {
-            assert_eq!(asset, AssetInfo::NativeToken {
-                denom: "uusd".to_string(),
-            });
-        },
-        _ => assert!(false, "Expected WithdrawLiquidity"),
+fn try_deposit_mixed_unwhitelisted_asset() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let luna_info = AssetInfo::NativeToken {
+        denom: "luna".to_string(),
+    };
+    let random_asset_info = AssetInfo::NativeToken {
+        denom: "random_asset".to_string(),
+    };
+
+    let depositor = mock_info(
+        "first_depositor",
+        &[Coin::new(1_000_000, "luna"), Coin::new(1_000_000, "random_asset")],
+    );
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![
+            Asset {
+                info: luna_info.clone(),
+                amount: Uint128::new(1_000_000),
+            },
+            Asset {
+                info: random_asset_info.clone(),
+                amount: Uint128::new(1_000_000),
+            },
+        ],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    let deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg);
+    match deposit_res {
+        Err(ContractError::AssetNotInBasket) => {}
+        x => {
+            panic!(
+                "Err(AssetNotInBasket) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    match basket.match_basket_assets(&[luna_info, random_asset_info]) {
+        Err(ContractError::AssetNotInBasket) => {}
+        x => {
+            panic!(
+                "Err(AssetNotInBasket) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that `to_asset_info` looks assets up in the basket rather than in the list being mapped
+#[test]
+fn to_asset_info_matches_basket_assets() {
+    use crate::state::ToAssetInfo;
+    let deps = instantiate_setup("addr0000");
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    let native = |denom: &str| AssetInfo::NativeToken {
+        denom: denom.to_string(),
+    };
+    let asset = |denom: &str| Asset {
+        info: native(denom),
+        amount: Uint128::new(1),
+    };
+
+    let assets = vec![asset("uusd"), asset("luna")];
+    assert_eq!(assets.to_asset_info(&basket.assets).unwrap(), vec![native("uusd"), native("luna")]);
+    assert_eq!(
+        basket.assets.to_asset_info(&basket.assets).unwrap(),
+        vec![native("luna"), native("uusd")]
+    );
+
+    let assets = vec![asset("luna"), asset("random_asset")];
+    match assets.to_asset_info(&basket.assets) {
+        Err(ContractError::AssetNotInBasket) => {}
+        x => {
+            panic!(
+                "Err(AssetNotInBasket) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    let mut other_basket_assets = basket.assets.clone();
+    other_basket_assets[1].info = native("uust");
+    match other_basket_assets.to_asset_info(&basket.assets) {
+        Err(ContractError::AssetNotInBasket) => {}
+        x => {
+            panic!(
+                "Err(AssetNotInBasket) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that depositing into a basket whose LP supply is backed by nothing fails cleanly
+#[test]
+fn try_deposit_zero_aum_with_lp_supply() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    for asset in basket.assets.iter_mut() {
+        asset.available_reserves = Uint128::zero();
+    }
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let depositor = mock_info(sender, &coins(1_000_000, "luna"));
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: AssetInfo::NativeToken {
+                denom: "luna".to_string(),
+            },
+            amount: Uint128::new(1_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    let deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg);
+    match deposit_res {
+        Err(ContractError::ZeroAumWithLpSupply) => {}
+        x => {
+            panic!(
+                "Err(ZeroAumWithLpSupply) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that depositing a CW20 the basket wasn't initialized with fails before any transfer is attempted
+#[test]
+fn try_deposit_unwhitelisted_token() {
+    let sender = "addr0000";
+    let mut deps = instantiate_setup(sender);
+
+    let random_token_info = AssetInfo::Token {
+        contract_addr: Addr::unchecked("random_token"),
+    };
+    let depositor = mock_info("first_depositor", &[]);
+    let deposit_asset = Asset {
+        info: random_token_info.clone(),
+        amount: Uint128::new(1),
+    };
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![deposit_asset],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+
+    let deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg);
+    match deposit_res {
+        Err(ContractError::AssetNotInBasket) => {}
+        x => {
+            panic!(
+                "Err(AssetNotInBasket) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that swapping an asset for itself is rejected
+#[test]
+fn try_swap_same_asset() {
+    let luna_info = AssetInfo::NativeToken {
+        denom: "luna".to_string(),
+    };
+
+    let sender = "addr0000";
+    let mut deps = instantiate_setup(sender);
+
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: luna_info.clone(),
+            amount: Uint128::new(1_000),
+        },
+        ask_asset: luna_info.clone(),
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+
+    let swapper = mock_info(sender, &coins(1_000, "luna"));
+    let swap_res = execute(deps.as_mut(), mock_env(), swapper, swap);
+    match swap_res {
+        Err(ContractError::SameAsset) => {}
+        x => {
+            panic!(
+                "Err(SameAsset) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that a swap offering nothing is rejected
+#[test]
+fn try_swap_zero_amount() {
+    let sender = "addr0000";
+    let mut deps = instantiate_setup(sender);
+
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        ask_asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+
+    let swapper = mock_info(sender, &[]);
+    let swap_res = execute(deps.as_mut(), mock_env(), swapper, swap);
+    match swap_res {
+        Err(ContractError::InvalidZeroAmount) => {}
+        x => {
+            panic!(
+                "Err(InvalidZeroAmount) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that a deposit of nothing is rejected
+#[test]
+fn try_deposit_zero_amount() {
+    let sender = "addr0000";
+    let mut deps = instantiate_setup(sender);
+
+    let depositor = mock_info("first_depositor", &[]);
+    let deposit_asset = Asset {
+        info: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        amount: Uint128::zero(),
+    };
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![deposit_asset],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+
+    let deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg);
+    match deposit_res {
+        Err(ContractError::InvalidZeroAmount) => {}
+        x => {
+            panic!(
+                "Err(InvalidZeroAmount) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that coins sent on top of the swap offer are rejected
+#[test]
+fn try_swap_with_stray_funds() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "luna".to_string(),
+            },
+            amount: Uint128::new(1_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+
+    let swapper = mock_info(
+        sender,
+        &[Coin::new(1_000_000, "luna"), Coin::new(1_000, "uust")],
+    );
+    let swap_res = execute(deps.as_mut(), mock_env(), swapper, swap);
+    match swap_res {
+        Err(ContractError::Std(GenericErr { msg: _ })) => {}
+        x => {
+            panic!(
+                "Err(GenericErr) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that coins sent on top of the deposited assets are rejected
+#[test]
+fn try_deposit_with_stray_funds() {
+    let sender = "addr0000";
+    let mut deps = instantiate_setup(sender);
+
+    let depositor = mock_info(
+        "first_depositor",
+        &[Coin::new(1_000_000, "luna"), Coin::new(1_000, "uust")],
+    );
+    let deposit_asset = Asset {
+        info: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        amount: Uint128::new(1_000_000),
+    };
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![deposit_asset],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+
+    let deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg);
+    match deposit_res {
+        Err(ContractError::Std(GenericErr { msg: _ })) => {}
+        x => {
+            panic!(
+                "Err(GenericErr) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that a deposit of several native assets must attach exactly the declared coins
+#[test]
+fn deposit_multiple_native_denoms() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "luna".to_string(),
+                },
+                amount: Uint128::new(10_000),
+            },
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::new(1_000_000),
+            },
+        ],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+
+    // Missing the uusd leg of the deposit
+    let depositor = mock_info(sender, &[Coin::new(10_000, "luna")]);
+    let res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg.clone());
+    match res {
+        Err(ContractError::Std(GenericErr { msg: _ })) => {}
+        x => {
+            panic!(
+                "Err(GenericErr) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    // An extra denom on top of the declared coins
+    let depositor = mock_info(
+        sender,
+        &[
+            Coin::new(10_000, "luna"),
+            Coin::new(1_000_000, "uusd"),
+            Coin::new(1_000, "uust"),
+        ],
+    );
+    let res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg.clone());
+    match res {
+        Err(ContractError::Std(GenericErr { msg: _ })) => {}
+        x => {
+            panic!(
+                "Err(GenericErr) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    // Exactly the declared coins
+    let depositor = mock_info(
+        sender,
+        &[Coin::new(10_000, "luna"), Coin::new(1_000_000, "uusd")],
+    );
+    execute(deps.as_mut(), mock_env(), depositor, deposit_msg).unwrap();
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].available_reserves, Uint128::new(10_010_000));
+    assert_eq!(basket.assets[1].available_reserves, Uint128::new(1_001_000_000));
+}
+
+/// Check that the mint/burn fee prices deposits independently of swaps
+#[test]
+fn mint_burn_fee_applies_to_deposits_not_swaps() {
+    use crate::state::BASKET;
+
+    let deposit_and_simulate = |mint_burn_basis_points: u128| {
+        let sender = "addr0000";
+        let mut deps = funded_basket_setup(sender);
+        let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+        basket.mint_burn_basis_points = Uint128::new(mint_burn_basis_points);
+        BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+        let luna = Asset {
+            info: AssetInfo::NativeToken {
+                denom: "luna".to_string(),
+            },
+            amount: Uint128::new(1_000_000),
+        };
+        let simulation: SimulationResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::SimulateSwap {
+                    offer_asset: luna.clone(),
+                    ask_asset: AssetInfo::NativeToken {
+                        denom: "uusd".to_string(),
+                    },
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let depositor = mock_info(sender, &coins(1_000_000, "luna"));
+        let deposit_msg = ExecuteMsg::DepositLiquidity {
+            assets: vec![luna],
+            slippage_tolerance: None,
+            min_lp_out: None,
+            receiver: None,
+            auto_stake: None,
+            deadline: None,
+        };
+        let deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg).unwrap();
+        (deposit_res.attributes[4].value.clone(), simulation)
+    };
+
+    let (minted_low_fee, simulation_low_fee) = deposit_and_simulate(15);
+    let (minted_high_fee, simulation_high_fee) = deposit_and_simulate(50);
+    // 100 USD into a 2000 USD basket mints a twentieth of the supply, less the mint/burn fee
+    assert_eq!(minted_low_fee, "99850000000");
+    assert_eq!(minted_high_fee, "99500000000");
+    assert_eq!(simulation_low_fee, simulation_high_fee);
+}
+
+/// Check that a swap between a non-stable and a stable asset is charged the normal swap fee
+#[test]
+fn simulate_swap_mixed_pair_fee() {
+    let deps = funded_basket_setup("addr0000");
+
+    let simulation: SimulationResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::SimulateSwap {
+                offer_asset: Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: "luna".to_string(),
+                    },
+                    amount: Uint128::new(1_000),
+                },
+                ask_asset: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(simulation.offer_fee_bps, Uint128::new(15));
+    assert_eq!(simulation.ask_fee_bps, Uint128::new(15));
+}
+
+/// Check that simulating an ask worth more than the ask reserves errors instead of panicking
+#[test]
+fn simulate_swap_oversized_ask() {
+    let deps = funded_basket_setup("addr0000");
+    let luna = AssetInfo::NativeToken { denom: "luna".to_string() };
+    let uusd = AssetInfo::NativeToken { denom: "uusd".to_string() };
+
+    // 20 luna is 2000 USD, against 1000 USD of uusd reserves
+    let simulate = QueryMsg::SimulateSwap {
+        offer_asset: Asset { info: luna.clone(), amount: Uint128::new(20_000_000) },
+        ask_asset: uusd.clone(),
+    };
+    let reverse_simulate = QueryMsg::ReverseSimulateSwap {
+        ask_asset: Asset { info: uusd, amount: Uint128::new(2_000_000_000) },
+        offer_info: luna,
+    };
+    for msg in [simulate, reverse_simulate] {
+        match query(deps.as_ref(), mock_env(), msg) {
+            Err(ContractError::InsufficientReserves) => {}
+            x => {
+                panic!("Err(InsufficientReserves) should have been returned, {:?} was returned instead", x);
+            }
+        }
+    }
+}
+
+/// Check that a swap between two stable assets is charged the stable swap fee
+#[test]
+fn simulate_swap_stable_pair_fee() {
+    use crate::state::BASKET;
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_token_balances(&[(
+        &String::from(FAKE_LP_TOKEN_ADDRESS),
+        &[(&String::from(MOCK_CONTRACT_ADDR), &Uint128::zero())],
+    )]);
+
+    let assets = ["uusd", "uust"]
+        .iter()
+        .map(|denom| InstantiateAssetInfo {
+            info: AssetInfo::NativeToken {
+                denom: denom.to_string(),
+            },
+            address: Addr::unchecked(format!("{}_addr", denom)),
+            oracle: OracleInterface::from_dummy(1_000_000, -6),
+            ..create_instantiate_asset_info()
+        })
+        .collect();
+    let msg = InstantiateMsg {
+        assets,
+        ..create_instantiate_msg()
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.lp_token_address = Addr::unchecked(FAKE_LP_TOKEN_ADDRESS);
+    for asset in basket.assets.iter_mut() {
+        asset.available_reserves = Uint128::new(1_000_000_000);
+    }
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let simulation: SimulationResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::SimulateSwap {
+                offer_asset: Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: "uusd".to_string(),
+                    },
+                    amount: Uint128::new(1_000),
+                },
+                ask_asset: AssetInfo::NativeToken {
+                    denom: "uust".to_string(),
+                },
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(simulation.offer_fee_bps, Uint128::new(4));
+    assert_eq!(simulation.ask_fee_bps, Uint128::new(4));
+}
+
+/// Check that only the admin can halt swaps for an asset, and that swaps resume once re-enabled
+#[test]
+fn set_asset_swap_enabled() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let luna_info = AssetInfo::NativeToken {
+        denom: "luna".to_string(),
+    };
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(1_000_000),
+        },
+        ask_asset: luna_info.clone(),
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+    let set_swap_enabled = |enabled: bool| ExecuteMsg::SetAssetSwapEnabled {
+        asset: luna_info.clone(),
+        enabled,
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), set_swap_enabled(false));
+    match res {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!(
+                "Err(Unauthorized) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), set_swap_enabled(false)).unwrap();
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert!(!basket.assets[0].swap_enabled);
+    assert!(basket.assets[1].swap_enabled);
+
+    let swapper = mock_info(sender, &coins(1_000_000, "uusd"));
+    let res = execute(deps.as_mut(), mock_env(), swapper.clone(), swap.clone());
+    match res {
+        Err(ContractError::SwapDisabled { asset }) => assert!(asset.starts_with("luna ")),
+        x => {
+            panic!(
+                "Err(SwapDisabled) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), set_swap_enabled(true)).unwrap();
+    execute(deps.as_mut(), mock_env(), swapper, swap).unwrap();
+}
+
+/// Check that swaps fail while the basket is paused and succeed once it is unpaused
+#[test]
+fn swap_while_paused() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(1_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+    let swapper = mock_info(sender, &coins(1_000_000, "uusd"));
+    let admin = mock_info("name", &[]);
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), ExecuteMsg::SetPaused { paused: true });
+    match res {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!(
+                "Err(Unauthorized) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    execute(deps.as_mut(), mock_env(), admin.clone(), ExecuteMsg::SetPaused { paused: true }).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), swapper.clone(), swap.clone());
+    match res {
+        Err(ContractError::Paused) => {}
+        x => {
+            panic!(
+                "Err(Paused) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    execute(deps.as_mut(), mock_env(), admin, ExecuteMsg::SetPaused { paused: false }).unwrap();
+    execute(deps.as_mut(), mock_env(), swapper, swap).unwrap();
+}
+
+/// Check that the admin can retarget weights, which moves the fees charged afterwards
+#[test]
+fn update_asset_weights() {
+    let sender = "addr0000";
+    let mut deps = instantiate_setup(sender);
+    let luna_info = AssetInfo::NativeToken {
+        denom: "luna".to_string(),
+    };
+    let fees_for_luna_deposit = |basket: &Basket| {
+        calculate_fee_basis_points(
+            Uint128::new(100_000),
+            basket,
+            basket.swap_fee_basis_points,
+            &[Uint128::new(40_000)],
+            &vec![Uint128::new(1_000)],
+            &basket.assets[..1],
+            Action::Offer,
+        ).unwrap()
+    };
+    let initial_fees = fees_for_luna_deposit(&query_basket(deps.as_ref()).unwrap());
+
+    let update = ExecuteMsg::UpdateAssetWeights {
+        weights: vec![(luna_info, Uint128::new(3))],
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), update.clone());
+    match res {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!(
+                "Err(Unauthorized) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), update).unwrap();
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].token_weight, Uint128::new(3));
+    assert_eq!(basket.get_total_weights(), Uint128::new(4));
+    assert_eq!(basket.total_weights, Uint128::new(4));
+
+    // Luna is now further below its target, so adding it is cheaper
+    let updated_fees = fees_for_luna_deposit(&basket);
+    assert_eq!(initial_fees, vec![Uint128::new(12)]);
+    assert_eq!(updated_fees, vec![Uint128::new(8)]);
+
+    let update = ExecuteMsg::UpdateAssetWeights {
+        weights: vec![(
+            AssetInfo::NativeToken {
+                denom: "uust".to_string(),
+            },
+            Uint128::new(3),
+        )],
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("name", &[]), update);
+    match res {
+        Err(ContractError::AssetNotInBasket) => {}
+        x => {
+            panic!(
+                "Err(AssetNotInBasket) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that a zero weight is rejected for an asset with reserves, and accepted for an empty one
+#[test]
+fn zero_weight_only_for_empty_asset() {
+    use crate::state::BASKET;
+    let mut deps = funded_basket_setup("addr0000");
+    let zero_luna_weight = ExecuteMsg::UpdateAssetWeights {
+        weights: vec![(AssetInfo::NativeToken { denom: "luna".to_string() }, Uint128::zero())],
+    };
+
+    match execute(deps.as_mut(), mock_env(), mock_info("name", &[]), zero_luna_weight.clone()) {
+        Err(ContractError::ZeroWeightWithReserves { .. }) => {}
+        x => {
+            panic!("Err(ZeroWeightWithReserves) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    assert_eq!(query_basket(deps.as_ref()).unwrap().assets[0].token_weight, Uint128::new(1));
+
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].available_reserves = Uint128::zero();
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), zero_luna_weight).unwrap();
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].token_weight, Uint128::zero());
+    assert_eq!(basket.total_weights, basket.assets[1].token_weight);
+}
+
+/// Check that the admin can whitelist a new asset, whose first deposit is fee free
+#[test]
+fn add_asset() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let uust_info = AssetInfo::NativeToken {
+        denom: "uust".to_string(),
+    };
+    let add = ExecuteMsg::AddAsset {
+        asset: InstantiateAssetInfo {
+            info: uust_info.clone(),
+            address: Addr::unchecked("uust_addr"),
+            oracle: OracleInterface::from_dummy(1_000_000, -6),
+            ..create_instantiate_asset_info()
+        },
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), add.clone());
+    match res {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!(
+                "Err(Unauthorized) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), add).unwrap();
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets.len(), 3);
+    assert_eq!(basket.total_weights, Uint128::new(3));
+    assert!(basket.assets[2].info.equal(&uust_info));
+    assert_eq!(basket.assets[2].available_reserves, Uint128::zero());
+    assert_eq!(basket.assets[2].occupied_reserves, Uint128::zero());
+    assert_eq!(basket.assets[2].fee_reserves, Uint128::zero());
+
+    // 1 USD into a 2000 USD basket mints 1/2000th of the supply, with no fee taken
+    let depositor = mock_info(sender, &coins(1_000_000, "uust"));
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: uust_info,
+            amount: Uint128::new(1_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    let deposit_res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg).unwrap();
+    assert_eq!(deposit_res.attributes[4], attr("tokens_to_mint", "1000000000"));
+}
+
+/// Check that a CW20 deposit alongside a native deposit pulls in exactly the declared token amount
+#[test]
+fn cw20_deposit() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let token_addr = String::from("mir_token_address");
+    let token_info = AssetInfo::Token {
+        contract_addr: Addr::unchecked(&token_addr),
+    };
+    let add = ExecuteMsg::AddAsset {
+        asset: InstantiateAssetInfo {
+            info: token_info.clone(),
+            address: Addr::unchecked(&token_addr),
+            oracle: OracleInterface::from_dummy(1_000_000, -6),
+            ..create_instantiate_asset_info()
+        },
+    };
+    deps.querier.with_token_balances(&[
+        (
+            &String::from(FAKE_LP_TOKEN_ADDRESS),
+            &[(&String::from("first_depositor"), &Uint128::new(2_000_000_000_000))],
+        ),
+        (&token_addr, &[(&String::from(sender), &Uint128::new(5_000_000))]),
+    ]);
+    deps.querier.with_token_decimals(&[(&token_addr, 6)]);
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), add).unwrap();
+
+    let depositor = mock_info(sender, &coins(1_000_000, "luna"));
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![
+            Asset {
+                info: AssetInfo::NativeToken { denom: "luna".to_string() },
+                amount: Uint128::new(1_000_000),
+            },
+            Asset {
+                info: token_info.clone(),
+                amount: Uint128::new(5_000_000),
+            },
+        ],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg).unwrap();
+
+    let transfers: Vec<&CosmosMsg> = res
+        .messages
+        .iter()
+        .map(|sub_msg| &sub_msg.msg)
+        .filter(|msg| match msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => matches!(
+                from_binary(msg),
+                Ok(Cw20ExecuteMsg::TransferFrom { .. })
+            ),
+            _ => false,
+        })
+        .collect();
+    assert_eq!(
+        transfers,
+        vec![&CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: token_addr.clone(),
+            msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: sender.to_string(),
+                recipient: MOCK_CONTRACT_ADDR.to_string(),
+                amount: Uint128::new(5_000_000),
+            })
+            .unwrap(),
+            funds: vec![],
+        })]
+    );
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].available_reserves, Uint128::new(11_000_000));
+    assert_eq!(basket.assets[1].available_reserves, Uint128::new(1_000_000_000));
+    assert!(basket.assets[2].info.equal(&token_info));
+    assert_eq!(basket.assets[2].available_reserves, Uint128::new(5_000_000));
+}
+
+/// Check that a deposit queries a CW20's decimals once, however often the token is valued
+#[test]
+fn deposit_queries_token_decimals_once() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let token_addr = String::from("mir_token_address");
+    let token_info = AssetInfo::Token {
+        contract_addr: Addr::unchecked(&token_addr),
+    };
+    let add = ExecuteMsg::AddAsset {
+        asset: InstantiateAssetInfo {
+            info: token_info.clone(),
+            address: Addr::unchecked(&token_addr),
+            oracle: OracleInterface::from_dummy(1_000_000, -6),
+            ..create_instantiate_asset_info()
+        },
+    };
+    deps.querier.with_token_balances(&[
+        (
+            &String::from(FAKE_LP_TOKEN_ADDRESS),
+            &[(&String::from("first_depositor"), &Uint128::new(2_000_000_000_000))],
+        ),
+        (&token_addr, &[(&String::from(sender), &Uint128::new(5_000_000))]),
+    ]);
+    deps.querier.with_token_decimals(&[(&token_addr, 6)]);
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), add).unwrap();
+
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: token_info,
+            amount: Uint128::new(5_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    // AUM, the basket's token value and the deposit's value all need the decimals, which were
+    // stored when the asset was added
+    let queries_before = deps.querier.token_info_query_count(&token_addr);
+    execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), deposit_msg).unwrap();
+    assert_eq!(deps.querier.token_info_query_count(&token_addr) - queries_before, 0);
+}
+
+/// Check that auto staked deposits mint LP to the basket and stake it in the generator for the depositor
+#[test]
+fn deposit_with_auto_stake() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(1_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: Some(true),
+        deadline: None,
+    };
+
+    let depositor = mock_info(sender, &coins(1_000_000, "uusd"));
+    let res = execute(deps.as_mut(), mock_env(), depositor.clone(), deposit_msg.clone());
+    match res {
+        Err(ContractError::AutoStakeError) => {}
+        x => {
+            panic!(
+                "Err(AutoStakeError) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    let set_generator = ExecuteMsg::SetGenerator {
+        generator_address: Some("generator".to_string()),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), set_generator.clone());
+    match res {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!(
+                "Err(Unauthorized) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), set_generator).unwrap();
+
+    let res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg).unwrap();
+    let tokens_to_mint = Uint128::new(998_500_000);
+    assert_eq!(res.attributes[4], attr("tokens_to_mint", tokens_to_mint.to_string()));
+    assert_eq!(
+        res.messages.iter().map(|sub_msg| sub_msg.msg.clone()).collect::<Vec<CosmosMsg>>(),
+        vec![
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: FAKE_LP_TOKEN_ADDRESS.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Mint {
+                    recipient: MOCK_CONTRACT_ADDR.to_string(),
+                    amount: tokens_to_mint,
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: FAKE_LP_TOKEN_ADDRESS.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: "generator".to_string(),
+                    amount: tokens_to_mint,
+                    msg: to_binary(&GeneratorHookMsg::DepositFor(Addr::unchecked(sender))).unwrap(),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+        ]
+    );
+}
+
+/// Check that an added CW20 stores the decimals of its token info, and migrating backfills them
+#[test]
+fn token_decimals_stored_and_migrated() {
+    use crate::contract::migrate;
+    use crate::state::BASKET;
+    use cw20::{Cw20QueryMsg, TokenInfoResponse};
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let token_addr = String::from("mir_token_address");
+    deps.querier.with_token_balances(&[(&token_addr, &[(&String::from(sender), &Uint128::new(5_000_000))])]);
+    deps.querier.with_token_decimals(&[(&token_addr, 8)]);
+
+    let add = ExecuteMsg::AddAsset {
+        asset: InstantiateAssetInfo {
+            info: AssetInfo::Token { contract_addr: Addr::unchecked(&token_addr) },
+            address: Addr::unchecked(&token_addr),
+            oracle: OracleInterface::from_dummy(1_000_000, -6),
+            ..create_instantiate_asset_info()
+        },
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), add).unwrap();
+
+    let token_info: TokenInfoResponse = QuerierWrapper::new(&deps.querier)
+        .query_wasm_smart(&token_addr, &Cw20QueryMsg::TokenInfo {})
+        .unwrap();
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].decimals, Some(6));
+    assert_eq!(basket.assets[2].decimals, Some(token_info.decimals));
+
+    // A basket stored before decimals were has them filled in by the migration
+    for asset in basket.assets.iter_mut() {
+        asset.decimals = None;
+    }
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    let decimals: Vec<Option<u8>> = basket.assets.iter().map(|asset| asset.decimals).collect();
+    assert_eq!(decimals, vec![Some(6), Some(6), Some(8)]);
+}
+
+/// Check that a basket stored by the first release, before any of the later fields existed,
+/// loads and is completed by the migration
+#[test]
+fn migrate_legacy_basket() {
+    use crate::contract::migrate;
+    use cosmwasm_std::Storage;
+    let mut deps = mock_dependencies(&[]);
+
+    // The layout of `Basket` and `BasketAsset` in the first release
+    let zero_id = "0".repeat(64);
+    let legacy_asset = |denom: &str, price: i64| {
+        format!(
+            concat!(
+                r#"{{"info":{{"native_token":{{"denom":"{denom}"}}}},"token_weight":"1","#,
+                r#""min_profit_basis_points":"1","max_asset_amount":"100","stable_token":false,"#,
+                r#""shortable_token":true,"cumulative_funding_rate":"0","last_funding_time":"0","#,
+                r#""oracle":{{"Stub":{{"price":{price},"expo":-6}}}},"#,
+                r#""backup_oracle":{{"Stub":{{"price":{price},"expo":-6}}}},"global_short_size":"0","#,
+                r#""net_protocol_liabilities":"0","occupied_reserves":"0","fee_reserves":"0","#,
+                r#""available_reserves":"1000000","ticker_data":{{"testnet_address":"addr","#,
+                r#""mainnet_address":"addr","dummy_address":"addr","testnet_price_feed":"{id}","#,
+                r#""mainnet_price_feed":"{id}","dummy_price_feed":"{id}"}}}}"#,
+            ),
+            denom = denom,
+            price = price,
+            id = zero_id,
+        )
+    };
+    let legacy_basket = format!(
+        concat!(
+            r#"{{"assets":[{luna},{uusd}],"name":"blue chip","tax_basis_points":"1","#,
+            r#""stable_tax_basis_points":"1","mint_burn_basis_points":"15","swap_fee_basis_points":"15","#,
+            r#""stable_swap_fee_basis_points":"1","margin_fee_basis_points":"1","liquidation_fee_usd":"1","#,
+            r#""min_profit_time":"1","admin":"name","lp_token_address":"{lp}"}}"#,
+        ),
+        luna = legacy_asset("luna", 100_000_000),
+        uusd = legacy_asset("uusd", 1_000_000),
+        lp = FAKE_LP_TOKEN_ADDRESS,
+    );
+    deps.storage.set(b"basket", legacy_basket.as_bytes());
+
+    migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.total_weights, Uint128::new(2));
+    assert!(basket.lp_token_initialized);
+    assert!(!basket.paused);
+    assert_eq!(basket.lp_decimals, LP_DECIMALS);
+    assert_eq!(basket.frozen_aum, None);
+    assert_eq!(basket.large_swap_threshold_usd, None);
+    assert_eq!(basket.max_price_age, None);
+    assert_eq!(basket.last_borrow_accrual_time, mock_env().block.time.seconds());
+    for asset in basket.assets.iter() {
+        assert!(asset.swap_enabled);
+        assert!(!asset.use_ema_price);
+        assert!(!asset.fee_on_transfer);
+        assert_eq!(asset.decimals, Some(6));
+    }
+
+    // The migrated basket prices as before: 1 luna at 100 USD and 1 uusd at 1 USD
+    let res: AumResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Aum {}).unwrap()).unwrap();
+    assert_eq!(res.aum, Uint128::new(101_000_000));
+}
+
+/// Check that a deposit of a token skimming 1% on transfer is credited with the amount received
+#[test]
+fn fee_on_transfer_cw20_deposit() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let token_addr = String::from("skim_token_address");
+    let token_info = AssetInfo::Token {
+        contract_addr: Addr::unchecked(&token_addr),
+    };
+    let add = ExecuteMsg::AddAsset {
+        asset: InstantiateAssetInfo {
+            info: token_info.clone(),
+            address: Addr::unchecked(&token_addr),
+            oracle: OracleInterface::from_dummy(1_000_000, -6),
+            fee_on_transfer: true,
+            ..create_instantiate_asset_info()
+        },
+    };
+    let with_contract_balance = |deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, balance: u128| {
+        deps.querier.with_token_balances(&[
+            (
+                &String::from(FAKE_LP_TOKEN_ADDRESS),
+                &[(&String::from("first_depositor"), &Uint128::new(2_000_000_000_000))],
+            ),
+            (&token_addr, &[(&String::from(MOCK_CONTRACT_ADDR), &Uint128::new(balance))]),
+        ]);
+    };
+    with_contract_balance(&mut deps, 0);
+    deps.querier.with_token_decimals(&[(&token_addr, 6)]);
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), add).unwrap();
+
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: token_info.clone(),
+            amount: Uint128::new(5_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), deposit_msg).unwrap();
+
+    // Nothing is credited until the transfer replies
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages[0].id, 3);
+    assert_eq!(res.messages[0].reply_on, ReplyOn::Success);
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[2].available_reserves, Uint128::zero());
+
+    // The token keeps 1% of the transfer
+    with_contract_balance(&mut deps, 4_950_000);
+    let transferred = Reply {
+        id: 3,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), transferred).unwrap();
+    assert_eq!(res.attributes[1], attr("sender", sender));
+    assert_eq!(res.attributes[5], attr("usd_value", "4950000"));
+    match &res.messages[0].msg {
+        CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => match from_binary(msg).unwrap() {
+            Cw20ExecuteMsg::Mint { recipient, .. } => assert_eq!(recipient, sender),
+            _ => panic!("Expected a mint"),
+        },
+        _ => panic!("Expected WasmMsg::Execute"),
+    }
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[2].available_reserves, Uint128::new(4_950_000));
+}
+
+/// Check that deposits minting fewer LP tokens than the requested minimum are rejected
+#[test]
+fn deposit_with_min_lp_out() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let deposit_msg = |min_lp_out: u128| ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(1_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: Some(Uint128::new(min_lp_out)),
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    let depositor = mock_info(sender, &coins(1_000_000, "uusd"));
+
+    // The deposit mints 998_500_000 LP tokens after fees, one short of the minimum
+    let res = execute(deps.as_mut(), mock_env(), depositor.clone(), deposit_msg(998_500_001));
+    match res {
+        Err(ContractError::MaxSlippageAssertion) => {}
+        x => {
+            panic!(
+                "Err(MaxSlippageAssertion) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg(998_500_000)).unwrap();
+    assert_eq!(res.attributes[4], attr("tokens_to_mint", "998500000"));
+}
+
+/// Check that deposits worth less than the basket's minimum deposit value are rejected
+#[test]
+fn try_deposit_below_min_value() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    // $1 floor
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.min_deposit_value_usd = Uint128::new(1_000_000);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let deposit = |deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, amount: u128| {
+        let deposit_msg = ExecuteMsg::DepositLiquidity {
+            assets: vec![Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::new(amount),
+            }],
+            slippage_tolerance: None,
+            min_lp_out: None,
+            receiver: None,
+            auto_stake: None,
+            deadline: None,
+        };
+        let depositor = mock_info(sender, &coins(amount, "uusd"));
+        execute(deps.as_mut(), mock_env(), depositor, deposit_msg)
+    };
+
+    match deposit(&mut deps, 999_999) {
+        Err(ContractError::DepositTooSmall) => {}
+        x => {
+            panic!(
+                "Err(DepositTooSmall) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    let res = deposit(&mut deps, 1_000_000).unwrap();
+    assert_eq!(res.attributes[5], attr("usd_value", "1000000"));
+    let res = deposit(&mut deps, 2_000_000).unwrap();
+    assert_eq!(res.attributes[5], attr("usd_value", "2000000"));
+}
+
+/// Check that deposits may fill the LP supply cap but not mint past it
+#[test]
+fn deposit_with_lp_supply_cap() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    let deposit = |deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, amount: u128| {
+        let deposit_msg = ExecuteMsg::DepositLiquidity {
+            assets: vec![Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::new(amount),
+            }],
+            slippage_tolerance: None,
+            min_lp_out: None,
+            receiver: None,
+            auto_stake: None,
+            deadline: None,
+        };
+        let depositor = mock_info(sender, &coins(amount, "uusd"));
+        execute(deps.as_mut(), mock_env(), depositor, deposit_msg)
+    };
+
+    // Only the admin may set the cap
+    let set_cap = |max_lp_supply: u128| ExecuteMsg::SetLpSupplyCap {
+        max_lp_supply: Some(Uint128::new(max_lp_supply)),
+    };
+    match execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), set_cap(0)) {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!("Err(Unauthorized) should have been returned, {:?} was returned instead", x);
+        }
+    }
+
+    // One short of the LP minted by a 1 USD deposit
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), set_cap(2_000_998_499_999)).unwrap();
+    match deposit(&mut deps, 1_000_000) {
+        Err(ContractError::LpSupplyCapExceeded) => {}
+        x => {
+            panic!(
+                "Err(LpSupplyCapExceeded) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    // Room for exactly the LP minted
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), set_cap(2_000_998_500_000)).unwrap();
+    let res = deposit(&mut deps, 1_000_000).unwrap();
+    assert_eq!(res.attributes[4], attr("tokens_to_mint", "998500000"));
+}
+
+/// Check that deposit fees round up, so a fractional fee is never rounded in the depositor's favor
+#[test]
+fn deposit_fee_rounds_up() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    // 15 bps of 1.000001 USD is 1500.0015 micro USD, charged as 1501
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(1_000_001),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    let depositor = mock_info(sender, &coins(1_000_001, "uusd"));
+    let res = execute(deps.as_mut(), mock_env(), depositor, deposit_msg).unwrap();
+    assert_eq!(res.attributes[4], attr("tokens_to_mint", "998500000"));
+
+    // The helper itself only rounds up a remainder
+    assert_eq!(
+        ceil_multiply_ratio(Uint128::new(1_000_001), Uint128::new(15), BASIS_POINTS_PRECISION).unwrap(),
+        Uint128::new(1501)
+    );
+    assert_eq!(
+        ceil_multiply_ratio(Uint128::new(1_000_000), Uint128::new(15), BASIS_POINTS_PRECISION).unwrap(),
+        Uint128::new(1500)
+    );
+}
+
+/// Check that an asset already in the basket cannot be added again
+#[test]
+fn try_add_duplicate_asset() {
+    let sender = "addr0000";
+    let mut deps = instantiate_setup(sender);
+    let add = ExecuteMsg::AddAsset {
+        asset: InstantiateAssetInfo {
+            info: AssetInfo::NativeToken {
+                denom: "luna".to_string(),
+            },
+            address: Addr::unchecked("other_luna_addr"),
+            oracle: OracleInterface::from_dummy(100_000_000, -6),
+            ..create_instantiate_asset_info()
+        },
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("name", &[]), add);
+    match res {
+        Err(ContractError::DuplicateAssetAssertion) => {}
+        x => {
+            panic!(
+                "Err(DuplicateAssetAssertion) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that the admin can remove an asset with no reserves
+#[test]
+fn remove_empty_asset() {
+    let sender = "addr0000";
+    let mut deps = instantiate_setup(sender);
+    let remove = ExecuteMsg::RemoveAsset {
+        asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), remove.clone());
+    match res {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!(
+                "Err(Unauthorized) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), remove).unwrap();
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets.len(), 1);
+    assert_eq!(basket.total_weights, Uint128::new(1));
+    assert!(basket.assets[0].info.equal(&AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    }));
+}
+
+/// Check that an asset holding reserves cannot be removed
+#[test]
+fn try_remove_asset_with_reserves() {
+    let mut deps = funded_basket_setup("addr0000");
+    let remove = ExecuteMsg::RemoveAsset {
+        asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("name", &[]), remove);
+    match res {
+        Err(ContractError::AssetNotEmpty { asset }) => {
+            assert_eq!(asset, "luna (weight 1, available 10000000, occupied 0, fees 0)");
+        }
+        x => {
+            panic!(
+                "Err(AssetNotEmpty) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+    assert_eq!(query_basket(deps.as_ref()).unwrap().assets.len(), 2);
+}
+
+/// Check that the admin can repoint an asset to a pyth oracle, which then prices the asset
+#[test]
+fn update_oracle() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let pyth_contract = String::from("pyth-contract");
+    let price_id = PriceIdentifier::new([1; 32]);
+    let luna_feed = PriceFeed::new(
+        price_id,
+        PriceStatus::Trading,
+        0,
+        -6,
+        5,
+        5,
+        price_id,
+        200_000_000,
+        0,
+        200_000_000,
+        0,
+        200_000_000,
+        0,
+        0,
+    );
+    deps.querier.with_price_feeds(&[(&pyth_contract, &luna_feed)]);
+
+    let luna_info = AssetInfo::NativeToken {
+        denom: "luna".to_string(),
+    };
+    let simulate_swap = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| {
+        let simulation: SimulationResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::SimulateSwap {
+                    offer_asset: Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: "uusd".to_string(),
+                        },
+                        amount: Uint128::new(10_000_000),
+                    },
+                    ask_asset: luna_info.clone(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        simulation.return_amount
+    };
+    assert_eq!(simulate_swap(&deps), Uint128::new(99_700));
+
+    let update = ExecuteMsg::UpdateOracle {
+        asset: luna_info.clone(),
+        oracle: OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), price_id),
+        backup_oracle: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), update.clone());
+    match res {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!(
+                "Err(Unauthorized) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), update).unwrap();
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(
+        basket.assets[0].oracle,
+        OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), price_id)
+    );
+    assert_eq!(basket.assets[0].backup_oracle, OracleInterface::from_dummy(100, 0));
+
+    // Luna now prices at 200 USD, so the same offer buys about half as much
+    assert_eq!(simulate_swap(&deps), Uint128::new(49_900));
+
+    // An invalid address or an unset price id is rejected, for the backup oracle as well
+    let invalid_oracles = [
+        (OracleInterface::from_pyth(Addr::unchecked(""), price_id), None),
+        (OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), PriceIdentifier::new([0; 32])), None),
+        (
+            OracleInterface::from_dummy(100, 0),
+            Some(OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), PriceIdentifier::new([0; 32]))),
+        ),
+    ];
+    for (oracle, backup_oracle) in invalid_oracles {
+        let update = ExecuteMsg::UpdateOracle {
+            asset: luna_info.clone(),
+            oracle,
+            backup_oracle,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("name", &[]), update);
+        match res {
+            Err(ContractError::InvalidOracleConfig) => {}
+            x => {
+                panic!(
+                    "Err(InvalidOracleConfig) should have been returned, {:?} was returned instead",
+                    x
+                );
+            }
+        }
+    }
+}
+
+/// Check that the admin can set an emergency manual price, which is then used to price the asset
+#[test]
+fn set_manual_price() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let luna_info = AssetInfo::NativeToken {
+        denom: "luna".to_string(),
+    };
+    let set_price = |price: i64| ExecuteMsg::SetManualPrice {
+        asset: luna_info.clone(),
+        price,
+        expo: -6,
+    };
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), set_price(200_000_000));
+    match res {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!(
+                "Err(Unauthorized) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("name", &[]), set_price(-1));
+    match res {
+        Err(ContractError::NegativePrice) => {}
+        x => {
+            panic!(
+                "Err(NegativePrice) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), set_price(200_000_000)).unwrap();
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(
+        basket.assets[0].oracle,
+        OracleInterface::Manual {
+            price: 200_000_000,
+            expo: -6,
+            set_by: Addr::unchecked("name"),
+        }
+    );
+
+    let basket: BasketResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Basket {}).unwrap()).unwrap();
+    assert_eq!(basket.assets[0].price, Price { price: 200_000_000, conf: 0, expo: -6 });
+    assert_eq!(basket.assets[0].oracle_source, OracleSource::Manual);
+    assert_eq!(basket.assets[1].price, Price { price: 1_000_000, conf: 0, expo: -6 });
+}
+
+/// Check that the Basket query prices each asset with its oracle
+#[test]
+fn query_basket_response() {
+    let deps = funded_basket_setup("addr0000");
+
+    let basket: BasketResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Basket {}).unwrap()).unwrap();
+    assert_eq!(basket.name, "blue chip basket");
+    assert_eq!(basket.lp_token_address, Addr::unchecked(FAKE_LP_TOKEN_ADDRESS));
+    assert_eq!(basket.swap_fee_basis_points, Uint128::new(15));
+    assert_eq!(basket.assets.len(), 2);
+
+    let luna = &basket.assets[0];
+    assert_eq!(luna.available_reserves, Uint128::new(10_000_000));
+    assert_eq!(luna.price, Price { price: 100_000_000, conf: 0, expo: -6 });
+    let uusd = &basket.assets[1];
+    assert_eq!(uusd.available_reserves, Uint128::new(1_000_000_000));
+    assert_eq!(uusd.price, Price { price: 1_000_000, conf: 0, expo: -6 });
+
+    let raw_basket: Basket =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::RawBasket {}).unwrap()).unwrap();
+    assert_eq!(raw_basket, query_basket(deps.as_ref()).unwrap());
+}
+
+/// Check the imbalance of a basket holding more luna than its weight targets
+#[test]
+fn query_asset_imbalances() {
+    use crate::state::BASKET;
+    let mut deps = funded_basket_setup("addr0000");
+
+    // 3000 USD of luna against 1000 USD of uusd, with equal weights
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].available_reserves = Uint128::new(30_000_000);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let res: AssetImbalancesResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::AssetImbalances {}).unwrap()).unwrap();
+    assert_eq!(
+        res.imbalances,
+        vec![
+            AssetImbalance {
+                info: AssetInfo::NativeToken {
+                    denom: "luna".to_string(),
+                },
+                target_usd_value: Uint128::new(2_000_000_000),
+                current_usd_value: Uint128::new(3_000_000_000),
+                imbalance_usd_value: 1_000_000_000,
+            },
+            AssetImbalance {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                target_usd_value: Uint128::new(2_000_000_000),
+                current_usd_value: Uint128::new(1_000_000_000),
+                imbalance_usd_value: -1_000_000_000,
+            },
+        ]
+    );
+}
+
+/// Check that oracles shared by several assets are queried once, and that a reverse simulation
+/// queries the basket's prices once across all of its rounds, reusing them for the offer and ask.
+/// Deposits likewise price each offer asset with the prices fetched for AUM
+#[test]
+fn get_prices_queries_each_oracle_once() {
+    use crate::state::BASKET;
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_token_balances(&[(
+        &String::from(FAKE_LP_TOKEN_ADDRESS),
+        &[(&String::from(MOCK_CONTRACT_ADDR), &Uint128::new(2_000_000_000_000))],
+    )]);
+
+    let pyth_contract = String::from("pyth-contract");
+    let price_feed = |id: PriceIdentifier, price: i64| {
+        PriceFeed::new(id, PriceStatus::Trading, 0, -6, 5, 5, id, price, 0, price, 0, price, 0, 0)
+    };
+    let luna_feed = price_feed(PriceIdentifier::new([1; 32]), 100_000_000);
+    let usd_feed = price_feed(PriceIdentifier::new([2; 32]), 1_000_000);
+    deps.querier.with_price_feeds(&[(&pyth_contract, &luna_feed), (&pyth_contract, &usd_feed)]);
+
+    // uusd and uust are both priced by the same USD feed
+    let assets = [("luna", luna_feed.id), ("uusd", usd_feed.id), ("uust", usd_feed.id)]
+        .iter()
+        .map(|(denom, price_id)| InstantiateAssetInfo {
+            info: AssetInfo::NativeToken {
+                denom: denom.to_string(),
+            },
+            address: Addr::unchecked(format!("{}_addr", denom)),
+            oracle: OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), *price_id),
+            ..create_instantiate_asset_info()
+        })
+        .collect();
+    let msg = InstantiateMsg {
+        assets,
+        ..create_instantiate_msg()
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.lp_token_address = Addr::unchecked(FAKE_LP_TOKEN_ADDRESS);
+    basket.assets[0].available_reserves = Uint128::new(10_000_000);
+    basket.assets[1].available_reserves = Uint128::new(1_000_000_000);
+    basket.assets[2].available_reserves = Uint128::new(1_000_000_000);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let querier = QuerierWrapper::new(&deps.querier);
+    let prices = basket.get_prices(&querier, &mut PriceCache::default()).unwrap();
+    assert_eq!(prices.len(), 3);
+    assert_eq!(prices[1], prices[2]);
+    assert_eq!(deps.querier.pyth_query_count(), 2);
+
+    // Basket prices once, the offer and ask reuse them
+    let hits_before = deps.querier.pyth_query_count();
+    let _reverse: ReverseSimulationResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ReverseSimulateSwap {
+                ask_asset: Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: "luna".to_string(),
+                    },
+                    amount: Uint128::new(100_000),
+                },
+                offer_info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(deps.querier.pyth_query_count() - hits_before, 2);
+
+    // Without the cache the uusd offer would query the USD feed a second time
+    let hits_before = deps.querier.pyth_query_count();
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(1_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    let depositor = mock_info("addr0000", &coins(1_000_000, "uusd"));
+    execute(deps.as_mut(), mock_env(), depositor, deposit_msg).unwrap();
+    assert_eq!(deps.querier.pyth_query_count() - hits_before, 2);
+}
+
+/// Check that a routed swap returns what the same hops return as separate swaps, and charges
+/// the fees of every hop
+#[test]
+fn swap_route_through_two_hops() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let uusd = AssetInfo::NativeToken { denom: "uusd".to_string() };
+    let luna = AssetInfo::NativeToken { denom: "luna".to_string() };
+    let uatom = AssetInfo::NativeToken { denom: "uatom".to_string() };
+
+    // The funded basket with 1000 USD of atom at 10 USD
+    let setup = || {
+        let mut deps = funded_basket_setup(sender);
+        let add = ExecuteMsg::AddAsset {
+            asset: InstantiateAssetInfo {
+                info: uatom.clone(),
+                address: Addr::unchecked("atom_addr"),
+                oracle: OracleInterface::from_dummy(10_000_000, -6),
+                is_asset_stable: false,
+                ..create_instantiate_asset_info()
+            },
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("name", &[]), add).unwrap();
+        let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+        basket.assets[2].available_reserves = Uint128::new(100_000_000);
+        BASKET.save(deps.as_mut().storage, &basket).unwrap();
+        deps
+    };
+    let swap = |offer_asset: Asset, ask_asset: AssetInfo| ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset,
+        belief_price: None,
+        max_spread: None,
+        to: None,
+        ask_asset,
+        deadline: None,
+    };
+
+    // uusd -> luna -> atom as two swaps
+    let mut deps = setup();
+    let offer = Asset { info: uusd.clone(), amount: Uint128::new(10_000_000) };
+    let info = mock_info(sender, &coins(10_000_000, "uusd"));
+    let first = execute(deps.as_mut(), mock_env(), info, swap(offer.clone(), luna.clone())).unwrap();
+    let luna_amount: u128 = first.attributes[6].value.parse().unwrap();
+    let luna_offer = Asset { info: luna.clone(), amount: Uint128::new(luna_amount) };
+    let info = mock_info(sender, &coins(luna_amount, "luna"));
+    let second = execute(deps.as_mut(), mock_env(), info, swap(luna_offer, uatom.clone())).unwrap();
+    let fee_value = |res: &cosmwasm_std::Response| {
+        let offer_bps: u128 = res.attributes[7].value.parse().unwrap();
+        let ask_bps: u128 = res.attributes[8].value.parse().unwrap();
+        let bps = offer_bps + ask_bps;
+        let value = Uint128::new(res.attributes[9].value.parse().unwrap());
+        value - value.multiply_ratio(10_000 - bps, 10_000_u128)
+    };
+    let two_swaps_basket: Basket = query_basket(deps.as_ref()).unwrap();
+
+    // The same as one routed swap
+    let mut deps = setup();
+    let route = |minimum_receive: u128| ExecuteMsg::SwapRoute {
+        offer_asset: offer.clone(),
+        route: vec![luna.clone(), uatom.clone()],
+        minimum_receive: Some(Uint128::new(minimum_receive)),
+    };
+    let info = mock_info(sender, &coins(10_000_000, "uusd"));
+    match execute(deps.as_mut(), mock_env(), info.clone(), route(994_109)) {
+        Err(ContractError::MaxSpreadAssertion) => {}
+        x => {
+            panic!("Err(MaxSpreadAssertion) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    let res = execute(deps.as_mut(), mock_env(), info, route(994_108)).unwrap();
+    assert_eq!(second.attributes[6].value, "994108");
+    assert_eq!(res.attributes[4], attr("return_asset", "994108uatom"));
+    let total_fees = fee_value(&first) + fee_value(&second);
+    assert_eq!(res.attributes[5], attr("fee_usd_value", total_fees.to_string()));
+    match &res.messages[0].msg {
+        CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+            assert_eq!(to_address, sender);
+            assert_eq!(amount, &coins(994_108, "uatom"));
+        }
+        _ => panic!("Expected BankMsg"),
+    }
+    assert_eq!(query_basket(deps.as_ref()).unwrap().assets, two_swaps_basket.assets);
+
+    let msg = ExecuteMsg::SwapRoute { offer_asset: offer, route: vec![], minimum_receive: None };
+    match execute(deps.as_mut(), mock_env(), mock_info(sender, &coins(10_000_000, "uusd")), msg) {
+        Err(ContractError::EmptyRoute) => {}
+        x => {
+            panic!("Err(EmptyRoute) should have been returned, {:?} was returned instead", x);
+        }
+    }
+}
+
+/// Check that a swap adds the offer to its reserves and takes the return from the ask reserves
+#[test]
+fn swap_updates_reserves() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(10_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+    let swapper = mock_info(sender, &coins(10_000_000, "uusd"));
+    let swap_res = execute(deps.as_mut(), mock_env(), swapper, swap).unwrap();
+    assert_eq!(swap_res.attributes[6], attr("return_asset_amount", "99700"));
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].available_reserves, Uint128::new(10_000_000 - 99_700));
+    assert_eq!(basket.assets[1].available_reserves, Uint128::new(1_000_000_000 + 10_000_000));
+    assert_eq!(basket.assets[0].occupied_reserves, Uint128::zero());
+    assert_eq!(basket.assets[1].occupied_reserves, Uint128::zero());
+}
+
+/// Check that a swap reports the return implied by the belief price only when one is given
+#[test]
+fn swap_reports_expected_return() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let swap = |belief_price: Option<Decimal>| ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(10_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        to: None,
+        max_spread: None,
+        belief_price,
+        deadline: None,
+    };
+    let swapper = mock_info(sender, &coins(10_000_000, "uusd"));
+
+    let res = execute(deps.as_mut(), mock_env(), swapper.clone(), swap(Some(Decimal::percent(1)))).unwrap();
+    assert_eq!(res.attributes[6], attr("return_asset_amount", "99700"));
+    assert_eq!(res.attributes.last().unwrap(), &attr("expected_return", "100000"));
+
+    let res = execute(deps.as_mut(), mock_env(), swapper, swap(None)).unwrap();
+    assert!(res.attributes.iter().all(|attribute| attribute.key != "expected_return"));
+}
+
+/// Check that the spot price is the oracle price ratio, in token units, less the fees an empty
+/// swap is charged
+#[test]
+fn query_spot_price() {
+    use std::str::FromStr;
+    let mut deps = funded_basket_setup("addr0000");
+    let luna = AssetInfo::NativeToken { denom: "luna".to_string() };
+    let uusd = AssetInfo::NativeToken { denom: "uusd".to_string() };
+    let spot_price = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, offer: &AssetInfo, ask: &AssetInfo| {
+        let msg = QueryMsg::SpotPrice { offer: offer.clone(), ask: ask.clone() };
+        from_binary::<SpotPriceResponse>(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+    };
+
+    // luna is $100 and uusd $1, both legs are charged the 15 bps swap fee
+    let res = spot_price(&deps, &luna, &uusd);
+    assert_eq!(res.offer_fee_bps + res.ask_fee_bps, Uint128::new(30));
+    assert_eq!(res.price, Decimal::from_str("99.7").unwrap());
+    let res = spot_price(&deps, &uusd, &luna);
+    assert_eq!(res.price, Decimal::from_str("0.00997").unwrap());
+
+    // A $20 token with 8 decimals, priced with an exponent of -8
+    let token_addr = String::from("mir_token_address");
+    deps.querier.with_token_balances(&[
+        (
+            &String::from(FAKE_LP_TOKEN_ADDRESS),
+            &[(&String::from("first_depositor"), &Uint128::new(2_000_000_000_000))],
+        ),
+        (&token_addr, &[]),
+    ]);
+    deps.querier.with_token_decimals(&[(&token_addr, 8)]);
+    let mir = AssetInfo::Token { contract_addr: Addr::unchecked(&token_addr) };
+    let add = ExecuteMsg::AddAsset {
+        asset: InstantiateAssetInfo {
+            info: mir.clone(),
+            address: Addr::unchecked(&token_addr),
+            oracle: OracleInterface::from_dummy(2_000_000_000, -8),
+            backup_oracle: OracleInterface::from_dummy(20, 0),
+            ..create_instantiate_asset_info()
+        },
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), add).unwrap();
+
+    // 1 mir buys 20 uusd, so one unit of mir buys 20 * 10^6 / 10^8 units of uusd
+    let res = spot_price(&deps, &mir, &uusd);
+    let net_bps = (BASIS_POINTS_PRECISION - res.offer_fee_bps - res.ask_fee_bps).u128();
+    assert_eq!(res.price, Decimal::from_ratio(2 * net_bps, 10 * BASIS_POINTS_PRECISION.u128()));
+    let res = spot_price(&deps, &uusd, &mir);
+    let net_bps = (BASIS_POINTS_PRECISION - res.offer_fee_bps - res.ask_fee_bps).u128();
+    assert_eq!(res.price, Decimal::from_ratio(5 * net_bps, BASIS_POINTS_PRECISION.u128()));
+
+    let unknown = AssetInfo::NativeToken { denom: "uatom".to_string() };
+    match query(deps.as_ref(), mock_env(), QueryMsg::SpotPrice { offer: luna, ask: unknown }) {
+        Err(ContractError::AssetNotInBasket) => {}
+        x => {
+            panic!("Err(AssetNotInBasket) should have been returned, {:?} was returned instead", x);
+        }
+    }
+}
+
+/// Check that large swaps from one address must be spaced by the cooldown, while small swaps and
+/// other addresses are unaffected
+#[test]
+fn swap_cooldown() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let swap = |sender: &str, amount: u128| {
+        (
+            mock_info(sender, &coins(amount, "uusd")),
+            ExecuteMsg::Swap {
+                sender: Addr::unchecked(sender),
+                offer_asset: Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: "uusd".to_string(),
+                    },
+                    amount: Uint128::new(amount),
+                },
+                ask_asset: AssetInfo::NativeToken {
+                    denom: "luna".to_string(),
+                },
+                to: None,
+                max_spread: None,
+                belief_price: None,
+                deadline: None,
+            },
+        )
+    };
+
+    // Swaps of 10 USD and up are large, and must be 60 seconds apart
+    let set_cooldown = ExecuteMsg::SetSwapCooldown {
+        large_swap_usd: Some(Uint128::new(10_000_000)),
+        cooldown_seconds: 60,
+    };
+    match execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), set_cooldown.clone()) {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!("Err(Unauthorized) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), set_cooldown).unwrap();
+
+    let (info, msg) = swap(sender, 10_000_000);
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    // A second large swap 30 seconds later is too soon
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(30);
+    let (info, msg) = swap(sender, 10_000_000);
+    match execute(deps.as_mut(), env.clone(), info, msg) {
+        Err(ContractError::SwapCooldown { cooldown_seconds: 60, remaining_seconds: 30 }) => {}
+        x => {
+            panic!("Err(SwapCooldown) should have been returned, {:?} was returned instead", x);
+        }
+    }
+
+    // Small swaps, and large swaps by anyone else, go through meanwhile
+    let (info, msg) = swap(sender, 1_000_000);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let (info, msg) = swap("addr0001", 10_000_000);
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // Once the cooldown has passed the sender may swap large again
+    env.block.time = env.block.time.plus_seconds(30);
+    let (info, msg) = swap(sender, 10_000_000);
+    execute(deps.as_mut(), env, info, msg).unwrap();
+}
+
+/// Check that a swap bundled with a Pyth price update runs once the update succeeds, and fails
+/// along with it otherwise
+#[test]
+fn update_prices_then_swap() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let update_then_swap = ExecuteMsg::UpdatePricesThenSwap {
+        price_update_data: vec![Binary::from(b"vaa".to_vec())],
+        swap: Box::new(SwapParams {
+            sender: Addr::unchecked(sender),
+            offer_asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::new(10_000_000),
+            },
+            belief_price: None,
+            max_spread: None,
+            to: None,
+            ask_asset: AssetInfo::NativeToken {
+                denom: "luna".to_string(),
+            },
+            deadline: None,
+        }),
+    };
+    let swapper = mock_info(sender, &coins(10_000_000, "uusd"));
+
+    let res = execute(deps.as_mut(), mock_env(), swapper.clone(), update_then_swap.clone());
+    match res {
+        Err(ContractError::PythNotConfigured) => {}
+        x => {
+            panic!(
+                "Err(PythNotConfigured) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.pyth_contract_address = Some(Addr::unchecked("pyth-contract"));
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    // The update is posted first, the swap waits for its reply
+    let res = execute(deps.as_mut(), mock_env(), swapper.clone(), update_then_swap.clone()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg {
+            msg: WasmMsg::Execute {
+                contract_addr: "pyth-contract".to_string(),
+                msg: to_binary(&PythExecuteMsg::UpdatePriceFeeds {
+                    data: vec![Binary::from(b"vaa".to_vec())],
+                })
+                .unwrap(),
+                funds: vec![],
+            }
+            .into(),
+            id: 2,
+            gas_limit: None,
+            reply_on: ReplyOn::Always,
+        }]
+    );
+
+    let failed_update = Reply {
+        id: 2,
+        result: ContractResult::Err("invalid vaa".to_string()),
+    };
+    match reply(deps.as_mut(), mock_env(), failed_update) {
+        Err(ContractError::PriceUpdateFailed { reason }) => assert_eq!(reason, "invalid vaa"),
+        x => {
+            panic!(
+                "Err(PriceUpdateFailed) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    execute(deps.as_mut(), mock_env(), swapper, update_then_swap).unwrap();
+    let successful_update = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), successful_update).unwrap();
+    assert_eq!(res.attributes[6], attr("return_asset_amount", "99700"));
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].available_reserves, Uint128::new(10_000_000 - 99_700));
+    assert_eq!(basket.assets[1].available_reserves, Uint128::new(1_000_000_000 + 10_000_000));
+}
+
+/// Check that user actions are rejected while a swap waits on its price update, as they would be
+/// for a call back into the basket from the pyth contract
+#[test]
+fn pending_swap_locks_user_actions() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.pyth_contract_address = Some(Addr::unchecked("pyth-contract"));
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let swap_params = SwapParams {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            amount: Uint128::new(10_000_000),
+        },
+        belief_price: None,
+        max_spread: None,
+        to: None,
+        ask_asset: AssetInfo::NativeToken { denom: "luna".to_string() },
+        deadline: None,
+    };
+    let swap = ExecuteMsg::Swap {
+        sender: swap_params.sender.clone(),
+        offer_asset: swap_params.offer_asset.clone(),
+        belief_price: None,
+        max_spread: None,
+        to: None,
+        ask_asset: swap_params.ask_asset.clone(),
+        deadline: None,
+    };
+    let update_then_swap = ExecuteMsg::UpdatePricesThenSwap {
+        price_update_data: vec![Binary::from(b"vaa".to_vec())],
+        swap: Box::new(swap_params),
+    };
+    let swapper = mock_info(sender, &coins(10_000_000, "uusd"));
+    execute(deps.as_mut(), mock_env(), swapper.clone(), update_then_swap.clone()).unwrap();
+
+    // Nested swap and a second update while the first is pending
+    for msg in [swap.clone(), update_then_swap] {
+        match execute(deps.as_mut(), mock_env(), swapper.clone(), msg) {
+            Err(ContractError::Reentrancy) => {}
+            x => {
+                panic!("Err(Reentrancy) should have been returned, {:?} was returned instead", x);
+            }
+        }
+    }
+
+    // The reply releases the lock
+    let successful_update = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    reply(deps.as_mut(), mock_env(), successful_update).unwrap();
+    execute(deps.as_mut(), mock_env(), swapper, swap).unwrap();
+}
+
+/// Check that a basket quoted in luna reports its AUM in luna, while swap returns are unaffected
+#[test]
+fn basket_priced_in_luna() {
+    use crate::state::{Quote, BASKET};
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let simulate = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| -> SimulationResponse {
+        let msg = QueryMsg::SimulateSwap {
+            offer_asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::new(10_000_000),
+            },
+            ask_asset: AssetInfo::NativeToken {
+                denom: "luna".to_string(),
+            },
+        };
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+    };
+    let aum = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| -> AumResponse {
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Aum {}).unwrap()).unwrap()
+    };
+
+    // 10 luna at 100 USD and 1000 uusd at 1 USD
+    assert_eq!(aum(&deps).aum, Uint128::new(2_000_000_000));
+    let usd_simulation = simulate(&deps);
+
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.quote = Some(Quote {
+        info: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        oracle: OracleInterface::from_dummy(100_000_000, -6),
+    });
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    // The same basket is worth 20 luna
+    assert_eq!(aum(&deps).aum, Uint128::new(20_000_000));
+    assert_eq!(simulate(&deps), usd_simulation);
+
+    // A quote without a price cannot value the basket
+    basket.quote = Some(Quote {
+        info: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        oracle: OracleInterface::from_dummy(0, -6),
+    });
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    match query(deps.as_ref(), mock_env(), QueryMsg::Aum {}) {
+        Err(ContractError::ZeroPrice) => {}
+        x => {
+            panic!(
+                "Err(ZeroPrice) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that swaps into or out of a stable asset are blocked once it trades away from 1 USD
+#[test]
+fn try_swap_depegged_stable() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let swap = |offer: &str, amount: u128, ask: &str| ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: offer.to_string(),
+            },
+            amount: Uint128::new(amount),
+        },
+        ask_asset: AssetInfo::NativeToken {
+            denom: ask.to_string(),
+        },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+
+    // uusd is 0.5% off its peg, within a 1% tolerance
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.stable_peg_tolerance_bps = Uint128::new(100);
+    basket.assets[1].oracle = OracleInterface::from_dummy(995_000, -6);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    let swapper = mock_info(sender, &coins(1_000_000, "uusd"));
+    execute(deps.as_mut(), mock_env(), swapper, swap("uusd", 1_000_000, "luna")).unwrap();
+
+    // Then 5% off, both directions are blocked
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[1].oracle = OracleInterface::from_dummy(950_000, -6);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    for (offer, ask) in [("uusd", "luna"), ("luna", "uusd")] {
+        let swapper = mock_info(sender, &coins(10_000, offer));
+        match execute(deps.as_mut(), mock_env(), swapper, swap(offer, 10_000, ask)) {
+            Err(ContractError::StableDepegged) => {}
+            x => {
+                panic!(
+                    "Err(StableDepegged) should have been returned, {:?} was returned instead",
+                    x
+                );
+            }
+        }
+    }
+
+    // A zero tolerance disables the guard
+    basket.stable_peg_tolerance_bps = Uint128::zero();
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    let swapper = mock_info(sender, &coins(10_000, "luna"));
+    execute(deps.as_mut(), mock_env(), swapper, swap("luna", 10_000, "uusd")).unwrap();
+}
+
+/// Check that fee previews of both legs match the fees a swap is then charged
+#[test]
+fn fee_preview_matches_swap() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let preview = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>,
+                   denom: &str,
+                   amount: u128,
+                   action: &str| {
+        let msg = QueryMsg::FeePreview {
+            asset: AssetInfo::NativeToken {
+                denom: denom.to_string(),
+            },
+            amount: Uint128::new(amount),
+            action: action.to_string(),
+        };
+        query(deps.as_ref(), mock_env(), msg).map(|res| from_binary::<FeePreviewResponse>(&res).unwrap())
+    };
+
+    // 200 USD of uusd in, 200 USD of luna out before fees
+    let offer_preview = preview(&deps, "uusd", 200_000_000, "offer").unwrap();
+    let ask_preview = preview(&deps, "luna", 2_000_000, "ask").unwrap();
+    assert_eq!(offer_preview.value, ask_preview.value);
+
+    match preview(&deps, "uusd", 200_000_000, "buy") {
+        Err(ContractError::UnknownAction { action }) => assert_eq!(action, "buy"),
+        x => {
+            panic!(
+                "Err(UnknownAction) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+    match preview(&deps, "luna", 20_000_000, "ask") {
+        Err(ContractError::InsufficientReserves) => {}
+        x => {
+            panic!(
+                "Err(InsufficientReserves) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(200_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+    let swapper = mock_info(sender, &coins(200_000_000, "uusd"));
+    let res = execute(deps.as_mut(), mock_env(), swapper, swap).unwrap();
+    assert_eq!(res.attributes[7], attr("offer_bps", offer_preview.fee_bps.to_string()));
+    assert_eq!(res.attributes[8], attr("ask_bps", ask_preview.fee_bps.to_string()));
+}
+
+/// Check that the offer value of every swap accumulates into the lifetime swap volume
+#[test]
+fn query_stats_after_swaps() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let swap = |offer: &str, amount: u128, ask: &str| ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: offer.to_string(),
+            },
+            amount: Uint128::new(amount),
+        },
+        ask_asset: AssetInfo::NativeToken {
+            denom: ask.to_string(),
+        },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+
+    // 10 USD of uusd, then 5 USD of luna
+    let swapper = mock_info(sender, &coins(10_000_000, "uusd"));
+    execute(deps.as_mut(), mock_env(), swapper, swap("uusd", 10_000_000, "luna")).unwrap();
+    let swapper = mock_info(sender, &coins(50_000, "luna"));
+    execute(deps.as_mut(), mock_env(), swapper, swap("luna", 50_000, "uusd")).unwrap();
+
+    let stats: StatsResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Stats {}).unwrap()).unwrap();
+    assert_eq!(stats.cumulative_swap_volume_usd, Uint128::new(15_000_000));
+    // Luna is priced at 100 USD, uusd at 1 USD
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    let aum = basket.assets[0].available_reserves * Uint128::new(100) + basket.assets[1].available_reserves;
+    assert_eq!(stats.aum, aum);
+    assert_eq!(stats.lp_supply, Uint128::new(2_000_000_000_000));
+}
+
+/// Check that the config query reports the cw2 version stored at instantiation and the pause state
+#[test]
+fn query_config() {
+    let sender = "addr0000";
+    let mut deps = instantiate_setup(sender);
+    let query_config = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| -> ConfigResponse {
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap()
+    };
+
+    let config = query_config(&deps);
+    assert_eq!(config.contract_version, CONTRACT_VERSION);
+    assert_eq!(config.admin, Addr::unchecked("name"));
+    assert_eq!(config.lp_token_address, query_basket(deps.as_ref()).unwrap().lp_token_address);
+    assert_eq!(config.usd_value_precision, -6);
+    assert!(!config.paused);
+
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), ExecuteMsg::SetPaused { paused: true }).unwrap();
+    assert!(query_config(&deps).paused);
+}
+
+/// Check that borrow fees accrue on occupied reserves with utilization and elapsed time
+#[test]
+fn accrue_borrow_fees() {
+    use crate::contract::SECONDS_PER_YEAR;
+    use crate::state::BASKET;
+    let mut deps = instantiate_setup("addr0000");
+
+    // 1% base and 10% slope per year, luna half utilized for a 6% rate
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.borrow_rate_basis_points = Uint128::new(100);
+    basket.borrow_rate_slope_basis_points = Uint128::new(1_000);
+    basket.assets[0].available_reserves = Uint128::new(1_000_000);
+    basket.assets[0].occupied_reserves = Uint128::new(500_000);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let rates: BorrowRatesResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::BorrowRates {}).unwrap()).unwrap();
+    assert_eq!(rates.assets[0].borrow_rate_bps, Uint128::new(600));
+    assert_eq!(rates.assets[1].borrow_rate_bps, Uint128::new(100));
+
+    // Half a year at 6% on 500_000 occupied
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(SECONDS_PER_YEAR / 2);
+    let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), ExecuteMsg::AccrueInterest {}).unwrap();
+    assert_eq!(res.attributes[1], attr("elapsed_seconds", (SECONDS_PER_YEAR / 2).to_string()));
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].fee_reserves, Uint128::new(15_000));
+    assert_eq!(basket.assets[1].fee_reserves, Uint128::zero());
+
+    // Nothing more accrues within the same block
+    execute(deps.as_mut(), env, mock_info("anyone", &[]), ExecuteMsg::AccrueInterest {}).unwrap();
+    assert_eq!(query_basket(deps.as_ref()).unwrap().assets[0].fee_reserves, Uint128::new(15_000));
+}
+
+/// Check that the LP price is the AUM per whole LP token, and the seeding price without LP supply
+#[test]
+fn query_lp_price() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let query_lp_price = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| -> LpPriceResponse {
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::LpPrice {}).unwrap()).unwrap()
+    };
+
+    // An empty basket is priced at what the first deposit mints at, 1 USD per LP
+    let mut deps = instantiate_setup(sender);
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.lp_token_address = Addr::unchecked(FAKE_LP_TOKEN_ADDRESS);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    assert_eq!(
+        query_lp_price(&deps),
+        LpPriceResponse {
+            price: Uint128::new(1_000_000),
+            aum: Uint128::zero(),
+            lp_supply: Uint128::zero(),
+        }
+    );
+
+    // 2000 USD backing 2000 LP
+    let mut deps = funded_basket_setup(sender);
+    assert_eq!(query_lp_price(&deps).price, Uint128::new(1_000_000));
+
+    // The swap fee stays in the basket and accrues to LPs
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            amount: Uint128::new(10_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken { denom: "luna".to_string() },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(sender, &coins(10_000_000, "uusd")), swap).unwrap();
+    assert_eq!(
+        query_lp_price(&deps),
+        LpPriceResponse {
+            price: Uint128::new(1_000_015),
+            aum: Uint128::new(2_000_030_000),
+            lp_supply: Uint128::new(2_000_000_000_000),
+        }
+    );
+}
+
+/// Check that swapping into an asset whose oracle reports a price of zero fails cleanly
+#[test]
+fn try_swap_zero_price() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].oracle = OracleInterface::from_dummy(0, -6);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(10_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+    let swapper = mock_info(sender, &coins(10_000_000, "uusd"));
+    let res = execute(deps.as_mut(), mock_env(), swapper, swap);
+    match res {
+        Err(ContractError::ZeroPrice) => {}
+        x => {
+            panic!(
+                "Err(ZeroPrice) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that the short interest of each asset is reported and valued at the oracle price
+#[test]
+fn query_global_shorts() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    // Stand-in for two open luna shorts of 1 and 2 luna
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].global_short_size = Uint128::new(1_000_000 + 2_000_000);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let shorts: GlobalShortsResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::GlobalShorts {}).unwrap()).unwrap();
+    assert_eq!(
+        shorts.assets,
+        vec![
+            AssetShorts {
+                info: AssetInfo::NativeToken { denom: "luna".to_string() },
+                global_short_size: Uint128::new(3_000_000),
+                short_notional_usd: Uint128::new(300_000_000),
+            },
+            AssetShorts {
+                info: AssetInfo::NativeToken { denom: "uusd".to_string() },
+                global_short_size: Uint128::zero(),
+                short_notional_usd: Uint128::zero(),
+            },
+        ]
+    );
+    assert_eq!(shorts.total_short_notional_usd, Uint128::new(300_000_000));
+}
+
+/// Check the reserve breakdown after a swap and after reserves are occupied and fees accrued
+#[test]
+fn query_reserves() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(10_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+    let swapper = mock_info(sender, &coins(10_000_000, "uusd"));
+    execute(deps.as_mut(), mock_env(), swapper, swap).unwrap();
+
+    let query_reserves = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| -> ReservesResponse {
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Reserves {}).unwrap()).unwrap()
+    };
+    let reserves = query_reserves(&deps);
+    assert_eq!(reserves.assets.len(), 2);
+    assert_eq!(
+        reserves.assets[0],
+        AssetReserves {
+            info: AssetInfo::NativeToken {
+                denom: "luna".to_string(),
+            },
+            available_reserves: Uint128::new(9_900_300),
+            occupied_reserves: Uint128::zero(),
+            fee_reserves: Uint128::zero(),
+            max_asset_amount: Uint128::new(100),
+            utilization: Decimal::zero(),
+        }
+    );
+    assert_eq!(reserves.assets[1].available_reserves, Uint128::new(1_010_000_000));
+
+    // A quarter of the luna reserves back positions, and some fees were collected
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].occupied_reserves = Uint128::new(2_475_075);
+    basket.assets[0].fee_reserves = Uint128::new(30_000);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    let reserves = query_reserves(&deps);
+    assert_eq!(reserves.assets[0].available_reserves, Uint128::new(9_900_300));
+    assert_eq!(reserves.assets[0].occupied_reserves, Uint128::new(2_475_075));
+    assert_eq!(reserves.assets[0].fee_reserves, Uint128::new(30_000));
+    assert_eq!(reserves.assets[0].utilization, Decimal::percent(25));
+    assert_eq!(reserves.assets[1].utilization, Decimal::zero());
+}
+
+/// Check that the offer returned by a reverse simulation buys at least the requested ask amount
+#[test]
+fn reverse_simulate_swap_round_trip() {
+    let deps = funded_basket_setup("addr0000");
+
+    let luna_info = AssetInfo::NativeToken {
+        denom: "luna".to_string(),
+    };
+    let ust_info = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    };
+
+    let ask_amount = Uint128::new(99_700);
+    let reverse: ReverseSimulationResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ReverseSimulateSwap {
+                ask_asset: Asset {
+                    info: luna_info.clone(),
+                    amount: ask_amount,
+                },
+                offer_info: ust_info.clone(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(reverse.offer_fee_bps, Uint128::new(15));
+    assert_eq!(reverse.ask_fee_bps, Uint128::new(15));
+    assert!(reverse.offer_amount <= Uint128::new(10_000_000));
+
+    let forward: SimulationResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::SimulateSwap {
+                offer_asset: Asset {
+                    info: ust_info.clone(),
+                    amount: reverse.offer_amount,
+                },
+                ask_asset: luna_info.clone(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(forward.return_amount >= ask_amount);
+
+    // One unit less must not be enough, otherwise the reverse simulation overcharged
+    let forward: SimulationResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::SimulateSwap {
+                offer_asset: Asset {
+                    info: ust_info,
+                    amount: reverse.offer_amount - Uint128::new(1),
+                },
+                ask_asset: luna_info,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(forward.return_amount < ask_amount);
+}
+
+/// Check that an exact-out swap returns exactly the ask amount and refunds over-sent native funds
+#[test]
+fn swap_exact_out_refunds_unused_funds() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    let luna_info = AssetInfo::NativeToken {
+        denom: "luna".to_string(),
+    };
+    let ust_info = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    };
+    let ask_asset = Asset {
+        info: luna_info.clone(),
+        amount: Uint128::new(99_700),
+    };
+
+    let reverse: ReverseSimulationResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ReverseSimulateSwap {
+                ask_asset: ask_asset.clone(),
+                offer_info: ust_info.clone(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    // Fails when the required offer exceeds the maximum the user accepts
+    let swap = ExecuteMsg::SwapExactOut {
+        offer_asset_info: ust_info.clone(),
+        ask_asset: ask_asset.clone(),
+        max_offer_amount: reverse.offer_amount - Uint128::new(1),
+        to: None,
+    };
+    let swapper = mock_info(sender, &coins(12_000_000, "uusd"));
+    match execute(deps.as_mut(), mock_env(), swapper.clone(), swap) {
+        Err(ContractError::MaxSpreadAssertion) => {}
+        x => panic!("Err(MaxSpreadAssertion) should have been returned, {:?} was returned instead", x),
+    }
+
+    let swap = ExecuteMsg::SwapExactOut {
+        offer_asset_info: ust_info.clone(),
+        ask_asset: ask_asset.clone(),
+        max_offer_amount: Uint128::new(12_000_000),
+        to: None,
+    };
+    let swap_res = execute(deps.as_mut(), mock_env(), swapper, swap).unwrap();
+
+    assert_eq!(
+        swap_res.messages.iter().map(|m| m.msg.clone()).collect::<Vec<_>>(),
+        vec![
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: sender.to_string(),
+                amount: coins(12_000_000 - reverse.offer_amount.u128(), "uusd"),
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: sender.to_string(),
+                amount: coins(99_700, "luna"),
+            }),
+        ]
+    );
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].available_reserves, Uint128::new(10_000_000 - 99_700));
+    assert_eq!(basket.assets[1].available_reserves, Uint128::new(1_000_000_000) + reverse.offer_amount);
+}
+
+/// Check that an exact-out swap to a native asset grosses the payout up for Terra tax, so the
+/// receiver gets exactly the ask amount and the offer pays for the tax
+#[test]
+fn swap_exact_out_covers_tax() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    let ust_info = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    };
+    let ask_asset = Asset {
+        info: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        amount: Uint128::new(99_700),
+    };
+    let reverse = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| -> ReverseSimulationResponse {
+        let msg = QueryMsg::ReverseSimulateSwap {
+            ask_asset: ask_asset.clone(),
+            offer_info: ust_info.clone(),
+        };
+        from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+    };
+    let untaxed = reverse(&deps);
+
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"luna".to_string(), &Uint128::new(1_000_000))],
+    );
+    let taxed = reverse(&deps);
+    assert!(taxed.offer_amount > untaxed.offer_amount);
+
+    let swap = ExecuteMsg::SwapExactOut {
+        offer_asset_info: ust_info.clone(),
+        ask_asset: ask_asset.clone(),
+        max_offer_amount: taxed.offer_amount,
+        to: None,
+    };
+    let swapper = mock_info(sender, &coins(taxed.offer_amount.u128(), "uusd"));
+    let swap_res = execute(deps.as_mut(), mock_env(), swapper, swap).unwrap();
+
+    // 99_700 * 1.01 is sent, of which 1% tax leaves exactly the ask amount
+    assert_eq!(swap_res.messages.len(), 1);
+    assert_eq!(
+        swap_res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: sender.to_string(),
+            amount: coins(99_700, "luna"),
+        })
+    );
+    assert!(swap_res.attributes.contains(&attr("return_asset_amount", "99700")));
+    assert!(swap_res.attributes.contains(&attr("tax_amount", "997")));
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].available_reserves, Uint128::new(10_000_000 - 100_697));
+    assert_eq!(basket.assets[1].available_reserves, Uint128::new(1_000_000_000) + taxed.offer_amount);
+}
+
+/// Check that an exact-out swap rejects native funds other than its native offer
+#[test]
+fn swap_exact_out_unexpected_funds() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let swap = |offer_asset_info: AssetInfo| ExecuteMsg::SwapExactOut {
+        offer_asset_info,
+        ask_asset: Asset {
+            info: AssetInfo::NativeToken { denom: "luna".to_string() },
+            amount: Uint128::new(99_700),
+        },
+        max_offer_amount: Uint128::new(12_000_000),
+        to: None,
+    };
+    let uusd = AssetInfo::NativeToken { denom: "uusd".to_string() };
+    let token = AssetInfo::Token { contract_addr: Addr::unchecked("token0000") };
+
+    // A stray denom next to the native offer, and native funds sent with a CW20 offer
+    let stray = vec![Coin::new(12_000_000, "uusd"), Coin::new(1_000, "ukrw")];
+    let cases = [(uusd, stray), (token, coins(12_000_000, "uusd"))];
+    for (offer_asset_info, funds) in cases {
+        match execute(deps.as_mut(), mock_env(), mock_info(sender, &funds), swap(offer_asset_info)) {
+            Err(ContractError::Std(GenericErr { msg: _ })) => {}
+            x => {
+                panic!("Err(GenericErr) should have been returned, {:?} was returned instead", x);
+            }
+        }
+    }
+}
+
+/// Check that a proportional withdrawal returns each asset in proportion to its reserves
+#[test]
+fn withdraw_liquidity_proportional() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    // Burn a tenth of the LP supply
+    let withdraw = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        amount: Uint128::new(200_000_000_000),
+        sender: sender.to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidityProportional { deadline: None }).unwrap(),
+    });
+    let withdrawer = mock_info(FAKE_LP_TOKEN_ADDRESS, &[]);
+    let withdraw_res = execute(deps.as_mut(), mock_env(), withdrawer, withdraw).unwrap();
+
+    // A tenth of each reserve, less the 15 bps mint/burn fee
+    assert_eq!(
+        withdraw_res.messages.iter().map(|m| m.msg.clone()).collect::<Vec<_>>(),
+        vec![
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: sender.to_string(),
+                amount: coins(998_500, "luna"),
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: sender.to_string(),
+                amount: coins(99_850_000, "uusd"),
+            }),
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: FAKE_LP_TOKEN_ADDRESS.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Burn {
+                    amount: Uint128::new(200_000_000_000)
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+        ]
+    );
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].available_reserves, Uint128::new(10_000_000 - 998_500));
+    assert_eq!(basket.assets[1].available_reserves, Uint128::new(1_000_000_000 - 99_850_000));
+}
+
+/// Check that the LP breakdown query returns what a proportional withdrawal pays out
+#[test]
+fn query_lp_to_assets() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let lp_amount = Uint128::new(200_000_000_000);
+
+    let res: LpToAssetsResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::LpToAssets { lp_amount }).unwrap()).unwrap();
+
+    let withdraw = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        amount: lp_amount,
+        sender: sender.to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidityProportional { deadline: None }).unwrap(),
+    });
+    let withdraw_res = execute(deps.as_mut(), mock_env(), mock_info(FAKE_LP_TOKEN_ADDRESS, &[]), withdraw).unwrap();
+    let paid_out: Vec<Asset> = withdraw_res
+        .messages
+        .iter()
+        .filter_map(|message| match &message.msg {
+            CosmosMsg::Bank(BankMsg::Send { amount, .. }) => Some(Asset {
+                info: AssetInfo::NativeToken { denom: amount[0].denom.clone() },
+                amount: amount[0].amount,
+            }),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(res.assets, paid_out);
+    assert_eq!(res.assets[0].amount, Uint128::new(998_500));
+    assert_eq!(res.assets[1].amount, Uint128::new(99_850_000));
+}
+
+/// Check that a USD denominated withdrawal burns just enough LP to pay out the target value after
+/// fees, and returns the rest of the LP
+#[test]
+fn withdraw_usd_value() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let withdraw_with = |amount: u128, min_out: Option<Uint128>, deadline: Option<u64>| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            amount: Uint128::new(amount),
+            sender: sender.to_string(),
+            msg: to_binary(&Cw20HookMsg::WithdrawUsdValue {
+                target_usd: Uint128::new(100_000_000),
+                ask_asset: AssetInfo::NativeToken { denom: "uusd".to_string() },
+                min_out,
+                deadline,
+            })
+            .unwrap(),
+        })
+    };
+    let withdraw = |amount: u128| withdraw_with(amount, None, None);
+    let withdrawer = mock_info(FAKE_LP_TOKEN_ADDRESS, &[]);
+
+    // 100 USD is about 5% of the 2000 USD basket
+    match execute(deps.as_mut(), mock_env(), withdrawer.clone(), withdraw(50_000_000_000)) {
+        Err(ContractError::InsufficientLpSent { .. }) => {}
+        x => {
+            panic!("Err(InsufficientLpSent) should have been returned, {:?} was returned instead", x);
+        }
+    }
+
+    // 100 USD of uusd can't pay out more than 100 uusd
+    let too_much = withdraw_with(300_000_000_000, Some(Uint128::new(100_000_002)), None);
+    match execute(deps.as_mut(), mock_env(), withdrawer.clone(), too_much) {
+        Err(ContractError::MaxSlippageAssertion) => {}
+        x => {
+            panic!("Err(MaxSlippageAssertion) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    let expired = withdraw_with(300_000_000_000, None, Some(mock_env().block.time.seconds() - 1));
+    match execute(deps.as_mut(), mock_env(), withdrawer.clone(), expired) {
+        Err(ContractError::Expired) => {}
+        x => {
+            panic!("Err(Expired) should have been returned, {:?} was returned instead", x);
+        }
+    }
+
+    let in_time = withdraw_with(
+        300_000_000_000,
+        Some(Uint128::new(100_000_000)),
+        Some(mock_env().block.time.seconds()),
+    );
+    let res = execute(deps.as_mut(), mock_env(), withdrawer, in_time).unwrap();
+    let attribute = |key: &str| res.attributes.iter().find(|attribute| attribute.key == key).unwrap().value.clone();
+    let lp_burned: u128 = attribute("lp_burned").parse().unwrap();
+    let usd_value: u128 = attribute("usd_value").parse().unwrap();
+    assert!((100_000_000..=100_000_001).contains(&usd_value), "{} paid out", usd_value);
+    assert!(lp_burned > 100_000_000_000 && lp_burned < 101_000_000_000);
+    assert_eq!(
+        res.messages.iter().map(|m| m.msg.clone()).collect::<Vec<_>>()[1..],
+        [
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: FAKE_LP_TOKEN_ADDRESS.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Burn { amount: Uint128::new(lp_burned) }).unwrap(),
+                funds: vec![],
+            }),
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: FAKE_LP_TOKEN_ADDRESS.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: sender.to_string(),
+                    amount: Uint128::new(300_000_000_000 - lp_burned),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+        ]
+    );
+}
+
+/// Check that the native return of a swap is sent net of Terra tax
+#[test]
+fn swap_deducts_tax_from_native_return() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"luna".to_string(), &Uint128::new(1_000_000))],
+    );
+
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            amount: Uint128::new(10_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken { denom: "luna".to_string() },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+    let swapper = mock_info(sender, &coins(10_000_000, "uusd"));
+    let swap_res = execute(deps.as_mut(), mock_env(), swapper, swap).unwrap();
+
+    // 99_700 luna gross, less 1% tax: 99_700 - 99_700 / 1.01
+    assert_eq!(&swap_res.attributes[6].value, "99700");
+    match &swap_res.messages[0].msg {
+        CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+            assert_eq!(to_address, sender);
+            assert_eq!(amount, &coins(98_712, "luna"));
+        }
+        _ => panic!("Expected BankMsg"),
+    }
+}
+
+/// Check that while the oracles are down, withdrawals redeem LP at the frozen AUM and deposits and
+/// swaps are held, until the admin unfreezes it
+#[test]
+fn freeze_aum() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let aum = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| {
+        let res: AumResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Aum {}).unwrap()).unwrap();
+        res.aum
+    };
+    assert_eq!(aum(&deps), Uint128::new(2_000_000_000));
+
+    // Take the luna oracles down: the mocked pyth contract has no feed for luna's price id
+    let pyth_contract = String::from("pyth-contract");
+    let other_id = PriceIdentifier::new([2; 32]);
+    let other_feed =
+        PriceFeed::new(other_id, PriceStatus::Trading, 0, -6, 5, 5, other_id, 1, 0, 1, 0, 1, 0, 0);
+    deps.querier.with_price_feeds(&[(&pyth_contract, &other_feed)]);
+    let mut basket = query_basket(deps.as_ref()).unwrap();
+    let live_oracles = (basket.assets[0].oracle.clone(), basket.assets[0].backup_oracle.clone());
+    let down = OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), PriceIdentifier::new([1; 32]));
+    basket.assets[0].oracle = down.clone();
+    basket.assets[0].backup_oracle = down;
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    assert!(query(deps.as_ref(), mock_env(), QueryMsg::Aum {}).is_err());
+
+    let freeze = ExecuteMsg::FreezeAum { value: Uint128::new(1_000_000_000) };
+    match execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), freeze.clone()) {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!("Err(Unauthorized) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    let res = execute(deps.as_mut(), mock_env(), mock_info("name", &[]), freeze).unwrap();
+    assert_eq!(res.attributes[1], attr("aum_frozen", "true"));
+    assert_eq!(aum(&deps), Uint128::new(1_000_000_000));
+
+    // 10% of the LP supply redeems 10% of the frozen AUM, less the withdrawal fee
+    let withdraw = |denom: &str| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            amount: Uint128::new(200_000_000_000),
+            sender: sender.to_string(),
+            msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+                asset: AssetInfo::NativeToken { denom: denom.to_string() },
+                min_out: None,
+                deadline: None,
+                receiver: None,
+            })
+            .unwrap(),
+        })
+    };
+    let withdrawer = mock_info(FAKE_LP_TOKEN_ADDRESS, &[]);
+    let withdraw_res = execute(deps.as_mut(), mock_env(), withdrawer.clone(), withdraw("uusd")).unwrap();
+    assert_eq!(&withdraw_res.attributes[2].value, "99990000uusd");
+    // luna still has no price to pay it out at
+    assert!(execute(deps.as_mut(), mock_env(), withdrawer, withdraw("luna")).is_err());
+
+    // 50 USD of the 1000 USD frozen AUM takes about 5% of the LP supply
+    let withdraw_usd = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        amount: Uint128::new(200_000_000_000),
+        sender: sender.to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawUsdValue {
+            target_usd: Uint128::new(50_000_000),
+            ask_asset: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            min_out: None,
+            deadline: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(deps.as_mut(), mock_env(), mock_info(FAKE_LP_TOKEN_ADDRESS, &[]), withdraw_usd).unwrap();
+    let lp_burned = &res.attributes.iter().find(|attribute| attribute.key == "lp_burned").unwrap().value;
+    assert_eq!(lp_burned, "100020006000");
+
+    // Deposits, swaps and LP pricing are held
+    let deposit = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            amount: Uint128::new(1_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            amount: Uint128::new(1_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken { denom: "luna".to_string() },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+    for (info, msg) in [
+        (mock_info(sender, &coins(1_000_000, "uusd")), deposit),
+        (mock_info(sender, &coins(1_000_000, "uusd")), swap),
+    ] {
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::AumFrozen) => {}
+            x => {
+                panic!("Err(AumFrozen) should have been returned, {:?} was returned instead", x);
+            }
+        }
+    }
+    match query(deps.as_ref(), mock_env(), QueryMsg::LpPrice {}) {
+        Err(ContractError::AumFrozen) => {}
+        x => {
+            panic!("Err(AumFrozen) should have been returned, {:?} was returned instead", x);
+        }
+    }
+
+    // A proportional withdrawal needs no prices either
+    let withdraw = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        amount: Uint128::new(200_000_000_000),
+        sender: sender.to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidityProportional { deadline: None }).unwrap(),
+    });
+    let withdrawer = mock_info(FAKE_LP_TOKEN_ADDRESS, &[]);
+    let withdraw_res = execute(deps.as_mut(), mock_env(), withdrawer, withdraw).unwrap();
+    assert_eq!(
+        withdraw_res.attributes[2],
+        attr("redemption_assets", "998500luna,84873498uusd")
+    );
+
+    // Back at the oracle prices once they are restored, less the assets paid out
+    let mut basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].oracle = live_oracles.0;
+    basket.assets[0].backup_oracle = live_oracles.1;
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("name", &[]), ExecuteMsg::UnfreezeAum {}).unwrap();
+    assert_eq!(res.attributes[1], attr("aum_frozen", "false"));
+    assert_eq!(aum(&deps), Uint128::new(1_665_286_500));
+}
+
+/// Check that fees accrued into fee_reserves neither count toward the AUM nor can be withdrawn
+#[test]
+fn fee_reserves_excluded_from_aum() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let aum = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| {
+        let res: AumResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Aum {}).unwrap()).unwrap();
+        res.aum
+    };
+    let withdraw = |amount: u128| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            amount: Uint128::new(amount),
+            sender: sender.to_string(),
+            msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+                asset: AssetInfo::NativeToken { denom: "uusd".to_string() },
+                min_out: None,
+                deadline: None,
+                receiver: None,
+            })
+            .unwrap(),
+        })
+    };
+
+    // A year of 10% borrow fees on 100 USD of occupied uusd
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[1].available_reserves = Uint128::new(900_000_000);
+    basket.assets[1].occupied_reserves = Uint128::new(100_000_000);
+    basket.borrow_rate_basis_points = Uint128::new(1_000);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    assert_eq!(aum(&deps), Uint128::new(2_000_000_000));
+
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(31_536_000);
+    execute(deps.as_mut(), env, mock_info(sender, &[]), ExecuteMsg::AccrueInterest {}).unwrap();
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[1].fee_reserves, Uint128::new(10_000_000));
+    assert_eq!(aum(&deps), Uint128::new(2_000_000_000));
+
+    // 5% of the LP supply redeems 5% of the AUM as before the fee, less the withdrawal fee
+    let withdrawer = mock_info(FAKE_LP_TOKEN_ADDRESS, &[]);
+    let res = execute(deps.as_mut(), mock_env(), withdrawer.clone(), withdraw(100_000_000_000)).unwrap();
+    assert_eq!(&res.attributes[2].value, "99850000uusd");
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[1].fee_reserves, Uint128::new(10_000_000));
+
+    // Redeeming more uusd than its available reserves fails, the fee reserves don't cover it
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[1].available_reserves = Uint128::new(50_000_000);
+    basket.assets[1].occupied_reserves = Uint128::zero();
+    basket.assets[1].fee_reserves = Uint128::new(1_000_000_000);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    match execute(deps.as_mut(), mock_env(), withdrawer, withdraw(100_000_000_000)) {
+        Err(ContractError::InsufficientReserves) => {}
+        x => {
+            panic!("Err(InsufficientReserves) should have been returned, {:?} was returned instead", x);
+        }
+    }
+}
+
+/// Check that the smallest redeemable LP amount pays out one unit, and that withdrawing less
+/// than it is rejected instead of burning the LP for nothing
+#[test]
+fn min_redeemable_lp() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let withdraw = |amount: Uint128| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            amount,
+            sender: sender.to_string(),
+            msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+                asset: AssetInfo::NativeToken { denom: "uusd".to_string() },
+                min_out: None,
+                deadline: None,
+                receiver: None,
+            })
+            .unwrap(),
+        })
+    };
+
+    let msg = QueryMsg::MinRedeemableLp { ask_asset: AssetInfo::NativeToken { denom: "uusd".to_string() } };
+    let res: MinRedeemableLpResponse = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(res.lp_amount, Uint128::new(2_000));
+
+    let withdrawer = mock_info(FAKE_LP_TOKEN_ADDRESS, &[]);
+    match execute(deps.as_mut(), mock_env(), withdrawer.clone(), withdraw(res.lp_amount - Uint128::new(1))) {
+        Err(ContractError::InvalidZeroAmount) => {}
+        x => {
+            panic!("Err(InvalidZeroAmount) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    let res = execute(deps.as_mut(), mock_env(), withdrawer, withdraw(res.lp_amount)).unwrap();
+    assert_eq!(&res.attributes[2].value, "1uusd");
+}
+
+/// Check that a rebalancing deposit goes to the asset furthest below its target, and refunds
+/// whatever was attached on top of the deposit
+#[test]
+fn deposit_to_underweight() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    // Halve the luna reserves, 500 USD of luna against 1000 USD of uusd
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].available_reserves = Uint128::new(5_000_000);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+
+    // 10 USD is 0.1 luna
+    let deposit = ExecuteMsg::DepositToUnderweight {
+        value_usd: Uint128::new(10_000_000),
+        slippage_tolerance: None,
+    };
+    match execute(deps.as_mut(), mock_env(), mock_info(sender, &coins(10_000_000, "uusd")), deposit.clone()) {
+        Err(ContractError::Std(_)) => {}
+        x => {
+            panic!("Err(Std) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &coins(150_000, "luna")), deposit).unwrap();
+    let attribute = |key: &str| res.attributes.iter().find(|attribute| attribute.key == key).unwrap().value.clone();
+    assert_eq!(attribute("underweight_asset"), "luna");
+    assert_eq!(attribute("underweight_amount"), "100000");
+    match &res.messages.last().unwrap().msg {
+        CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+            assert_eq!(to_address, sender);
+            assert_eq!(amount, &coins(50_000, "luna"));
+        }
+        _ => panic!("Expected BankMsg"),
+    }
+
+    let basket: Basket = query_basket(deps.as_ref()).unwrap();
+    assert_eq!(basket.assets[0].available_reserves, Uint128::new(5_100_000));
+}
+
+/// Check that a deposit's cost basis is kept against the LP it minted, shows a gain once the
+/// basket is priced up, and shrinks with the LP withdrawn
+#[test]
+fn lp_pnl() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let lp_pnl = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| -> LpPnlResponse {
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::LpPnl { user: sender.to_string() }).unwrap()).unwrap()
+    };
+    assert_eq!(lp_pnl(&deps).lp_amount, Uint128::zero());
+
+    let deposit = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            amount: Uint128::new(10_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(sender, &coins(10_000_000, "uusd")), deposit).unwrap();
+    let minted = Uint128::new(res.attributes[4].value.parse().unwrap());
+    let pnl = lp_pnl(&deps);
+    assert_eq!(pnl.lp_amount, minted);
+    assert_eq!(pnl.cost_basis_usd, Uint128::new(10_000_000));
+
+    // Double the luna price, the basket is worth 3000 USD instead of 2000 USD
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].oracle = OracleInterface::from_dummy(200_000_000, -6);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    let pnl = lp_pnl(&deps);
+    assert!(pnl.pnl_usd > 0);
+    assert_eq!(pnl.pnl_usd, pnl.current_value_usd.u128() as i64 - 10_000_000);
+
+    // Withdrawing half the LP takes half the cost basis with it
+    let withdraw = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        amount: minted.multiply_ratio(1_u128, 2_u128),
+        sender: sender.to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            asset: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            min_out: None,
+            deadline: None,
+            receiver: None,
+        })
+        .unwrap(),
+    });
+    execute(deps.as_mut(), mock_env(), mock_info(FAKE_LP_TOKEN_ADDRESS, &[]), withdraw).unwrap();
+    let pnl = lp_pnl(&deps);
+    assert_eq!(pnl.lp_amount, minted - minted.multiply_ratio(1_u128, 2_u128));
+    assert_eq!(pnl.cost_basis_usd, Uint128::new(5_000_000));
+    assert!(pnl.pnl_usd > 0);
+}
+
+/// Check that the tax estimate matches what a native transfer is charged, and is zero for CW20s
+#[test]
+fn query_estimate_tax() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::new(10_000_000))],
+    );
+    let estimate_tax = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, info: AssetInfo| {
+        let asset = Asset { info, amount: Uint128::new(199_680_000) };
+        let res: EstimateTaxResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::EstimateTax { asset }).unwrap()).unwrap();
+        res.tax_amount
+    };
+
+    // Same as `withdraw_liquidity_deducts_tax`
+    let native = AssetInfo::NativeToken { denom: "uusd".to_string() };
+    assert_eq!(estimate_tax(&deps, native), Uint128::new(1_977_030));
+
+    let token = AssetInfo::Token { contract_addr: Addr::unchecked("asset0000") };
+    assert_eq!(estimate_tax(&deps, token), Uint128::zero());
+}
+
+/// Check that a withdrawal can pay out to another address while burning the sender's LP
+#[test]
+fn withdraw_liquidity_to_receiver() {
+    let sender = "addr0000";
+    let receiver = "addr0001";
+    let mut deps = funded_basket_setup(sender);
+
+    let withdraw = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        amount: Uint128::new(200_000_000_000),
+        sender: sender.to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            asset: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            min_out: None,
+            deadline: None,
+            receiver: Some(receiver.to_string()),
+        })
+        .unwrap(),
+    });
+    let withdrawer = mock_info(FAKE_LP_TOKEN_ADDRESS, &[]);
+    let withdraw_res = execute(deps.as_mut(), mock_env(), withdrawer, withdraw).unwrap();
+
+    assert_eq!(&withdraw_res.attributes[1].value, sender);
+    match &withdraw_res.messages[0].msg {
+        CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+            assert_eq!(to_address, receiver);
+            assert_eq!(amount, &coins(199_680_000, "uusd"));
+        }
+        _ => panic!("Expected BankMsg"),
+    }
+    match &withdraw_res.messages[1].msg {
+        CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, .. }) => {
+            assert_eq!(contract_addr, FAKE_LP_TOKEN_ADDRESS);
+            assert_eq!(
+                from_binary::<Cw20ExecuteMsg>(msg).unwrap(),
+                Cw20ExecuteMsg::Burn { amount: Uint128::new(200_000_000_000) }
+            );
+        }
+        _ => panic!("Expected burn of the sender's LP"),
+    }
+}
+
+/// Check that a native withdrawal is sent net of Terra tax, that the tax cap applies, and that
+/// the tax is reported
+#[test]
+fn withdraw_liquidity_deducts_tax_from_native_payout() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::new(1_000_000))],
+    );
+
+    let withdraw = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        amount: Uint128::new(200_000_000_000),
+        sender: sender.to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            asset: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            min_out: None,
+            deadline: None,
+            receiver: None,
+        })
+        .unwrap(),
+    });
+    let withdrawer = mock_info(FAKE_LP_TOKEN_ADDRESS, &[]);
+    let withdraw_res = execute(deps.as_mut(), mock_env(), withdrawer, withdraw).unwrap();
+
+    // 1% of the gross payout is more than the cap, so exactly the cap is deducted and reported
+    assert_eq!(&withdraw_res.attributes[2].value, "199680000uusd");
+    assert_eq!(withdraw_res.attributes[6], attr("tax_amount", "1000000"));
+    match &withdraw_res.messages[0].msg {
+        CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+            assert_eq!(to_address, sender);
+            assert_eq!(amount, &coins(199_680_000 - 1_000_000, "uusd"));
+        }
+        _ => panic!("Expected BankMsg"),
+    }
+}
+
+#[test]
+fn test_from_binary() {
+    match from_binary(&Binary::from_base64("eyJ3aXRoZHJhd19saXF1aWRpdHkiOnsiYXNzZXQiOnsibmF0aXZlX3Rva2VuIjp7ImRlbm9tIjoidXVzZCJ9fX19").unwrap()) {
+        Ok(Cw20HookMsg::WithdrawLiquidity { asset, min_out, deadline, receiver }) => {
+            assert_eq!(min_out, None);
+            assert_eq!(deadline, None);
+            assert_eq!(receiver, None);
+            assert_eq!(asset, AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            });
+        },
+        _ => assert!(false, "Expected WithdrawLiquidity"),
+    }
+}
+
+/// Check that every query response exported by examples/schema.rs round-trips through
+/// `from_binary` and `to_binary` unchanged
+#[test]
+fn query_responses_round_trip() {
+    use serde::{de::DeserializeOwned, Serialize};
+    fn round_trip<T: Serialize + DeserializeOwned>(deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, msg: QueryMsg) {
+        let binary = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let response: T = from_binary(&binary).unwrap();
+        assert_eq!(to_binary(&response).unwrap(), binary);
+    }
+
+    let sender = "addr0000";
+    let deps = funded_basket_setup(sender);
+    let luna = AssetInfo::NativeToken { denom: "luna".to_string() };
+    let uusd = AssetInfo::NativeToken { denom: "uusd".to_string() };
+    let one_usd = Asset { info: uusd.clone(), amount: Uint128::new(1_000_000) };
+
+    round_trip::<Basket>(&deps, QueryMsg::RawBasket {});
+    round_trip::<BasketResponse>(&deps, QueryMsg::Basket {});
+    round_trip::<SimulationResponse>(
+        &deps,
+        QueryMsg::SimulateSwap { offer_asset: one_usd.clone(), ask_asset: luna.clone() },
+    );
+    round_trip::<ReverseSimulationResponse>(
+        &deps,
+        QueryMsg::ReverseSimulateSwap { ask_asset: one_usd.clone(), offer_info: luna.clone() },
+    );
+    round_trip::<AssetImbalancesResponse>(&deps, QueryMsg::AssetImbalances {});
+    round_trip::<AssetPriceResponse>(&deps, QueryMsg::AssetPrice { asset: luna.clone() });
+    round_trip::<StatsResponse>(&deps, QueryMsg::Stats {});
+    round_trip::<AumResponse>(&deps, QueryMsg::Aum {});
+    round_trip::<LpPriceResponse>(&deps, QueryMsg::LpPrice {});
+    round_trip::<ReservesResponse>(&deps, QueryMsg::Reserves {});
+    round_trip::<FeePreviewResponse>(
+        &deps,
+        QueryMsg::FeePreview { asset: uusd.clone(), amount: Uint128::new(1_000_000), action: "offer".to_string() },
+    );
+    round_trip::<GlobalShortsResponse>(&deps, QueryMsg::GlobalShorts {});
+    round_trip::<ConfigResponse>(&deps, QueryMsg::Config {});
+    round_trip::<BorrowRatesResponse>(&deps, QueryMsg::BorrowRates {});
+    round_trip::<EstimateTaxResponse>(&deps, QueryMsg::EstimateTax { asset: one_usd });
+    round_trip::<LpPnlResponse>(&deps, QueryMsg::LpPnl { user: sender.to_string() });
+    round_trip::<LpToAssetsResponse>(&deps, QueryMsg::LpToAssets { lp_amount: Uint128::new(1_000_000) });
+    round_trip::<SpotPriceResponse>(&deps, QueryMsg::SpotPrice { offer: luna, ask: uusd.clone() });
+    round_trip::<MinRedeemableLpResponse>(&deps, QueryMsg::MinRedeemableLp { ask_asset: uusd });
+}
+
+#[test]
+fn priced_asset_value_of_large_high_decimal_reserves() {
+    let mut deps = mock_dependencies(&[]);
+    let token_addr = "eth-token-address".to_string();
+    deps.querier.with_token_balances(&[(&token_addr, &[])]);
+    deps.querier.with_token_decimals(&[(&token_addr, 18)]);
+
+    // One billion tokens with 18 decimals at $3,000 each
+    let amount = Uint128::new(1_000_000_000 * 10_u128.pow(18));
+    let info = AssetInfo::Token { contract_addr: Addr::unchecked(&token_addr) };
+    let mut basket_asset = create_basket_asset();
+    basket_asset.info = info.clone();
+    basket_asset.oracle = OracleInterface::from_dummy(300_000_000_000, -8);
+    basket_asset.available_reserves = amount;
+    basket_asset.occupied_reserves = amount;
+
+    let querier = QuerierWrapper::new(&deps.querier);
+    let mut priced_asset = PricedAsset::new(Asset { info, amount }, basket_asset);
+
+    // amount * 10^6 alone no longer fits in a Uint128, but the resulting values do
+    assert_eq!(
+        priced_asset.query_value(&querier).unwrap(),
+        Uint128::new(3_000_000_000_000 * 10_u128.pow(6))
+    );
+    assert_eq!(
+        priced_asset.query_contract_value(&querier).unwrap(),
+        Uint128::new(6_000_000_000_000 * 10_u128.pow(6))
+    );
+}
+
+#[test]
+fn priced_asset_value_overflowing_uint128() {
+    let deps = mock_dependencies(&[]);
+
+    // The full Uint128 range of uluna at $10,000 each is worth more than a Uint128 can hold
+    let amount = Uint128::MAX;
+    let info = AssetInfo::NativeToken { denom: "uluna".to_string() };
+    let mut basket_asset = create_basket_asset();
+    basket_asset.oracle = OracleInterface::from_dummy(1_000_000_000_000, -8);
+
+    let querier = QuerierWrapper::new(&deps.querier);
+    let mut priced_asset = PricedAsset::new(Asset { info, amount }, basket_asset);
+
+    match priced_asset.query_value(&querier) {
+        Err(ContractError::FailedCast) => {}
+        x => {
+            panic!("Err(FailedCast) should have been returned, {:?} was returned instead", x);
+        }
+    }
+}
+
+/// Check that assets flagged with `use_ema_price` are valued at the EMA price for AUM, while swap
+/// execution keeps pricing them at spot
+#[test]
+fn ema_price_mode() {
+    let mut deps = mock_dependencies(&[]);
+    let pyth_contract = String::from("pyth-contract");
+    let price_id = PriceIdentifier::new([1; 32]);
+    // Spot luna price of 100 USD, EMA of 80 USD
+    let luna_feed = PriceFeed::new(
+        price_id,
+        PriceStatus::Trading,
+        0,
+        -6,
+        5,
+        5,
+        price_id,
+        100_000_000,
+        0,
+        80_000_000,
+        0,
+        100_000_000,
+        0,
+        0,
+    );
+    deps.querier.with_price_feeds(&[(&pyth_contract, &luna_feed)]);
+    let querier = QuerierWrapper::new(&deps.querier);
+
+    let mut basket = create_basket();
+    for asset in basket.assets.iter_mut() {
+        asset.oracle = OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), price_id);
+        asset.available_reserves = Uint128::new(1_000_000);
+    }
+    basket.assets[1].use_ema_price = true;
+
+    let prices = basket.get_prices(&querier, &mut PriceCache::default()).unwrap();
+    assert_eq!(prices[0], Price { price: 100_000_000, conf: 0, expo: -6 });
+    assert_eq!(prices[1], Price { price: 80_000_000, conf: 0, expo: -6 });
+
+    // 1 luna valued at spot plus 1 luna valued at EMA
+    let aum = basket.calculate_aum(&querier, &mut PriceCache::default()).unwrap().to_Uint128(-6).unwrap();
+    assert_eq!(aum, Uint128::new(180_000_000));
+
+    // Swap execution prices the EMA flagged asset at spot
+    let mut priced_asset = PricedAsset::new(
+        Asset {
+            info: basket.assets[1].info.clone(),
+            amount: Uint128::new(1_000_000),
+        },
+        basket.assets[1].clone(),
+    );
+    assert_eq!(priced_asset.query_value(&querier).unwrap(), Uint128::new(100_000_000));
+}
+
+/// Check that assets are priced when their primary and backup oracles agree within
+/// `max_oracle_deviation_bps`, and rejected when they diverge
+#[test]
+fn oracle_deviation_circuit_breaker() {
+    let mut deps = mock_dependencies(&[]);
+    // The mocked pyth contract has no feed for the price id used below
+    let pyth_contract = String::from("pyth-contract");
+    let other_id = PriceIdentifier::new([2; 32]);
+    let other_feed =
+        PriceFeed::new(other_id, PriceStatus::Trading, 0, -6, 5, 5, other_id, 1, 0, 1, 0, 1, 0, 0);
+    deps.querier.with_price_feeds(&[(&pyth_contract, &other_feed)]);
+    let querier = QuerierWrapper::new(&deps.querier);
+
+    let mut basket = create_basket();
+    basket.assets[1].info = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    };
+    basket.max_oracle_deviation_bps = Uint128::new(200);
+    for asset in basket.assets.iter_mut() {
+        asset.oracle = OracleInterface::from_dummy(100_000_000, -6);
+        asset.backup_oracle = OracleInterface::from_dummy(101, 0);
+    }
+
+    // Backup is 1% off the primary, the primary price is used
+    let prices = basket.get_prices(&querier, &mut PriceCache::default()).unwrap();
+    assert_eq!(prices[0], Price { price: 100_000_000, conf: 0, expo: -6 });
+
+    // Backup is 50% off the primary
+    basket.assets[1].backup_oracle = OracleInterface::from_dummy(150, 0);
+    match basket.get_prices(&querier, &mut PriceCache::default()) {
+        Err(ContractError::OracleDeviationTooHigh) => {}
+        x => {
+            panic!(
+                "Err(OracleDeviationTooHigh) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    // A zero threshold disables the check
+    basket.max_oracle_deviation_bps = Uint128::zero();
+    assert!(basket.get_prices(&querier, &mut PriceCache::default()).is_ok());
+
+    // A failing primary oracle falls back to the backup
+    basket.max_oracle_deviation_bps = Uint128::new(200);
+    basket.assets[1].oracle =
+        OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), PriceIdentifier::new([1; 32]));
+    let prices = basket.get_prices(&querier, &mut PriceCache::default()).unwrap();
+    assert_eq!(prices[1], Price { price: 150, conf: 0, expo: 0 });
+}
+
+/// Check that the basket query reports which oracle priced each asset
+#[test]
+fn basket_query_reports_oracle_source() {
+    use crate::state::BASKET;
+    let mut deps = funded_basket_setup("addr0000");
+    let pyth_contract = String::from("pyth-contract");
+    let other_id = PriceIdentifier::new([2; 32]);
+    let other_feed =
+        PriceFeed::new(other_id, PriceStatus::Trading, 0, -6, 5, 5, other_id, 1, 0, 1, 0, 1, 0, 0);
+    deps.querier.with_price_feeds(&[(&pyth_contract, &other_feed)]);
+    let query_sources = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| -> Vec<OracleSource> {
+        let basket: BasketResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Basket {}).unwrap()).unwrap();
+        basket.assets.iter().map(|asset| asset.oracle_source).collect()
+    };
+    assert_eq!(query_sources(&deps), vec![OracleSource::Primary, OracleSource::Primary]);
+
+    // The luna feed is missing from the pyth contract
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].oracle =
+        OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), PriceIdentifier::new([1; 32]));
+    basket.assets[0].backup_oracle = OracleInterface::from_dummy(99_000_000, -6);
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    assert_eq!(query_sources(&deps), vec![OracleSource::Backup, OracleSource::Primary]);
+    let basket: BasketResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Basket {}).unwrap()).unwrap();
+    assert_eq!(basket.assets[0].price, Price { price: 99_000_000, conf: 0, expo: -6 });
+
+    // EMA prices are tagged as such
+    let mut basket: Basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[1].use_ema_price = true;
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    assert_eq!(query_sources(&deps), vec![OracleSource::Backup, OracleSource::Ema]);
+}
+
+/// Check that a 0 decimal CW20 is valued correctly, both with a negative and a zero price exponent
+#[test]
+fn priced_asset_value_of_zero_decimal_token() {
+    let mut deps = mock_dependencies(&[]);
+    let token_addr = "ticket-token-address".to_string();
+    deps.querier.with_token_balances(&[(&token_addr, &[])]);
+    deps.querier.with_token_decimals(&[(&token_addr, 0)]);
+    let querier = QuerierWrapper::new(&deps.querier);
+
+    let info = AssetInfo::Token { contract_addr: Addr::unchecked(&token_addr) };
+    let priced_asset = |oracle: OracleInterface| {
+        let mut basket_asset = create_basket_asset();
+        basket_asset.info = info.clone();
+        basket_asset.oracle = oracle;
+        basket_asset.available_reserves = Uint128::new(100);
+        PricedAsset::new(Asset { info: info.clone(), amount: Uint128::new(7) }, basket_asset)
+    };
+
+    // 2.50 USD per token
+    let mut asset = priced_asset(OracleInterface::from_dummy(250_000_000, -8));
+    assert_eq!(asset.query_value(&querier).unwrap(), Uint128::new(17_500_000));
+    assert_eq!(asset.query_contract_value(&querier).unwrap(), Uint128::new(250_000_000));
+    assert_eq!(asset.query_amount_for_value(&querier, Uint128::new(17_500_000)).unwrap(), Uint128::new(7));
+    assert_eq!(asset.query_amount_for_value(&querier, Uint128::new(17_500_001)).unwrap(), Uint128::new(8));
+
+    // 3 USD per token, with no exponent on either the price or the token
+    let mut asset = priced_asset(OracleInterface::from_dummy(3, 0));
+    assert_eq!(asset.query_value(&querier).unwrap(), Uint128::new(21_000_000));
+    assert_eq!(asset.query_contract_value(&querier).unwrap(), Uint128::new(300_000_000));
+    assert_eq!(asset.query_amount_for_value(&querier, Uint128::new(21_000_000)).unwrap(), Uint128::new(7));
+}
+
+/// Check the single asset price query against the dummy and mocked pyth oracles
+#[test]
+fn query_asset_price() {
+    let mut deps = funded_basket_setup("addr0000");
+    let luna_info = AssetInfo::NativeToken {
+        denom: "luna".to_string(),
+    };
+    let query_price = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, asset: &AssetInfo| {
+        query(deps.as_ref(), mock_env(), QueryMsg::AssetPrice { asset: asset.clone() })
+            .map(|res| from_binary::<AssetPriceResponse>(&res).unwrap())
+    };
+
+    assert_eq!(
+        query_price(&deps, &luna_info).unwrap(),
+        AssetPriceResponse { price: 100_000_000, expo: -6, conf: 0 }
+    );
+
+    match query_price(&deps, &AssetInfo::NativeToken { denom: "uust".to_string() }) {
+        Err(ContractError::AssetNotInBasket) => {}
+        x => {
+            panic!(
+                "Err(AssetNotInBasket) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+
+    let pyth_contract = String::from("pyth-contract");
+    let trading_id = PriceIdentifier::new([1; 32]);
+    let halted_id = PriceIdentifier::new([2; 32]);
+    let price_feed = |id: PriceIdentifier, status: PriceStatus| {
+        PriceFeed::new(id, status, 0, -8, 5, 5, id, 9_000_000_000, 4_000_000, 9_000_000_000, 0, 9_000_000_000, 0, 0)
+    };
+    deps.querier.with_price_feeds(&[
+        (&pyth_contract, &price_feed(trading_id, PriceStatus::Trading)),
+        (&pyth_contract, &price_feed(halted_id, PriceStatus::Halted)),
+    ]);
+    let update_oracle = |price_id: PriceIdentifier| ExecuteMsg::UpdateOracle {
+        asset: luna_info.clone(),
+        oracle: OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), price_id),
+        backup_oracle: None,
+    };
+
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), update_oracle(trading_id)).unwrap();
+    assert_eq!(
+        query_price(&deps, &luna_info).unwrap(),
+        AssetPriceResponse { price: 9_000_000_000, expo: -8, conf: 4_000_000 }
+    );
+
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), update_oracle(halted_id)).unwrap();
+    match query_price(&deps, &luna_info) {
+        Err(ContractError::PriceNotTrading) => {}
+        x => {
+            panic!(
+                "Err(PriceNotTrading) should have been returned, {:?} was returned instead",
+                x
+            );
+        }
+    }
+}
+
+/// Check that a Pyth oracle can be built from a hex price id, and that malformed ids are rejected
+#[test]
+fn oracle_from_pyth_hex() {
+    let hex = "0a3f000000000000000000000000000000000000000000000000000000000001";
+    let mut id = [0; 32];
+    id[0] = 0x0a;
+    id[1] = 0x3f;
+    id[31] = 0x01;
+    let expected = OracleInterface::from_pyth(Addr::unchecked("pyth-contract"), PriceIdentifier::new(id));
+    assert_eq!(OracleInterface::from_pyth_hex(Addr::unchecked("pyth-contract"), hex).unwrap(), expected);
+    let prefixed = format!("0x{}", hex);
+    assert_eq!(OracleInterface::from_pyth_hex(Addr::unchecked("pyth-contract"), &prefixed).unwrap(), expected);
+
+    for invalid in ["0a3f", "zz3f000000000000000000000000000000000000000000000000000000000001"] {
+        match OracleInterface::from_pyth_hex(Addr::unchecked("pyth-contract"), invalid) {
+            Err(ContractError::InvalidPriceId { hex }) => assert_eq!(hex, invalid),
+            x => {
+                panic!("Err(InvalidPriceId) should have been returned, {:?} was returned instead", x);
+            }
+        }
+    }
+}
+
+/// Check that a price older than the allowed age fails with StalePrice, a halted one with
+/// PriceNotTrading, and that stub prices never go stale
+#[test]
+fn stale_and_halted_prices() {
+    let mut deps = mock_dependencies(&[]);
+    let pyth_contract = String::from("pyth-contract");
+    let trading_id = PriceIdentifier::new([1; 32]);
+    let halted_id = PriceIdentifier::new([2; 32]);
+    // Published at t = 1000
+    let price_feed = |id: PriceIdentifier, status: PriceStatus| {
+        PriceFeed::new(id, status, 1_000, -8, 5, 5, id, 9_000_000_000, 0, 9_000_000_000, 0, 9_000_000_000, 0, 0)
+    };
+    deps.querier.with_price_feeds(&[
+        (&pyth_contract, &price_feed(trading_id, PriceStatus::Trading)),
+        (&pyth_contract, &price_feed(halted_id, PriceStatus::Halted)),
+    ]);
+    let querier = QuerierWrapper::new(&deps.querier);
+    let trading = OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), trading_id);
+    let halted = OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), halted_id);
+
+    assert_eq!(
+        trading.get_price(&querier, 1_060, Some(60)).unwrap(),
+        Price { price: 9_000_000_000, conf: 0, expo: -8 }
+    );
+    match trading.get_price(&querier, 1_061, Some(60)) {
+        Err(ContractError::StalePrice { age_seconds: 61, max_age: 60 }) => {}
+        x => {
+            panic!("Err(StalePrice) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    match trading.get_ema_price(&querier, 1_061, Some(60)) {
+        Err(ContractError::StalePrice { age_seconds: 61, max_age: 60 }) => {}
+        x => {
+            panic!("Err(StalePrice) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    // Without a max age, prices of any age are accepted
+    assert!(trading.get_price(&querier, u64::MAX, None).is_ok());
+    match halted.get_price(&querier, 1_000, Some(60)) {
+        Err(ContractError::PriceNotTrading) => {}
+        x => {
+            panic!("Err(PriceNotTrading) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    assert!(OracleInterface::from_dummy(100, 0).get_price(&querier, u64::MAX, Some(0)).is_ok());
+}
+
+/// Check that the basket rejects prices older than the `max_price_age` set by the admin
+#[test]
+fn max_price_age() {
+    use crate::state::BASKET;
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+
+    // luna's oracles publish $100, two minutes before the block
+    let pyth_contract = String::from("pyth-contract");
+    let price_id = PriceIdentifier::new([1; 32]);
+    let publish_time = mock_env().block.time.seconds() as i64 - 120;
+    let luna_feed = PriceFeed::new(
+        price_id,
+        PriceStatus::Trading,
+        publish_time,
+        -6,
+        5,
+        5,
+        price_id,
+        100_000_000,
+        0,
+        100_000_000,
+        0,
+        100_000_000,
+        0,
+        0,
+    );
+    deps.querier.with_price_feeds(&[(&pyth_contract, &luna_feed)]);
+    let mut basket = query_basket(deps.as_ref()).unwrap();
+    basket.assets[0].oracle = OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), price_id);
+    basket.assets[0].backup_oracle = basket.assets[0].oracle.clone();
+    BASKET.save(deps.as_mut().storage, &basket).unwrap();
+    assert!(query(deps.as_ref(), mock_env(), QueryMsg::Aum {}).is_ok());
+
+    let set_max_age = |max_price_age: Option<u64>| ExecuteMsg::SetMaxPriceAge { max_price_age };
+    match execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), set_max_age(Some(60))) {
+        Err(ContractError::Unauthorized) => {}
+        x => {
+            panic!("Err(Unauthorized) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    let res = execute(deps.as_mut(), mock_env(), mock_info("name", &[]), set_max_age(Some(60))).unwrap();
+    assert_eq!(res.attributes[1], attr("max_price_age", "60"));
+    match query(deps.as_ref(), mock_env(), QueryMsg::Aum {}) {
+        Err(ContractError::StalePrice { age_seconds: 120, max_age: 60 }) => {}
+        x => {
+            panic!("Err(StalePrice) should have been returned, {:?} was returned instead", x);
+        }
+    }
+    let swap = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken { denom: "uusd".to_string() },
+            amount: Uint128::new(1_000_000),
+        },
+        ask_asset: AssetInfo::NativeToken { denom: "luna".to_string() },
+        to: None,
+        max_spread: None,
+        belief_price: None,
+        deadline: None,
+    };
+    match execute(deps.as_mut(), mock_env(), mock_info(sender, &coins(1_000_000, "uusd")), swap) {
+        Err(ContractError::StalePrice { .. }) => {}
+        x => {
+            panic!("Err(StalePrice) should have been returned, {:?} was returned instead", x);
+        }
+    }
+
+    // Two minutes is within a longer max age
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), set_max_age(Some(120))).unwrap();
+    let res: AumResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Aum {}).unwrap()).unwrap();
+    assert_eq!(res.aum, Uint128::new(2_000_000_000));
+}
+
+/// Check that the AUM query carries the confidence of the asset prices, and that a wider price
+/// confidence widens the AUM confidence
+#[test]
+fn query_aum_confidence() {
+    let mut deps = funded_basket_setup("addr0000");
+    let pyth_contract = String::from("pyth-contract");
+    let price_id = PriceIdentifier::new([1; 32]);
+    let luna_feed = |conf: u64| {
+        PriceFeed::new(price_id, PriceStatus::Trading, 0, -6, 5, 5, price_id, 100_000_000, conf, 100_000_000, 0, 100_000_000, 0, 0)
+    };
+    let update = ExecuteMsg::UpdateOracle {
+        asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        oracle: OracleInterface::from_pyth(Addr::unchecked(&pyth_contract), price_id),
+        backup_oracle: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("name", &[]), update).unwrap();
+    let query_aum = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>| -> AumResponse {
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Aum {}).unwrap()).unwrap()
+    };
+
+    // 10 luna at 100 +- 1 USD, and 1000 uusd with an exact stub price
+    deps.querier.with_price_feeds(&[(&pyth_contract, &luna_feed(1_000_000))]);
+    let aum = query_aum(&deps);
+    assert_eq!(aum.aum, Uint128::new(2_000_000_000));
+    assert_eq!(aum.conf, Uint128::new(10_000_000));
+
+    // 10 luna at 100 +- 5 USD
+    deps.querier.with_price_feeds(&[(&pyth_contract, &luna_feed(5_000_000))]);
+    let aum = query_aum(&deps);
+    assert_eq!(aum.aum, Uint128::new(2_000_000_000));
+    assert_eq!(aum.conf, Uint128::new(50_000_000));
+}
+
+/// Check that reserves too large for pyth's basket pricing fail cleanly instead of panicking
+#[test]
+fn calculate_aum_reserve_overflow() {
+    let deps = mock_dependencies(&[]);
+    let querier = QuerierWrapper::new(&deps.querier);
+
+    let mut basket = create_basket();
+    basket.assets[0].available_reserves = Uint128::new(i64::MAX as u128 + 1);
+
+    match basket.calculate_aum(&querier, &mut PriceCache::default()) {
+        Err(ContractError::FailedCast) => {}
+        x => {
+            panic!("Err(FailedCast) should have been returned, {:?} was returned instead", x);
+        }
+    }
+}
+
+/// Check that deposits, swaps and withdrawals go through up to their deadline and fail after it
+#[test]
+fn deadline() {
+    let sender = "addr0000";
+    let mut deps = funded_basket_setup(sender);
+    let now = mock_env().block.time.seconds();
+    let mut expired_env = mock_env();
+    expired_env.block.time = expired_env.block.time.plus_seconds(1);
+
+    let deposit_msg = ExecuteMsg::DepositLiquidity {
+        assets: vec![Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(1_000_000),
+        }],
+        slippage_tolerance: None,
+        min_lp_out: None,
+        receiver: None,
+        auto_stake: None,
+        deadline: Some(now),
+    };
+    let swap_msg = ExecuteMsg::Swap {
+        sender: Addr::unchecked(sender),
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(1_000_000),
+        },
+        belief_price: None,
+        max_spread: None,
+        to: None,
+        ask_asset: AssetInfo::NativeToken {
+            denom: "luna".to_string(),
+        },
+        deadline: Some(now),
+    };
+    let withdraw_msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        amount: Uint128::new(1_000_000),
+        sender: sender.to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            asset: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            min_out: None,
+            deadline: Some(now),
+            receiver: None,
+        })
+        .unwrap(),
+    });
+    let funds = coins(1_000_000, "uusd");
+    let messages = [
+        (deposit_msg, mock_info(sender, &funds)),
+        (swap_msg, mock_info(sender, &funds)),
+        (withdraw_msg, mock_info(FAKE_LP_TOKEN_ADDRESS, &[])),
+    ];
+
+    for (msg, info) in messages.iter() {
+        let res = execute(deps.as_mut(), expired_env.clone(), info.clone(), msg.clone());
+        match res {
+            Err(ContractError::Expired) => {}
+            x => {
+                panic!("Err(Expired) should have been returned, {:?} was returned instead", x);
+            }
+        }
+    }
+    for (msg, info) in messages {
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
     }
 }